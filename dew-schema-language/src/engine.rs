@@ -1,15 +1,37 @@
-use std::{collections::HashMap, num::ParseFloatError};
+use std::{
+    any::Any,
+    cell::{Cell, RefCell, RefMut},
+    collections::{BTreeSet, HashMap},
+    fmt, io,
+};
 
 use crate::{
-    expression::DewSchemaLanguageExpression,
-    methods::{alias, array, cores, math, string},
+    error::{DslError, DslErrorKind},
+    expression::{
+        DewSchemaLanguageExpression, DewSchemaLanguageExpressionKind, DewSchemaLanguageParser,
+    },
+    gas::DslGasCost,
+    host::{DslNativeFunction, DslNativeMethod, DslSignature},
+    limits::DslLimits,
+    methods::{
+        array,
+        registry::{BuiltinMethod, MethodRegistry},
+    },
+    number::{self, BigDecimal},
+    trace::{self, DslTrace, DslTraceNode},
 };
 
 type Value = serde_json::Value;
 
-#[derive(Debug, PartialEq, Clone)]
+/// A value produced while evaluating an expression.
+///
+/// Scalars are always represented by their native variants; `Value` only ever holds JSON
+/// arrays and objects. Results entering the engine from JSON or host functions are brought
+/// into this form with [`DewSchemaLanguageResult::from_json`] and
+/// [`DewSchemaLanguageResult::normalize`].
+#[derive(Debug, Clone)]
 pub enum DewSchemaLanguageResult {
-    Number(f64),
+    Number(BigDecimal),
     String(String),
     Boolean(bool),
     Value(Value),
@@ -18,287 +40,2768 @@ pub enum DewSchemaLanguageResult {
     Undefined,
 }
 
+impl DewSchemaLanguageResult {
+    /// Converts JSON into its canonical result: scalars become native variants and arrays
+    /// and objects stay wrapped in `Value`.
+    pub fn from_json(value: &Value) -> Result<Self, DslError> {
+        match value {
+            Value::Null => Ok(DewSchemaLanguageResult::Null),
+            Value::Bool(b) => Ok(DewSchemaLanguageResult::Boolean(*b)),
+            Value::Number(n) => Ok(DewSchemaLanguageResult::Number(number::from_json_number(
+                n,
+            )?)),
+            Value::String(s) => Ok(DewSchemaLanguageResult::String(s.clone())),
+            Value::Array(_) | Value::Object(_) => Ok(DewSchemaLanguageResult::Value(value.clone())),
+        }
+    }
+
+    /// Converts the result back to JSON. `Undefined` and `Error` have no JSON form.
+    pub fn to_json(&self) -> Result<Value, DslError> {
+        match self {
+            DewSchemaLanguageResult::Value(value) => Ok(value.clone()),
+            DewSchemaLanguageResult::Boolean(b) => Ok(Value::Bool(*b)),
+            DewSchemaLanguageResult::Number(n) => Ok(Value::Number(number::to_json_number(n)?)),
+            DewSchemaLanguageResult::String(s) => Ok(Value::String(s.clone())),
+            DewSchemaLanguageResult::Null => Ok(Value::Null),
+            DewSchemaLanguageResult::Error(_) | DewSchemaLanguageResult::Undefined => Err(
+                DslError::type_mismatch("Cannot convert an error or undefined value to JSON"),
+            ),
+        }
+    }
+
+    /// Name of the value's type as reported by `type_of`: `number`, `string`, `boolean`,
+    /// `array`, `object`, `null`, `undefined` or `error`.
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            DewSchemaLanguageResult::Number(_) => "number",
+            DewSchemaLanguageResult::String(_) => "string",
+            DewSchemaLanguageResult::Boolean(_) => "boolean",
+            DewSchemaLanguageResult::Value(Value::Array(_)) => "array",
+            DewSchemaLanguageResult::Value(Value::Object(_)) => "object",
+            // scalars wrapped in `Value` report the type of their canonical form
+            DewSchemaLanguageResult::Value(value) => match value {
+                Value::Null => "null",
+                Value::Bool(_) => "boolean",
+                Value::Number(_) => "number",
+                _ => "string",
+            },
+            DewSchemaLanguageResult::Error(_) => "error",
+            DewSchemaLanguageResult::Null => "null",
+            DewSchemaLanguageResult::Undefined => "undefined",
+        }
+    }
+
+    /// Brings a result built outside the engine, e.g. `Value(Value::Null)` returned by a
+    /// host function, into canonical form.
+    pub fn normalize(self) -> Result<Self, DslError> {
+        match self {
+            DewSchemaLanguageResult::Value(ref value)
+                if !value.is_array() && !value.is_object() =>
+            {
+                Self::from_json(value)
+            }
+            result => Ok(result),
+        }
+    }
+}
+
+/// Renders the value as it would be written in an expression: JSON, with numbers in plain
+/// decimal notation, or `undefined`. Used in error messages, e.g. `2 is not equal to 3`.
+impl fmt::Display for DewSchemaLanguageResult {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DewSchemaLanguageResult::Number(n) => f.write_str(&n.to_plain_string()),
+            DewSchemaLanguageResult::String(s) => {
+                serde_json::to_writer(FormatterWriter(f), s).map_err(|_| fmt::Error)
+            }
+            DewSchemaLanguageResult::Boolean(b) => write!(f, "{}", b),
+            DewSchemaLanguageResult::Value(value) => write!(f, "{}", value),
+            DewSchemaLanguageResult::Error(message) => write!(f, "error: {}", message),
+            DewSchemaLanguageResult::Null => f.write_str("null"),
+            DewSchemaLanguageResult::Undefined => f.write_str("undefined"),
+        }
+    }
+}
+
+/// Whether the first step of `expression` is a method call without a callee, e.g. `gte(0)` in
+/// `gte(0) && value < 10`. Parentheses are a call too, but without a method name.
+fn starts_with_bare_call(expression: &DewSchemaLanguageExpression) -> bool {
+    match &expression.kind {
+        DewSchemaLanguageExpressionKind::Call { method_name, .. } => !method_name.is_empty(),
+        DewSchemaLanguageExpressionKind::Chain(chain) => starts_with_bare_call(&chain[0]),
+        _ => false,
+    }
+}
+
+/// Lets serde_json write straight into a formatter, so a string is not copied to be quoted.
+struct FormatterWriter<'a, 'b>(&'a mut fmt::Formatter<'b>);
+
+impl io::Write for FormatterWriter<'_, '_> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        // serde_json only splits its output between characters
+        let s = std::str::from_utf8(buf).map_err(io::Error::other)?;
+        self.0.write_str(s).map_err(io::Error::other)?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Equality is structural and never fails: numbers compare by value (`1.0 == 1`), including
+/// numbers nested in arrays and objects, and values of different types are never equal.
+/// Scalars wrapped in `Value` compare like their canonical form.
+impl PartialEq for DewSchemaLanguageResult {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (DewSchemaLanguageResult::Number(a), DewSchemaLanguageResult::Number(b)) => a == b,
+            (DewSchemaLanguageResult::String(a), DewSchemaLanguageResult::String(b)) => a == b,
+            (DewSchemaLanguageResult::Boolean(a), DewSchemaLanguageResult::Boolean(b)) => a == b,
+            (DewSchemaLanguageResult::Error(a), DewSchemaLanguageResult::Error(b)) => a == b,
+            (DewSchemaLanguageResult::Null, DewSchemaLanguageResult::Null) => true,
+            (DewSchemaLanguageResult::Undefined, DewSchemaLanguageResult::Undefined) => true,
+            (DewSchemaLanguageResult::Value(a), DewSchemaLanguageResult::Value(b)) => {
+                json_equal(a, b)
+            }
+            (DewSchemaLanguageResult::Value(value), scalar)
+            | (scalar, DewSchemaLanguageResult::Value(value)) => {
+                match DewSchemaLanguageResult::from_json(value) {
+                    Ok(DewSchemaLanguageResult::Value(_)) | Err(_) => false,
+                    Ok(canonical) => canonical == *scalar,
+                }
+            }
+            _ => false,
+        }
+    }
+}
+
+fn json_equal(a: &Value, b: &Value) -> bool {
+    match (a, b) {
+        (Value::Number(a), Value::Number(b)) => {
+            match (number::from_json_number(a), number::from_json_number(b)) {
+                (Ok(a), Ok(b)) => a == b,
+                _ => a == b,
+            }
+        }
+        (Value::Array(a), Value::Array(b)) => {
+            a.len() == b.len() && a.iter().zip(b).all(|(a, b)| json_equal(a, b))
+        }
+        (Value::Object(a), Value::Object(b)) => {
+            a.len() == b.len()
+                && a.iter()
+                    .all(|(key, a)| b.get(key).is_some_and(|b| json_equal(a, b)))
+        }
+        _ => a == b,
+    }
+}
+
 pub type DslFunctionParams = Vec<DewSchemaLanguageResult>;
 pub type DslFunctionCallee<'host_function_lifetime> =
     Option<&'host_function_lifetime DewSchemaLanguageResult>;
 
 pub type DslFunction =
-    Box<dyn Fn(DslFunctionParams, DslFunctionCallee) -> Result<DewSchemaLanguageResult, String>>;
+    Box<dyn Fn(DslFunctionParams, DslFunctionCallee) -> Result<DewSchemaLanguageResult, DslError>>;
 
-pub struct DewSchemaLanguageEngine {
-    root_object: Value,
-    host_functions: HashMap<String, DslFunction>,
+/// Lexical environment of an expression: the variables bound by enclosing `let` expressions
+/// and iteration methods. Each scope adds one binding on top of its parent, so inner
+/// bindings shadow outer ones and disappear when their expression has been evaluated.
+#[derive(Clone, Copy)]
+struct DslScope<'scope_lifetime> {
+    name: &'scope_lifetime str,
+    value: &'scope_lifetime DewSchemaLanguageResult,
+    parent: Option<&'scope_lifetime DslScope<'scope_lifetime>>,
 }
 
-impl DewSchemaLanguageEngine {
-    pub fn new(
-        root_object_json: String,
-        host_functions: HashMap<
-            String,
-            Box<
-                dyn Fn(
-                    DslFunctionParams,
-                    DslFunctionCallee,
-                ) -> Result<DewSchemaLanguageResult, String>,
-            >,
-        >,
-    ) -> Self {
-        let root_object: Value = serde_json::from_str(&root_object_json).unwrap();
+impl DslScope<'_> {
+    /// Finds the innermost binding of `name` in `scope`.
+    fn lookup<'lookup_lifetime>(
+        scope: Option<&'lookup_lifetime DslScope<'lookup_lifetime>>,
+        name: &str,
+    ) -> Option<&'lookup_lifetime DewSchemaLanguageResult> {
+        let mut current = scope;
 
-        Self {
-            root_object,
-            host_functions,
+        while let Some(scope) = current {
+            if scope.name == name {
+                return Some(scope.value);
+            }
+            current = scope.parent;
         }
+
+        None
     }
+}
 
-    pub fn evaluate(&self, expression_str: String) -> Result<DewSchemaLanguageResult, String> {
-        let expression = crate::expression::DewSchemaLanguageParser::consume(&expression_str)?;
+/// Unevaluated arguments of a method call. Each argument is only evaluated when the
+/// method asks for it, which lets `and`/`or` short-circuit and iteration methods bind each
+/// element in turn.
+pub struct DslLazyParams<'lazy_params_lifetime> {
+    evaluator: &'lazy_params_lifetime DslEvaluator<'lazy_params_lifetime>,
+    args: &'lazy_params_lifetime [DewSchemaLanguageExpression],
+    scope: Option<&'lazy_params_lifetime DslScope<'lazy_params_lifetime>>,
+}
 
-        self.evaluate_atom(&expression, None, None)
+impl DslLazyParams<'_> {
+    pub fn len(&self) -> usize {
+        self.args.len()
     }
 
-    fn evaluate_atom(
+    pub fn is_empty(&self) -> bool {
+        self.args.is_empty()
+    }
+
+    /// Evaluates the argument at `index` in the scope of the call site.
+    pub fn evaluate(&self, index: usize) -> Result<DewSchemaLanguageResult, DslError> {
+        let arg = self
+            .args
+            .get(index)
+            .ok_or_else(|| DslError::arity(format!("Missing argument at index {}", index)))?;
+
+        self.evaluator
+            .evaluate_atom(arg, None, self.scope)
+            .map_err(|e| e.or_path(|| DewSchemaLanguageExpression::path(std::slice::from_ref(arg))))
+    }
+
+    /// Evaluates the argument at `index` for one element of an iteration. A lambda argument
+    /// `element => body` or `(element, position) => body` binds its own parameter names;
+    /// any other argument sees the element as `item`. The element's position is bound as
+    /// `index` unless the lambda names it.
+    pub fn evaluate_with_element(
         &self,
-        expression: &DewSchemaLanguageExpression,
-        callee: Option<&DewSchemaLanguageResult>,
-        iterable_item: Option<&DewSchemaLanguageResult>,
-    ) -> Result<DewSchemaLanguageResult, String> {
-        let result = match expression {
-            DewSchemaLanguageExpression::Number(num_str) => {
-                let num: f64 = num_str
-                    .parse()
-                    .map_err(|e: ParseFloatError| e.to_string())?;
-                DewSchemaLanguageResult::Number(num)
-            }
-            DewSchemaLanguageExpression::StringLiteral(s) => {
-                DewSchemaLanguageResult::String(s.clone())
-            }
-            DewSchemaLanguageExpression::Identifier(identifier) => {
-                if callee.is_none() && identifier == "$" {
-                    let object = self.root_object.clone();
-
-                    match object {
-                        Value::Null => DewSchemaLanguageResult::Null,
-                        Value::Bool(b) => DewSchemaLanguageResult::Boolean(b),
-                        Value::Number(n) => DewSchemaLanguageResult::Number(n.as_f64().unwrap()),
-                        Value::String(s) => DewSchemaLanguageResult::String(s),
-                        _ => DewSchemaLanguageResult::Value(object),
-                    }
-                } else if callee.is_none() && identifier == "item" {
-                    iterable_item.unwrap().clone()
-                } else if callee.is_none() && identifier == "true" {
-                    DewSchemaLanguageResult::Boolean(true)
-                } else if callee.is_none() && identifier == "false" {
-                    DewSchemaLanguageResult::Boolean(false)
-                } else if callee.is_none() {
-                    return Err(format!("Unknown identifier: {}", identifier));
-                } else {
-                    match callee.unwrap() {
-                        DewSchemaLanguageResult::Value(Value::Object(map)) => {
-                            if let Some(value) = map.get(identifier) {
-                                match value {
-                                    Value::Null => DewSchemaLanguageResult::Null,
-                                    Value::Bool(b) => DewSchemaLanguageResult::Boolean(*b),
-                                    Value::Number(n) => {
-                                        DewSchemaLanguageResult::Number(n.as_f64().unwrap())
-                                    }
-                                    Value::String(s) => DewSchemaLanguageResult::String(s.clone()),
-                                    _ => DewSchemaLanguageResult::Value(value.clone()),
-                                }
-                            } else {
-                                DewSchemaLanguageResult::Undefined
-                            }
-                        }
-                        _ => {
-                            return Err(format!(
-                                "Cannot access property '{}' on non-object",
-                                identifier
-                            ));
-                        }
-                    }
-                }
-            }
-            DewSchemaLanguageExpression::Call { method_name, args } => {
-                let evaluated_args: Result<Vec<DewSchemaLanguageResult>, String> = args
-                    .iter()
-                    .map(|arg| self.evaluate_atom(arg, None, iterable_item))
-                    .collect();
+        index: usize,
+        element: &DewSchemaLanguageResult,
+        position: usize,
+    ) -> Result<DewSchemaLanguageResult, DslError> {
+        let arg = self
+            .args
+            .get(index)
+            .ok_or_else(|| DslError::arity(format!("Missing argument at index {}", index)))?;
 
-                let evaluated_args = evaluated_args?;
+        let (element_name, position_name, body) = match &arg.kind {
+            DewSchemaLanguageExpressionKind::Lambda { parameters, body } => (
+                parameters[0].as_str(),
+                parameters.get(1).map_or("index", |name| name.as_str()),
+                body.as_ref(),
+            ),
+            _ => ("item", "index", arg),
+        };
 
-                let aliased_functions = alias::functions();
-                let cores_functions = cores::functions();
-                let math_functions = math::functions();
-                let array_functions = array::functions();
-                let string_functions = string::functions();
+        let position = DewSchemaLanguageResult::Number(BigDecimal::from(position as u64));
+        let position_scope = DslScope {
+            name: position_name,
+            value: &position,
+            parent: self.scope,
+        };
+        let element_scope = DslScope {
+            name: element_name,
+            value: element,
+            parent: Some(&position_scope),
+        };
 
-                match method_name {
-                    method_name if self.host_functions.contains_key(method_name) => {
-                        let func = self.host_functions.get(method_name).unwrap();
+        self.evaluator
+            .evaluate_atom(body, None, Some(&element_scope))
+            .map_err(|e| e.or_path(|| DewSchemaLanguageExpression::path(std::slice::from_ref(arg))))
+    }
 
-                        func(evaluated_args, callee)?
-                    }
-                    method_name if aliased_functions.contains_key(method_name) => {
-                        let func = aliased_functions.get(method_name).unwrap();
+    /// Evaluates every argument in order, as eager methods expect.
+    pub fn evaluate_all(&self) -> Result<DslFunctionParams, DslError> {
+        (0..self.len()).map(|index| self.evaluate(index)).collect()
+    }
+}
 
-                        func(evaluated_args, callee)?
-                    }
-                    method_name if cores_functions.contains_key(method_name) => {
-                        let func = cores_functions.get(method_name).unwrap();
+pub type DslLazyFunction =
+    Box<dyn Fn(DslLazyParams, DslFunctionCallee) -> Result<DewSchemaLanguageResult, DslError>>;
 
-                        func(evaluated_args, callee)?
-                    }
-                    method_name if math_functions.contains_key(method_name) => {
-                        let func = math_functions.get(method_name).unwrap();
+/// What a context host function sees of the evaluation calling it: the root object, the
+/// variables bound at the call site, the data passed to
+/// [`DewSchemaLanguageEngine::evaluate_with_data`], and the built-in methods.
+pub struct DslHostContext<'host_context_lifetime> {
+    evaluator: &'host_context_lifetime DslEvaluator<'host_context_lifetime>,
+    scope: Option<&'host_context_lifetime DslScope<'host_context_lifetime>>,
+    data: Option<RefMut<'host_context_lifetime, dyn Any>>,
+}
 
-                        func(evaluated_args, callee)?
-                    }
-                    method_name if array_functions.contains_key(method_name) => {
-                        let func = array_functions.get(method_name).unwrap();
+impl DslHostContext<'_> {
+    /// The root object the expression is evaluated against, `$`.
+    pub fn root(&self) -> &Value {
+        self.evaluator.root_object
+    }
 
-                        func(evaluated_args, callee)?
-                    }
-                    method_name if string_functions.contains_key(method_name) => {
-                        let func = string_functions.get(method_name).unwrap();
+    /// The value of the variable `name` at the call site, bound by an enclosing `let`,
+    /// lambda or iteration method.
+    pub fn binding(&self, name: &str) -> Option<&DewSchemaLanguageResult> {
+        DslScope::lookup(self.scope, name)
+    }
 
-                        func(evaluated_args, callee)?
-                    }
-                    _ => {
-                        return Err(format!("Unknown method: {}", method_name));
-                    }
-                }
+    /// Every variable visible at the call site, innermost first. Shadowed bindings are
+    /// left out.
+    pub fn bindings(&self) -> Vec<(&str, &DewSchemaLanguageResult)> {
+        let mut bindings: Vec<(&str, &DewSchemaLanguageResult)> = Vec::new();
+        let mut current = self.scope;
+
+        while let Some(scope) = current {
+            if bindings.iter().all(|(name, _)| *name != scope.name) {
+                bindings.push((scope.name, scope.value));
             }
-            DewSchemaLanguageExpression::Chain(chains) => {
-                let first_expression = &chains[0];
-                let first_expression_result =
-                    self.evaluate_atom(first_expression, callee, iterable_item)?;
+            current = scope.parent;
+        }
 
-                if chains.len() == 1 {
-                    first_expression_result
-                } else {
-                    let remaining_chains = DewSchemaLanguageExpression::Chain(chains[1..].to_vec());
+        bindings
+    }
 
-                    self.evaluate_atom(
-                        &remaining_chains,
-                        Some(&first_expression_result),
-                        iterable_item,
-                    )?
-                }
+    /// The data the evaluation was started with, if it is a `T`.
+    pub fn data<T: Any>(&mut self) -> Option<&mut T> {
+        self.data.as_mut()?.downcast_mut()
+    }
+
+    /// Calls a built-in method with already evaluated arguments, charging its gas like a
+    /// call from the expression would. Methods that evaluate their arguments themselves,
+    /// such as `every` or `and`, cannot be called this way and give an `Evaluation` error.
+    pub fn call_method(
+        &self,
+        method_name: &str,
+        callee: DslFunctionCallee,
+        args: DslFunctionParams,
+    ) -> Result<DewSchemaLanguageResult, DslError> {
+        let registry = MethodRegistry::global();
+
+        match registry.get(method_name) {
+            Some(BuiltinMethod::Eager(func)) => {
+                registry.reject_undefined(method_name, callee, &args)?;
+                self.evaluator.charge_gas(
+                    registry
+                        .gas_cost(method_name)
+                        .expect("every built-in method declares a gas cost"),
+                    callee,
+                    &args,
+                )?;
+                func(args, callee)
             }
-        };
+            Some(BuiltinMethod::Lazy(_)) => Err(DslError::evaluation(format!(
+                "'{}' method evaluates its own arguments and cannot be called from a host function",
+                method_name
+            ))),
+            None => Err(DslError::unknown_method(method_name)),
+        }
+    }
+}
 
-        Ok(result)
+/// A host function that also receives a [`DslHostContext`].
+pub type DslContextFunction = Box<
+    dyn Fn(
+        DslFunctionParams,
+        DslFunctionCallee,
+        &mut DslHostContext,
+    ) -> Result<DewSchemaLanguageResult, DslError>,
+>;
+
+/// Data passed to [`DewSchemaLanguageEngine::evaluate_with_data`], lent to one context host
+/// function at a time.
+trait DslHostData {
+    fn borrow_data(&self) -> Option<RefMut<'_, dyn Any>>;
+}
+
+impl<T: Any> DslHostData for RefCell<&mut T> {
+    fn borrow_data(&self) -> Option<RefMut<'_, dyn Any>> {
+        let data = self.try_borrow_mut().ok()?;
+
+        Some(RefMut::map(data, |data| &mut **data as &mut dyn Any))
     }
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use serde_json::json;
+/// A parsed expression whose method names have been checked against the built-in methods
+/// and the host functions of the engine that compiled it. It can be evaluated any number of
+/// times, against any root object, without tokenizing or parsing again.
+#[derive(Debug, Clone)]
+pub struct CompiledExpression {
+    source: String,
+    expression: DewSchemaLanguageExpression,
+    host_function_names: BTreeSet<String>,
+}
 
-    #[test]
-    fn test_evaluate_number() {
-        let engine = DewSchemaLanguageEngine::new("{}".into(), HashMap::new());
-        let result = engine.evaluate("42".into()).unwrap();
-        match result {
-            DewSchemaLanguageResult::Number(n) => assert_eq!(n, 42.0),
-            _ => panic!("Expected number"),
-        }
+impl CompiledExpression {
+    pub fn source(&self) -> &str {
+        &self.source
     }
 
-    #[test]
-    fn test_evaluate_string() {
-        let engine = DewSchemaLanguageEngine::new("{}".into(), HashMap::new());
-        let result = engine.evaluate(r#""hello""#.into()).unwrap();
-        match result {
-            DewSchemaLanguageResult::String(s) => assert_eq!(s, "hello"),
-            _ => panic!("Expected string"),
-        }
+    pub fn expression(&self) -> &DewSchemaLanguageExpression {
+        &self.expression
     }
 
-    #[test]
-    fn test_evaluate_identifier() {
-        let engine =
-            DewSchemaLanguageEngine::new(r#"{"foo": {"bar": 123}}"#.into(), HashMap::new());
-        let result = engine.evaluate("$.foo.bar".into()).unwrap();
-        match result {
-            DewSchemaLanguageResult::Number(n) => assert_eq!(n, 123.0),
-            _ => panic!("Expected number"),
+    /// Host functions the expression calls; an engine must provide all of them to evaluate it.
+    pub fn host_function_names(&self) -> &BTreeSet<String> {
+        &self.host_function_names
+    }
+}
+
+/// Outcome of [`DewSchemaLanguageEngine::evaluate_collecting`]: the value or the error
+/// that stopped evaluation, and every assertion that failed on the way.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DslCollectedEvaluation {
+    pub result: Result<DewSchemaLanguageResult, DslError>,
+    /// Failed assertions in the order they were evaluated, each with its span and path.
+    pub violations: Vec<DslError>,
+}
+
+impl DslCollectedEvaluation {
+    /// Whether evaluation finished without an error and every assertion held.
+    pub fn is_ok(&self) -> bool {
+        self.result.is_ok() && self.violations.is_empty()
+    }
+}
+
+/// Outcome of [`DewSchemaLanguageEngine::evaluate_metered`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct DslMeteredEvaluation {
+    pub result: Result<DewSchemaLanguageResult, DslError>,
+    /// Gas charged by every method and host function called, up to the budget.
+    pub gas_used: u64,
+}
+
+fn parse_root_object(root_object_json: &str) -> Result<Value, DslError> {
+    serde_json::from_str(root_object_json)
+        .map_err(|e| DslError::invalid_root(format!("Invalid root object JSON: {}", e)))
+}
+
+pub struct DewSchemaLanguageEngine {
+    root_object: Value,
+    host_functions: HashMap<String, DslFunction>,
+    lazy_host_functions: HashMap<String, DslLazyFunction>,
+    context_host_functions: HashMap<String, DslContextFunction>,
+    host_function_gas_costs: HashMap<String, DslGasCost>,
+    host_function_signatures: HashMap<String, DslSignature>,
+    limits: DslLimits,
+}
+
+impl DewSchemaLanguageEngine {
+    /// Creates an engine from the root object as a JSON string.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `root_object_json` is not valid JSON; use [`Self::try_new`] for untrusted input.
+    pub fn new(root_object_json: String, host_functions: HashMap<String, DslFunction>) -> Self {
+        Self::try_new(&root_object_json, host_functions).unwrap()
+    }
+
+    /// Like [`Self::new`], but returns a [`DslErrorKind::InvalidRoot`] error for malformed JSON.
+    pub fn try_new(
+        root_object_json: &str,
+        host_functions: HashMap<String, DslFunction>,
+    ) -> Result<Self, DslError> {
+        Ok(Self::from_value(
+            parse_root_object(root_object_json)?,
+            host_functions,
+        ))
+    }
+
+    /// Creates an engine from an already parsed root object.
+    pub fn from_value(root_object: Value, host_functions: HashMap<String, DslFunction>) -> Self {
+        Self {
+            root_object,
+            host_functions,
+            lazy_host_functions: HashMap::new(),
+            context_host_functions: HashMap::new(),
+            host_function_gas_costs: HashMap::new(),
+            host_function_signatures: HashMap::new(),
+            limits: DslLimits::default(),
         }
     }
 
-    #[test]
-    fn test_evaluate_call() {
-        let input = json!({
-            "foo": {
-                "bar": 100,
-                "baz": [1, 2, 3, 4, 5]
-            }
-        });
+    /// Like [`Self::from_value`], cloning the borrowed root object.
+    pub fn from_value_ref(
+        root_object: &Value,
+        host_functions: HashMap<String, DslFunction>,
+    ) -> Self {
+        Self::from_value(root_object.clone(), host_functions)
+    }
 
-        let engine = DewSchemaLanguageEngine::new(input.to_string(), HashMap::new());
+    /// Replaces the root object, keeping every registered host function.
+    pub fn set_root(&mut self, root_object: Value) {
+        self.root_object = root_object;
+    }
 
-        let first_evaluate = engine
-            .evaluate("$.foo.bar.percent(10).equal(10)".to_string())
-            .unwrap();
+    /// Like [`Self::set_root`], parsing the root object from a JSON string.
+    pub fn set_root_json(&mut self, root_object_json: &str) -> Result<(), DslError> {
+        self.set_root(parse_root_object(root_object_json)?);
+        Ok(())
+    }
 
-        let first_expected = DewSchemaLanguageResult::Boolean(true);
+    /// Builder form of [`Self::set_root`].
+    pub fn with_root(mut self, root_object: Value) -> Self {
+        self.set_root(root_object);
+        self
+    }
 
-        assert_eq!(first_evaluate, first_expected);
+    pub fn root(&self) -> &Value {
+        &self.root_object
+    }
 
-        let second_evaluate = engine
-            .evaluate("$.foo.baz.length().equal(5)".to_string())
-            .unwrap();
+    /// Registers host functions that receive their arguments unevaluated.
+    pub fn with_lazy_host_functions(
+        mut self,
+        lazy_host_functions: HashMap<String, DslLazyFunction>,
+    ) -> Self {
+        self.lazy_host_functions.extend(lazy_host_functions);
+        self
+    }
 
-        let second_expected = DewSchemaLanguageResult::Boolean(true);
-        assert_eq!(second_evaluate, second_expected);
+    /// Registers host functions that also receive a [`DslHostContext`].
+    pub fn with_context_host_functions(
+        mut self,
+        context_host_functions: HashMap<String, DslContextFunction>,
+    ) -> Self {
+        self.context_host_functions.extend(context_host_functions);
+        self
     }
 
-    #[test]
-    fn test_evaluate_host_function() {
-        let mut host_functions: HashMap<
-            String,
-            Box<
-                dyn Fn(
-                    DslFunctionParams,
-                    DslFunctionCallee,
-                ) -> Result<DewSchemaLanguageResult, String>,
-            >,
-        > = HashMap::new();
+    /// Registers a host function with a declared signature. The engine checks the number of
+    /// arguments when compiling, the callee and argument types before each call and the
+    /// returned type after it, so `function` can rely on them.
+    pub fn with_typed_host_function(
+        mut self,
+        method_name: impl Into<String>,
+        signature: DslSignature,
+        function: DslFunction,
+    ) -> Self {
+        let method_name = method_name.into();
 
-        host_functions.insert(
-            "vault_id".into(),
-            Box::new(|_, callee| {
-                if callee.is_some() {
-                    return Err("vault_id should not be called with a callee".into());
-                }
-                Ok(DewSchemaLanguageResult::String("dewvault.near".into()))
-            }),
-        );
+        self.host_functions.insert(method_name.clone(), function);
+        self.host_function_signatures.insert(method_name, signature);
+        self
+    }
 
-        let input = json!({
-            "account_id": "dewvault.near",
-            "receiver_id": "some_other_account.near"
-        });
+    /// Registers a closure over native types, e.g. `|account_id: String, limit: f64| ...`,
+    /// as a typed host function called on its own, like `is_allowed($.receiver_id, 10)`.
+    pub fn with_native_host_function<Args>(
+        self,
+        method_name: impl Into<String>,
+        function: impl DslNativeFunction<Args>,
+    ) -> Self {
+        let signature = function.signature();
 
-        let engine = DewSchemaLanguageEngine::new(input.to_string(), host_functions);
+        self.with_typed_host_function(method_name, signature, function.into_host_function())
+    }
 
-        let first_evaluate = engine
-            .evaluate("$.account_id.equal(vault_id())".to_string())
-            .unwrap();
-        let first_expected = DewSchemaLanguageResult::Boolean(true);
-        assert_eq!(first_evaluate, first_expected);
+    /// Like [`Self::with_native_host_function`], for a method called on a value, like
+    /// `$.receiver_id.is_allowed(10)`. The closure receives the callee as first argument.
+    pub fn with_native_host_method<Args>(
+        self,
+        method_name: impl Into<String>,
+        function: impl DslNativeMethod<Args>,
+    ) -> Self {
+        let signature = function.signature();
 
-        let second_evaluate = engine
-            .evaluate("$.receiver_id.equal(vault_id())".to_string())
-            .unwrap();
-        let second_expected = DewSchemaLanguageResult::Boolean(false);
-        assert_eq!(second_evaluate, second_expected);
+        self.with_typed_host_function(method_name, signature, function.into_host_function())
+    }
 
-        let third_evaluate = engine
-            .evaluate("vault_id().equal($.account_id)".to_string())
-            .unwrap();
-        let third_expected = DewSchemaLanguageResult::Boolean(true);
-        assert_eq!(third_evaluate, third_expected);
+    /// The declared signature of a typed host function.
+    pub fn host_function_signature(&self, method_name: &str) -> Option<&DslSignature> {
+        self.host_function_signatures.get(method_name)
+    }
+
+    /// Declares the gas charged by host functions. Host functions without a declared cost
+    /// are charged [`DslGasCost::DEFAULT_HOST_FUNCTION`].
+    pub fn with_host_function_gas_costs(
+        mut self,
+        host_function_gas_costs: HashMap<String, DslGasCost>,
+    ) -> Self {
+        self.host_function_gas_costs.extend(host_function_gas_costs);
+        self
+    }
+
+    fn host_function_gas_cost(&self, method_name: &str) -> DslGasCost {
+        self.host_function_gas_costs
+            .get(method_name)
+            .copied()
+            .unwrap_or(DslGasCost::DEFAULT_HOST_FUNCTION)
+    }
+
+    /// Replaces the [`DslLimits::default`] limits on expressions this engine compiles and
+    /// evaluates.
+    pub fn with_limits(mut self, limits: DslLimits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    pub fn limits(&self) -> &DslLimits {
+        &self.limits
+    }
+
+    pub fn evaluate(&self, expression_str: String) -> Result<DewSchemaLanguageResult, DslError> {
+        let compiled = self.compile(&expression_str)?;
+
+        self.evaluate_compiled(&compiled)
+    }
+
+    /// Parses the expression and resolves every method it calls, so it can be evaluated
+    /// repeatedly with [`Self::evaluate_compiled`] or [`Self::evaluate_compiled_with_root`].
+    pub fn compile(&self, expression_str: &str) -> Result<CompiledExpression, DslError> {
+        if expression_str.len() > self.limits.max_expression_length {
+            return Err(DslError::expression_too_long(
+                expression_str.len(),
+                self.limits.max_expression_length,
+            ));
+        }
+
+        let expression =
+            DewSchemaLanguageParser::consume_with_limits(expression_str, &self.limits)?;
+        let mut host_function_names = BTreeSet::new();
+
+        self.resolve_methods(&expression, &mut host_function_names)?;
+
+        Ok(CompiledExpression {
+            source: expression_str.to_string(),
+            expression,
+            host_function_names,
+        })
+    }
+
+    pub fn evaluate_compiled(
+        &self,
+        compiled: &CompiledExpression,
+    ) -> Result<DewSchemaLanguageResult, DslError> {
+        self.evaluate_compiled_with_root(compiled, &self.root_object)
+    }
+
+    /// Evaluates a compiled expression against `root_object` instead of the engine's root.
+    pub fn evaluate_compiled_with_root(
+        &self,
+        compiled: &CompiledExpression,
+        root_object: &Value,
+    ) -> Result<DewSchemaLanguageResult, DslError> {
+        self.check_host_functions(compiled)?;
+
+        let evaluator = DslEvaluator::new(self, root_object);
+
+        evaluator.evaluate_atom(&compiled.expression, None, None)
+    }
+
+    /// Like [`Self::evaluate`], lending `data` to every context host function called, e.g.
+    /// a database handle for this request. Host functions get it from
+    /// [`DslHostContext::data`].
+    pub fn evaluate_with_data<T: Any>(
+        &self,
+        expression_str: String,
+        data: &mut T,
+    ) -> Result<DewSchemaLanguageResult, DslError> {
+        let compiled = self.compile(&expression_str)?;
+
+        self.evaluate_compiled_with_data(&compiled, data)
+    }
+
+    /// Like [`Self::evaluate_with_data`], for an already compiled expression.
+    pub fn evaluate_compiled_with_data<T: Any>(
+        &self,
+        compiled: &CompiledExpression,
+        data: &mut T,
+    ) -> Result<DewSchemaLanguageResult, DslError> {
+        self.check_host_functions(compiled)?;
+
+        let data = RefCell::new(data);
+        let evaluator = DslEvaluator::new(self, &self.root_object).with_host_data(&data);
+
+        evaluator.evaluate_atom(&compiled.expression, None, None)
+    }
+
+    /// Like [`Self::evaluate`], but a failed `assert_*` call does not stop evaluation: it is
+    /// recorded as a violation and evaluates to `true`, so every assertion that can be
+    /// reached is checked. Other errors still stop evaluation.
+    pub fn evaluate_collecting(&self, expression_str: String) -> DslCollectedEvaluation {
+        match self.compile(&expression_str) {
+            Ok(compiled) => self.evaluate_compiled_collecting(&compiled),
+            Err(e) => DslCollectedEvaluation {
+                result: Err(e),
+                violations: Vec::new(),
+            },
+        }
+    }
+
+    /// Like [`Self::evaluate_collecting`], for an already compiled expression.
+    pub fn evaluate_compiled_collecting(
+        &self,
+        compiled: &CompiledExpression,
+    ) -> DslCollectedEvaluation {
+        if let Err(e) = self.check_host_functions(compiled) {
+            return DslCollectedEvaluation {
+                result: Err(e),
+                violations: Vec::new(),
+            };
+        }
+
+        let evaluator = DslEvaluator::new(self, &self.root_object).collecting_violations();
+        let result = evaluator.evaluate_atom(&compiled.expression, None, None);
+
+        DslCollectedEvaluation {
+            result,
+            violations: evaluator
+                .violations
+                .map(RefCell::into_inner)
+                .unwrap_or_default(),
+        }
+    }
+
+    /// Like [`Self::evaluate`], also reporting the gas used, including when evaluation
+    /// failed or ran out of gas.
+    pub fn evaluate_metered(&self, expression_str: String) -> DslMeteredEvaluation {
+        match self.compile(&expression_str) {
+            Ok(compiled) => self.evaluate_compiled_metered(&compiled),
+            Err(e) => DslMeteredEvaluation {
+                result: Err(e),
+                gas_used: 0,
+            },
+        }
+    }
+
+    /// Like [`Self::evaluate_metered`], for an already compiled expression.
+    pub fn evaluate_compiled_metered(&self, compiled: &CompiledExpression) -> DslMeteredEvaluation {
+        if let Err(e) = self.check_host_functions(compiled) {
+            return DslMeteredEvaluation {
+                result: Err(e),
+                gas_used: 0,
+            };
+        }
+
+        let evaluator = DslEvaluator::new(self, &self.root_object);
+        let result = evaluator.evaluate_atom(&compiled.expression, None, None);
+
+        DslMeteredEvaluation {
+            result,
+            gas_used: evaluator.gas_used.get(),
+        }
+    }
+
+    /// Like [`Self::evaluate`], but records every node evaluated along the way with its
+    /// callee, arguments and result. Only errors that prevent evaluation from starting are
+    /// returned as `Err`; an evaluation error is the result of the trace's root.
+    pub fn evaluate_traced(&self, expression_str: String) -> Result<DslTrace, DslError> {
+        let compiled = self.compile(&expression_str)?;
+
+        self.evaluate_compiled_traced(&compiled)
+    }
+
+    /// Like [`Self::evaluate_traced`], for an already compiled expression.
+    pub fn evaluate_compiled_traced(
+        &self,
+        compiled: &CompiledExpression,
+    ) -> Result<DslTrace, DslError> {
+        self.check_host_functions(compiled)?;
+
+        let evaluator = DslEvaluator::new(self, &self.root_object).tracing(&compiled.source);
+        let result = evaluator.evaluate_atom(&compiled.expression, None, None);
+
+        Ok(evaluator
+            .tracer
+            .and_then(|tracer| tracer.into_trace(result))
+            .expect("the root node is always traced"))
+    }
+
+    /// Evaluates a compiled schema rule against `root_object`, with the checked field bound
+    /// as `value`. A rule that starts with a bare method call takes the field as the callee
+    /// of that call, so `gte(0)` checks `value.gte(0)`.
+    pub(crate) fn evaluate_compiled_with_value(
+        &self,
+        compiled: &CompiledExpression,
+        root_object: &Value,
+        value: &DewSchemaLanguageResult,
+    ) -> Result<DewSchemaLanguageResult, DslError> {
+        self.check_host_functions(compiled)?;
+
+        let evaluator = DslEvaluator::new(self, root_object);
+        let scope = DslScope {
+            name: "value",
+            value,
+            parent: None,
+        };
+
+        let callee = starts_with_bare_call(&compiled.expression).then_some(value);
+
+        evaluator.evaluate_atom(&compiled.expression, callee, Some(&scope))
+    }
+
+    /// Makes sure this engine provides every host function `compiled` calls.
+    fn check_host_functions(&self, compiled: &CompiledExpression) -> Result<(), DslError> {
+        for method_name in compiled.host_function_names() {
+            if !self.is_host_function(method_name) {
+                return Err(DslError::unknown_method(method_name));
+            }
+        }
+
+        Ok(())
+    }
+
+    fn is_host_function(&self, method_name: &str) -> bool {
+        self.host_functions.contains_key(method_name)
+            || self.lazy_host_functions.contains_key(method_name)
+            || self.context_host_functions.contains_key(method_name)
+    }
+
+    fn resolve_methods(
+        &self,
+        expression: &DewSchemaLanguageExpression,
+        host_function_names: &mut BTreeSet<String>,
+    ) -> Result<(), DslError> {
+        match &expression.kind {
+            DewSchemaLanguageExpressionKind::Call { method_name, args } => {
+                if self.is_host_function(method_name) {
+                    if let Some(signature) = self.host_function_signatures.get(method_name) {
+                        signature
+                            .check_arity(method_name, args.len())
+                            .map_err(|e| e.with_span(expression.span))?;
+                    }
+                    host_function_names.insert(method_name.clone());
+                } else if !MethodRegistry::global().contains(method_name) {
+                    return Err(DslError::unknown_method(method_name).with_span(expression.span));
+                }
+
+                for arg in args {
+                    self.resolve_methods(arg, host_function_names)?;
+                }
+            }
+            DewSchemaLanguageExpressionKind::Chain(chains) => {
+                for chain in chains {
+                    self.resolve_methods(chain, host_function_names)?;
+                }
+            }
+            DewSchemaLanguageExpressionKind::Let { value, body, .. } => {
+                self.resolve_methods(value, host_function_names)?;
+                self.resolve_methods(body, host_function_names)?;
+            }
+            DewSchemaLanguageExpressionKind::Lambda { body, .. } => {
+                self.resolve_methods(body, host_function_names)?;
+            }
+            DewSchemaLanguageExpressionKind::Index(key) => {
+                self.resolve_methods(key, host_function_names)?;
+            }
+            DewSchemaLanguageExpressionKind::Optional(element) => {
+                self.resolve_methods(element, host_function_names)?;
+            }
+            DewSchemaLanguageExpressionKind::Array(elements) => {
+                for element in elements {
+                    self.resolve_methods(element, host_function_names)?;
+                }
+            }
+            DewSchemaLanguageExpressionKind::Object(entries) => {
+                for (_, value) in entries {
+                    self.resolve_methods(value, host_function_names)?;
+                }
+            }
+            DewSchemaLanguageExpressionKind::Number(_)
+            | DewSchemaLanguageExpressionKind::StringLiteral(_)
+            | DewSchemaLanguageExpressionKind::Identifier(_) => {}
+        }
+
+        Ok(())
+    }
+}
+
+/// Evaluates expressions against one root object on behalf of an engine.
+struct DslEvaluator<'evaluator_lifetime> {
+    engine: &'evaluator_lifetime DewSchemaLanguageEngine,
+    root_object: &'evaluator_lifetime Value,
+    /// When set, failed assertions are recorded here instead of aborting the evaluation.
+    violations: Option<RefCell<Vec<DslError>>>,
+    tracer: Option<DslTracer<'evaluator_lifetime>>,
+    /// Nodes evaluated so far, bounded by the engine's `max_evaluation_steps`.
+    steps: Cell<usize>,
+    /// Gas charged so far, bounded by the engine's `max_gas`.
+    gas_used: Cell<u64>,
+    host_data: Option<&'evaluator_lifetime (dyn DslHostData + 'evaluator_lifetime)>,
+}
+
+/// Builds the trace of an evaluation: each node being evaluated has a frame collecting the
+/// nodes evaluated on its behalf, which become its children once it has a result.
+struct DslTracer<'tracer_lifetime> {
+    source: &'tracer_lifetime str,
+    frames: RefCell<Vec<Vec<DslTraceNode>>>,
+}
+
+impl DslTracer<'_> {
+    fn enter(&self) {
+        self.frames.borrow_mut().push(Vec::new());
+    }
+
+    fn leave(
+        &self,
+        expression: &DewSchemaLanguageExpression,
+        callee: Option<&DewSchemaLanguageResult>,
+        result: &Result<DewSchemaLanguageResult, DslError>,
+    ) {
+        let mut frames = self.frames.borrow_mut();
+        let children = frames.pop().unwrap_or_default();
+        let args = match expression.kind {
+            DewSchemaLanguageExpressionKind::Call { .. } => {
+                children.iter().map(|child| child.result.clone()).collect()
+            }
+            _ => Vec::new(),
+        };
+        let node = DslTraceNode {
+            source: self
+                .source
+                .get(expression.span.start.offset..expression.span.end.offset)
+                .unwrap_or_default()
+                .to_string(),
+            span: expression.span,
+            callee: callee.map(trace::render_value),
+            args,
+            result: result
+                .as_ref()
+                .map(trace::render_value)
+                .map_err(Clone::clone),
+            children,
+        };
+
+        match frames.last_mut() {
+            Some(parent) => parent.push(node),
+            // the root node stays on the stack so it can be taken at the end
+            None => frames.push(vec![node]),
+        }
+    }
+
+    fn into_trace(self, result: Result<DewSchemaLanguageResult, DslError>) -> Option<DslTrace> {
+        let root = self.frames.into_inner().pop()?.pop()?;
+
+        Some(DslTrace { root, result })
+    }
+}
+
+impl<'evaluator_lifetime> DslEvaluator<'evaluator_lifetime> {
+    fn new(
+        engine: &'evaluator_lifetime DewSchemaLanguageEngine,
+        root_object: &'evaluator_lifetime Value,
+    ) -> Self {
+        DslEvaluator {
+            engine,
+            root_object,
+            violations: None,
+            tracer: None,
+            steps: Cell::new(0),
+            gas_used: Cell::new(0),
+            host_data: None,
+        }
+    }
+
+    fn with_host_data(
+        mut self,
+        host_data: &'evaluator_lifetime (dyn DslHostData + 'evaluator_lifetime),
+    ) -> Self {
+        self.host_data = Some(host_data);
+        self
+    }
+
+    fn tracing(mut self, source: &'evaluator_lifetime str) -> Self {
+        self.tracer = Some(DslTracer {
+            source,
+            frames: RefCell::new(Vec::new()),
+        });
+        self
+    }
+
+    fn collecting_violations(mut self) -> Self {
+        self.violations = Some(RefCell::new(Vec::new()));
+        self
+    }
+
+    fn violation_count(&self) -> usize {
+        self.violations
+            .as_ref()
+            .map_or(0, |violations| violations.borrow().len())
+    }
+
+    /// Gives the path of `chain` to the violations recorded since there were `since` of
+    /// them that do not have a more precise one yet.
+    fn set_violation_paths(&self, since: usize, chain: &[DewSchemaLanguageExpression]) {
+        if let Some(violations) = &self.violations {
+            for violation in violations.borrow_mut().iter_mut().skip(since) {
+                if violation.path.is_none() {
+                    violation.path = Some(DewSchemaLanguageExpression::path(chain));
+                }
+            }
+        }
+    }
+    /// Evaluates one node. Errors point at the innermost node that failed.
+    fn evaluate_atom(
+        &self,
+        expression: &DewSchemaLanguageExpression,
+        callee: Option<&DewSchemaLanguageResult>,
+        scope: Option<&DslScope>,
+    ) -> Result<DewSchemaLanguageResult, DslError> {
+        if let Some(tracer) = &self.tracer {
+            tracer.enter();
+        }
+
+        let result = self
+            .count_step()
+            .and_then(|_| self.evaluate_kind(&expression.kind, callee, scope))
+            .and_then(|result| self.check_collection_size(&expression.kind, result))
+            .and_then(|result| self.check_number_size(result))
+            .map_err(|e| e.or_span(expression.span));
+
+        if let Some(tracer) = &self.tracer {
+            tracer.leave(expression, callee, &result);
+        }
+
+        match (result, &self.violations, &expression.kind) {
+            // a failed assertion is recorded and then treated as passed, so that the
+            // assertions after it, e.g. on the right of `&&`, are still checked
+            (Err(e), Some(violations), DewSchemaLanguageExpressionKind::Call { .. })
+                if e.kind == DslErrorKind::AssertionFailed =>
+            {
+                violations.borrow_mut().push(e);
+                Ok(DewSchemaLanguageResult::Boolean(true))
+            }
+            (result, _, _) => result,
+        }
+    }
+
+    fn count_step(&self) -> Result<(), DslError> {
+        let steps = self.steps.get() + 1;
+        let max_steps = self.engine.limits.max_evaluation_steps;
+
+        if steps > max_steps {
+            return Err(DslError::step_limit_exceeded(max_steps));
+        }
+
+        self.steps.set(steps);
+        Ok(())
+    }
+
+    fn charge_gas(
+        &self,
+        cost: DslGasCost,
+        callee: Option<&DewSchemaLanguageResult>,
+        args: &[DewSchemaLanguageResult],
+    ) -> Result<(), DslError> {
+        let gas_used = self
+            .gas_used
+            .get()
+            .saturating_add(cost.charge(callee, args));
+        let max_gas = self.engine.limits.max_gas;
+
+        if gas_used > max_gas {
+            self.gas_used.set(max_gas);
+            return Err(DslError::out_of_gas(max_gas));
+        }
+
+        self.gas_used.set(gas_used);
+        Ok(())
+    }
+
+    /// Rejects arrays and objects above the size limit where they are created: by method
+    /// calls and literals. Collections read from the root object are not limited.
+    fn check_collection_size(
+        &self,
+        kind: &DewSchemaLanguageExpressionKind,
+        result: DewSchemaLanguageResult,
+    ) -> Result<DewSchemaLanguageResult, DslError> {
+        let creates_collection = matches!(
+            kind,
+            DewSchemaLanguageExpressionKind::Call { .. }
+                | DewSchemaLanguageExpressionKind::Array(_)
+                | DewSchemaLanguageExpressionKind::Object(_)
+        );
+        let size = match &result {
+            DewSchemaLanguageResult::Value(Value::Array(array)) if creates_collection => {
+                array.len()
+            }
+            DewSchemaLanguageResult::Value(Value::Object(object)) if creates_collection => {
+                object.len()
+            }
+            _ => return Ok(result),
+        };
+        let max_size = self.engine.limits.max_collection_size;
+
+        if size > max_size {
+            return Err(DslError::collection_too_large(size, max_size));
+        }
+
+        Ok(result)
+    }
+
+    /// Rejects numbers above the digit limit wherever they come from: the root object, a
+    /// host function or arithmetic.
+    fn check_number_size(
+        &self,
+        result: DewSchemaLanguageResult,
+    ) -> Result<DewSchemaLanguageResult, DslError> {
+        if let DewSchemaLanguageResult::Number(n) = &result {
+            let digits = number::digit_count(n);
+            let max_digits = self.engine.limits.max_number_digits;
+
+            if digits > max_digits {
+                return Err(DslError::number_too_large(digits, max_digits));
+            }
+        }
+
+        Ok(result)
+    }
+
+    fn evaluate_kind(
+        &self,
+        kind: &DewSchemaLanguageExpressionKind,
+        callee: Option<&DewSchemaLanguageResult>,
+        scope: Option<&DslScope>,
+    ) -> Result<DewSchemaLanguageResult, DslError> {
+        let result = match kind {
+            DewSchemaLanguageExpressionKind::Number(num_str) => {
+                DewSchemaLanguageResult::Number(number::parse_number(num_str)?)
+            }
+            DewSchemaLanguageExpressionKind::StringLiteral(s) => {
+                DewSchemaLanguageResult::String(s.clone())
+            }
+            DewSchemaLanguageExpressionKind::Identifier(identifier) => {
+                if callee.is_none() && identifier == "$" {
+                    DewSchemaLanguageResult::from_json(self.root_object)?
+                } else if callee.is_none() && identifier == "true" {
+                    DewSchemaLanguageResult::Boolean(true)
+                } else if callee.is_none() && identifier == "false" {
+                    DewSchemaLanguageResult::Boolean(false)
+                } else if let (None, Some(value)) = (callee, DslScope::lookup(scope, identifier)) {
+                    value.clone()
+                } else if callee.is_none() && identifier == "item" {
+                    return Err(DslError::new(
+                        DslErrorKind::UnknownIdentifier,
+                        "'item' can only be used inside an iteration method",
+                    ));
+                } else if let Some(callee) = callee {
+                    match callee {
+                        DewSchemaLanguageResult::Value(Value::Object(map)) => {
+                            if let Some(value) = map.get(identifier) {
+                                DewSchemaLanguageResult::from_json(value)?
+                            } else {
+                                DewSchemaLanguageResult::Undefined
+                            }
+                        }
+                        DewSchemaLanguageResult::Undefined | DewSchemaLanguageResult::Null => {
+                            return Err(DslError::type_mismatch(format!(
+                                "Cannot access property '{}' on {}, use '?.' for optional values",
+                                identifier,
+                                if *callee == DewSchemaLanguageResult::Null {
+                                    "null"
+                                } else {
+                                    "undefined"
+                                }
+                            )));
+                        }
+                        _ => {
+                            return Err(DslError::type_mismatch(format!(
+                                "Cannot access property '{}' on non-object",
+                                identifier
+                            )));
+                        }
+                    }
+                } else {
+                    return Err(DslError::unknown_identifier(identifier));
+                }
+            }
+            DewSchemaLanguageExpressionKind::Call { method_name, args } => {
+                let params = DslLazyParams {
+                    evaluator: self,
+                    args,
+                    scope,
+                };
+
+                if let Some(func) = self.engine.host_functions.get(method_name) {
+                    let args = params.evaluate_all()?;
+                    let signature = self.engine.host_function_signatures.get(method_name);
+
+                    if let Some(signature) = signature {
+                        signature.check_call(method_name, callee, &args)?;
+                    }
+                    self.charge_gas(
+                        self.engine.host_function_gas_cost(method_name),
+                        callee,
+                        &args,
+                    )?;
+                    let result = func(args, callee)?.normalize()?;
+
+                    if let Some(signature) = signature {
+                        signature.check_result(method_name, &result)?;
+                    }
+                    result
+                } else if let Some(func) = self.engine.context_host_functions.get(method_name) {
+                    let args = params.evaluate_all()?;
+                    self.charge_gas(
+                        self.engine.host_function_gas_cost(method_name),
+                        callee,
+                        &args,
+                    )?;
+                    let mut context = DslHostContext {
+                        evaluator: self,
+                        scope,
+                        data: self.host_data.and_then(|data| data.borrow_data()),
+                    };
+
+                    func(args, callee, &mut context)?.normalize()?
+                } else if let Some(func) = self.engine.lazy_host_functions.get(method_name) {
+                    self.charge_gas(self.engine.host_function_gas_cost(method_name), callee, &[])?;
+                    func(params, callee)?.normalize()?
+                } else {
+                    let registry = MethodRegistry::global();
+                    let gas_cost = || {
+                        registry
+                            .gas_cost(method_name)
+                            .expect("every built-in method declares a gas cost")
+                    };
+
+                    match registry.get(method_name) {
+                        Some(BuiltinMethod::Eager(func)) => {
+                            let args = params.evaluate_all()?;
+                            registry.reject_undefined(method_name, callee, &args)?;
+                            self.charge_gas(gas_cost(), callee, &args)?;
+                            func(args, callee)?
+                        }
+                        Some(BuiltinMethod::Lazy(func)) => {
+                            registry.reject_undefined(method_name, callee, &[])?;
+                            self.charge_gas(gas_cost(), callee, &[])?;
+                            func(params, callee)?
+                        }
+                        None => {
+                            return Err(DslError::unknown_method(method_name));
+                        }
+                    }
+                }
+            }
+            DewSchemaLanguageExpressionKind::Let { name, value, body } => {
+                let value = self.evaluate_atom(value, None, scope)?;
+                let scope = DslScope {
+                    name,
+                    value: &value,
+                    parent: scope,
+                };
+
+                self.evaluate_atom(body, None, Some(&scope))?
+            }
+            DewSchemaLanguageExpressionKind::Index(key) => {
+                let key = self.evaluate_atom(key, None, scope)?;
+
+                match (callee, &key) {
+                    (
+                        Some(DewSchemaLanguageResult::Value(Value::Array(arr))),
+                        DewSchemaLanguageResult::Number(index),
+                    ) => array::element_at(arr, index)?,
+                    (
+                        Some(DewSchemaLanguageResult::Value(Value::Object(map))),
+                        DewSchemaLanguageResult::String(key),
+                    ) => match map.get(key) {
+                        Some(value) => DewSchemaLanguageResult::from_json(value)?,
+                        None => DewSchemaLanguageResult::Undefined,
+                    },
+                    (Some(DewSchemaLanguageResult::Value(Value::Array(_))), _) => {
+                        return Err(DslError::type_mismatch("Array index must be a number"));
+                    }
+                    (Some(DewSchemaLanguageResult::Value(Value::Object(_))), _) => {
+                        return Err(DslError::type_mismatch("Object key must be a string"));
+                    }
+                    _ => {
+                        return Err(DslError::type_mismatch(
+                            "Cannot index into a value that is not an array or object",
+                        ));
+                    }
+                }
+            }
+            DewSchemaLanguageExpressionKind::Array(elements) => {
+                let mut array = Vec::with_capacity(elements.len());
+
+                for element in elements {
+                    array.push(self.evaluate_atom(element, None, scope)?.to_json()?);
+                }
+
+                DewSchemaLanguageResult::Value(Value::Array(array))
+            }
+            DewSchemaLanguageExpressionKind::Object(entries) => {
+                let mut object = serde_json::Map::new();
+
+                for (key, value) in entries {
+                    object.insert(
+                        key.clone(),
+                        self.evaluate_atom(value, None, scope)?.to_json()?,
+                    );
+                }
+
+                DewSchemaLanguageResult::Value(Value::Object(object))
+            }
+            DewSchemaLanguageExpressionKind::Optional(element) => match callee {
+                Some(DewSchemaLanguageResult::Null | DewSchemaLanguageResult::Undefined) => {
+                    DewSchemaLanguageResult::Undefined
+                }
+                _ => self.evaluate_atom(element, callee, scope)?,
+            },
+            DewSchemaLanguageExpressionKind::Lambda { .. } => {
+                return Err(DslError::type_mismatch(
+                    "A lambda can only be passed to an iteration method",
+                ));
+            }
+            DewSchemaLanguageExpressionKind::Chain(chains) => {
+                let violations = self.violation_count();
+                let mut result = self
+                    .evaluate_atom(&chains[0], callee, scope)
+                    .map_err(|e| e.or_path(|| DewSchemaLanguageExpression::path(&chains[..1])))?;
+                self.set_violation_paths(violations, &chains[..1]);
+
+                for (index, chain) in chains.iter().enumerate().skip(1) {
+                    // `?.` on null or undefined skips the rest of the chain
+                    if matches!(chain.kind, DewSchemaLanguageExpressionKind::Optional(_))
+                        && matches!(
+                            result,
+                            DewSchemaLanguageResult::Null | DewSchemaLanguageResult::Undefined
+                        )
+                    {
+                        result = DewSchemaLanguageResult::Undefined;
+                        break;
+                    }
+
+                    let violations = self.violation_count();
+                    result = self
+                        .evaluate_atom(chain, Some(&result), scope)
+                        .map_err(|e| {
+                            e.or_path(|| DewSchemaLanguageExpression::path(&chains[..=index]))
+                        })?;
+                    self.set_violation_paths(violations, &chains[..=index]);
+                }
+
+                result
+            }
+        };
+
+        Ok(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn number(input: &str) -> DewSchemaLanguageResult {
+        DewSchemaLanguageResult::Number(number::parse_number(input).unwrap())
+    }
+
+    #[test]
+    fn test_evaluate_number() {
+        let engine = DewSchemaLanguageEngine::new("{}".into(), HashMap::new());
+        let result = engine.evaluate("42".into()).unwrap();
+        match result {
+            DewSchemaLanguageResult::Number(n) => assert_eq!(n, BigDecimal::from(42)),
+            _ => panic!("Expected number"),
+        }
+    }
+
+    #[test]
+    fn test_evaluate_string() {
+        let engine = DewSchemaLanguageEngine::new("{}".into(), HashMap::new());
+        let result = engine.evaluate(r#""hello""#.into()).unwrap();
+        match result {
+            DewSchemaLanguageResult::String(s) => assert_eq!(s, "hello"),
+            _ => panic!("Expected string"),
+        }
+    }
+
+    #[test]
+    fn test_evaluate_identifier() {
+        let engine =
+            DewSchemaLanguageEngine::new(r#"{"foo": {"bar": 123}}"#.into(), HashMap::new());
+        let result = engine.evaluate("$.foo.bar".into()).unwrap();
+        match result {
+            DewSchemaLanguageResult::Number(n) => assert_eq!(n, BigDecimal::from(123)),
+            _ => panic!("Expected number"),
+        }
+    }
+
+    #[test]
+    fn test_evaluate_call() {
+        let input = json!({
+            "foo": {
+                "bar": 100,
+                "baz": [1, 2, 3, 4, 5]
+            }
+        });
+
+        let engine = DewSchemaLanguageEngine::new(input.to_string(), HashMap::new());
+
+        let first_evaluate = engine
+            .evaluate("$.foo.bar.percent(10).equal(10)".to_string())
+            .unwrap();
+
+        let first_expected = DewSchemaLanguageResult::Boolean(true);
+
+        assert_eq!(first_evaluate, first_expected);
+
+        let second_evaluate = engine
+            .evaluate("$.foo.baz.length().equal(5)".to_string())
+            .unwrap();
+
+        let second_expected = DewSchemaLanguageResult::Boolean(true);
+        assert_eq!(second_evaluate, second_expected);
+    }
+
+    #[test]
+    fn test_evaluate_host_function() {
+        let mut host_functions: HashMap<String, DslFunction> = HashMap::new();
+
+        host_functions.insert(
+            "vault_id".into(),
+            Box::new(|_, callee| {
+                if callee.is_some() {
+                    return Err("vault_id should not be called with a callee".into());
+                }
+                Ok(DewSchemaLanguageResult::String("dewvault.near".into()))
+            }),
+        );
+
+        let input = json!({
+            "account_id": "dewvault.near",
+            "receiver_id": "some_other_account.near"
+        });
+
+        let engine = DewSchemaLanguageEngine::new(input.to_string(), host_functions);
+
+        let first_evaluate = engine
+            .evaluate("$.account_id.equal(vault_id())".to_string())
+            .unwrap();
+        let first_expected = DewSchemaLanguageResult::Boolean(true);
+        assert_eq!(first_evaluate, first_expected);
+
+        let second_evaluate = engine
+            .evaluate("$.receiver_id.equal(vault_id())".to_string())
+            .unwrap();
+        let second_expected = DewSchemaLanguageResult::Boolean(false);
+        assert_eq!(second_evaluate, second_expected);
+
+        let third_evaluate = engine
+            .evaluate("vault_id().equal($.account_id)".to_string())
+            .unwrap();
+        let third_expected = DewSchemaLanguageResult::Boolean(true);
+        assert_eq!(third_evaluate, third_expected);
+    }
+
+    #[test]
+    fn test_evaluate_iteration_methods() {
+        let input = json!({
+            "recipients": [
+                { "account_id": "alice.near", "amount": 40 },
+                { "account_id": "bob.near", "amount": 100 },
+                { "account_id": "carol.near", "amount": 250 }
+            ]
+        });
+
+        let engine = DewSchemaLanguageEngine::new(input.to_string(), HashMap::new());
+
+        let every = engine
+            .evaluate("$.recipients.every(item.amount.lte(100))".to_string())
+            .unwrap();
+        assert_eq!(every, DewSchemaLanguageResult::Boolean(false));
+
+        let some = engine
+            .evaluate("$.recipients.some(item.amount.gte(200))".to_string())
+            .unwrap();
+        assert_eq!(some, DewSchemaLanguageResult::Boolean(true));
+
+        let count_if = engine
+            .evaluate("$.recipients.count_if(item.amount.lte(100))".to_string())
+            .unwrap();
+        assert_eq!(count_if, number("2"));
+
+        let filter = engine
+            .evaluate("$.recipients.filter(item.amount.gte(100)).length()".to_string())
+            .unwrap();
+        assert_eq!(filter, number("2"));
+
+        let map = engine
+            .evaluate("$.recipients.map(item.account_id)".to_string())
+            .unwrap();
+        assert_eq!(
+            map,
+            DewSchemaLanguageResult::Value(json!(["alice.near", "bob.near", "carol.near"]))
+        );
+
+        let find = engine
+            .evaluate(r#"$.recipients.find(item.account_id.equal("bob.near")).amount"#.to_string())
+            .unwrap();
+        assert_eq!(find, number("100"));
+
+        let not_found = engine
+            .evaluate(r#"$.recipients.find(item.account_id.equal("dave.near"))"#.to_string())
+            .unwrap();
+        assert_eq!(not_found, DewSchemaLanguageResult::Undefined);
+    }
+
+    #[test]
+    fn test_evaluate_item_outside_iteration() {
+        let engine = DewSchemaLanguageEngine::new("{}".into(), HashMap::new());
+        let result = engine.evaluate("item.equal(1)".to_string());
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_evaluate_short_circuit() {
+        let input = json!({
+            "a": 1,
+            "b": [1, 2]
+        });
+
+        let engine = DewSchemaLanguageEngine::new(input.to_string(), HashMap::new());
+
+        let or_result = engine
+            .evaluate("or($.a.equal(1), $.b.get_index(5).equal(2))".to_string())
+            .unwrap();
+        assert_eq!(or_result, DewSchemaLanguageResult::Boolean(true));
+
+        let and_result = engine
+            .evaluate("$.a.equal(2).and($.b.get_index(5).equal(2))".to_string())
+            .unwrap();
+        assert_eq!(and_result, DewSchemaLanguageResult::Boolean(false));
+
+        let evaluated_branch =
+            engine.evaluate("or($.a.equal(2), $.b.get_index(5).equal(2))".to_string());
+        assert!(evaluated_branch.is_err());
+    }
+
+    #[test]
+    fn test_evaluate_lazy_host_function() {
+        let mut lazy_host_functions: HashMap<String, DslLazyFunction> = HashMap::new();
+
+        lazy_host_functions.insert(
+            "first_ok".into(),
+            Box::new(|params, _| {
+                for index in 0..params.len() {
+                    if let Ok(result) = params.evaluate(index) {
+                        return Ok(result);
+                    }
+                }
+                Err("no argument evaluated successfully".into())
+            }),
+        );
+
+        let engine = DewSchemaLanguageEngine::new(r#"{"limits": [5]}"#.into(), HashMap::new())
+            .with_lazy_host_functions(lazy_host_functions);
+
+        let result = engine
+            .evaluate("first_ok($.limits.get_index(3), $.limits.get_index(0))".to_string())
+            .unwrap();
+        assert_eq!(result, number("5"));
+    }
+
+    #[test]
+    fn test_evaluate_infix_operators() {
+        let input = json!({
+            "x": 7,
+            "y": 2,
+            "receiver_id": "alice.near"
+        });
+
+        let engine = DewSchemaLanguageEngine::new(input.to_string(), HashMap::new());
+
+        let cases = [
+            (
+                "$.x >= 5 && $.y <= 3",
+                DewSchemaLanguageResult::Boolean(true),
+            ),
+            (
+                "$.x < 5 || $.y > 3",
+                DewSchemaLanguageResult::Boolean(false),
+            ),
+            (
+                r#"$.receiver_id != "bob.near""#,
+                DewSchemaLanguageResult::Boolean(true),
+            ),
+            (
+                r#"!($.receiver_id == "alice.near")"#,
+                DewSchemaLanguageResult::Boolean(false),
+            ),
+            ("$.x + $.y * 3", number("13")),
+            ("($.x + $.y) * 3", number("27")),
+            ("$.x % $.y - 10 / 4", number("-1.5")),
+            ("-$.x + 1", number("-6")),
+            ("$.x-1", number("6")),
+            // a prefix `-` negates the whole chain, also before a number literal
+            ("-5.add(1)", number("-6")),
+            ("- 5.add(1)", number("-6")),
+            ("-$.x.add(1)", number("-8")),
+            ("2 - -3", number("5")),
+            ("- -5", number("5")),
+        ];
+
+        for (expression, expected) in cases {
+            let result = engine.evaluate(expression.to_string()).unwrap();
+            assert_eq!(result, expected, "{}", expression);
+        }
+    }
+
+    #[test]
+    fn test_evaluate_compiled_expression() {
+        let mut host_functions: HashMap<String, DslFunction> = HashMap::new();
+        host_functions.insert("limit".into(), Box::new(|_, _| Ok(number("100"))));
+
+        let engine = DewSchemaLanguageEngine::new("{}".into(), host_functions);
+        let compiled = engine.compile("$.amount <= limit()").unwrap();
+
+        assert_eq!(compiled.source(), "$.amount <= limit()");
+        assert!(compiled.host_function_names().contains("limit"));
+
+        let below = engine
+            .evaluate_compiled_with_root(&compiled, &json!({ "amount": 40 }))
+            .unwrap();
+        assert_eq!(below, DewSchemaLanguageResult::Boolean(true));
+
+        let above = engine
+            .evaluate_compiled_with_root(&compiled, &json!({ "amount": 400 }))
+            .unwrap();
+        assert_eq!(above, DewSchemaLanguageResult::Boolean(false));
+
+        let other_engine = DewSchemaLanguageEngine::new(r#"{"amount": 1}"#.into(), HashMap::new());
+        assert!(other_engine.evaluate_compiled(&compiled).is_err());
+    }
+
+    #[test]
+    fn test_compile_unknown_method() {
+        let engine = DewSchemaLanguageEngine::new("{}".into(), HashMap::new());
+        let result = engine.compile("true.or(unknown_method())");
+        let error = result.unwrap_err();
+        assert_eq!(error.kind, DslErrorKind::UnknownMethod);
+        assert_eq!(error.message, "Unknown method: unknown_method");
+    }
+
+    #[test]
+    fn test_method_registry_is_built_once() {
+        let registry = MethodRegistry::global();
+
+        assert!(std::ptr::eq(registry, MethodRegistry::global()));
+        assert!(registry.contains("equal"));
+        assert!(registry.contains("every"));
+        assert!(!registry.contains("vault_id"));
+    }
+
+    #[test]
+    fn test_host_function_overrides_builtin() {
+        let mut host_functions: HashMap<String, DslFunction> = HashMap::new();
+        host_functions.insert("length".into(), Box::new(|_, _| Ok(number("-1"))));
+
+        let engine = DewSchemaLanguageEngine::new(r#"{"list": [1, 2]}"#.into(), host_functions);
+
+        let overridden = engine.evaluate("$.list.length()".to_string()).unwrap();
+        assert_eq!(overridden, number("-1"));
+
+        let alias = engine
+            .evaluate("$.list.get_index(0).assert_equal(1)".to_string())
+            .unwrap();
+        assert_eq!(alias, DewSchemaLanguageResult::Boolean(true));
+    }
+
+    #[test]
+    fn test_evaluate_exact_token_amounts() {
+        let input = r#"{
+            "deposit": 4999999999999999999999999,
+            "amount": "5000000000000000000000001",
+            "fee": 0.1
+        }"#;
+
+        let engine = DewSchemaLanguageEngine::new(input.to_string(), HashMap::new());
+
+        let cases = [
+            ("$.deposit <= 5e24", DewSchemaLanguageResult::Boolean(true)),
+            (
+                "$.deposit + 1 == 5e24",
+                DewSchemaLanguageResult::Boolean(true),
+            ),
+            (
+                "$.amount.to_number() <= 5e24",
+                DewSchemaLanguageResult::Boolean(false),
+            ),
+            ("$.amount.to_number() - $.deposit", number("2")),
+            ("$.fee + 0.2 == 0.3", DewSchemaLanguageResult::Boolean(true)),
+            (
+                "$.deposit.percent(10)",
+                number("499999999999999999999999.9"),
+            ),
+            (
+                "$.deposit * 1000000",
+                number("4999999999999999999999999000000"),
+            ),
+            ("10 / 4", number("2.5")),
+        ];
+
+        for (expression, expected) in cases {
+            let result = engine.evaluate(expression.to_string()).unwrap();
+            assert_eq!(result, expected, "{}", expression);
+        }
+
+        let mapped = engine
+            .evaluate("array($.deposit).map(item + 1)".to_string())
+            .unwrap();
+        assert_eq!(
+            mapped,
+            DewSchemaLanguageResult::Value(
+                serde_json::from_str("[5000000000000000000000000]").unwrap()
+            )
+        );
+    }
+
+    #[test]
+    fn test_reject_out_of_range_numbers() {
+        let input = r#"{ "huge": 1e900000000, "tiny": 1e-900000000, "large": 1e10000 }"#;
+        // the bound holds even without a limit on number size
+        let engine = DewSchemaLanguageEngine::new(input.to_string(), HashMap::new())
+            .with_limits(DslLimits::unlimited());
+        let started = std::time::Instant::now();
+
+        for expression in [
+            "1e100000000 + 1 == 0",
+            "[1e900000000].length()",
+            "$.huge + 1 > 0",
+            "$.tiny > 0",
+        ] {
+            let error = engine.evaluate(expression.to_string()).unwrap_err();
+            assert_eq!(error.kind, DslErrorKind::TypeMismatch, "{}", expression);
+            assert!(error.message.contains("out of range"), "{}", expression);
+        }
+        assert!(engine
+            .evaluate("\"1e-100000000\".to_number()".to_string())
+            .is_err());
+        // rejected before any work proportional to the exponent is done
+        assert!(started.elapsed() < std::time::Duration::from_secs(1));
+
+        assert_eq!(
+            engine.evaluate("$.large > 1e9999".to_string()).unwrap(),
+            DewSchemaLanguageResult::Boolean(true)
+        );
+    }
+
+    #[test]
+    fn test_evaluate_error_kinds() {
+        let mut host_functions: HashMap<String, DslFunction> = HashMap::new();
+        host_functions.insert(
+            "daily_limit".into(),
+            Box::new(|_, _| Err("limit service unavailable".into())),
+        );
+
+        let input = json!({
+            "args": { "amount": 500, "receiver_id": "bob.near" }
+        });
+
+        let engine = DewSchemaLanguageEngine::new(input.to_string(), host_functions);
+
+        let assertion = engine
+            .evaluate("$.args.amount.assert_lte(100)".to_string())
+            .unwrap_err();
+        assert_eq!(assertion.kind, DslErrorKind::AssertionFailed);
+        assert_eq!(
+            assertion.path.as_deref(),
+            Some("$.args.amount.assert_lte(…)")
+        );
+
+        let type_mismatch = engine
+            .evaluate(r#"$.args.receiver_id.gte(5) || true"#.to_string())
+            .unwrap_err();
+        assert_eq!(type_mismatch.kind, DslErrorKind::TypeMismatch);
+        assert_eq!(
+            type_mismatch.path.as_deref(),
+            Some("$.args.receiver_id.gte(…)")
+        );
+
+        let arity = engine
+            .evaluate("$.args.amount.add(1, 2)".to_string())
+            .unwrap_err();
+        assert_eq!(arity.kind, DslErrorKind::Arity);
+
+        let host = engine
+            .evaluate("$.args.amount <= daily_limit()".to_string())
+            .unwrap_err();
+        assert_eq!(host.kind, DslErrorKind::Host);
+        assert_eq!(host.path.as_deref(), Some("daily_limit()"));
+
+        let parse = engine.evaluate("$.args.amount <=".to_string()).unwrap_err();
+        assert_eq!(parse.kind, DslErrorKind::Parse);
+        assert!(parse.span.is_some());
+
+        let lex = engine
+            .evaluate("$.args.amount # 1".to_string())
+            .unwrap_err();
+        assert_eq!(lex.kind, DslErrorKind::Lex);
+    }
+
+    #[test]
+    fn test_evaluate_error_span() {
+        let input = json!({ "args": { "amount": 500, "receiver_id": "bob.near" } });
+        let engine = DewSchemaLanguageEngine::new(input.to_string(), HashMap::new());
+
+        let error = engine
+            .evaluate("$.args.amount > 1 &&\n  $.args.receiver_id.gte(5)".to_string())
+            .unwrap_err();
+        assert_eq!(error.kind, DslErrorKind::TypeMismatch);
+
+        let span = error.span.unwrap();
+        assert_eq!((span.start.offset, span.end.offset), (42, 48));
+        assert_eq!((span.start.line, span.start.column), (2, 22));
+        assert_eq!((span.end.line, span.end.column), (2, 28));
+
+        let unknown = engine.compile("$.args.amount.frobnicate()").unwrap_err();
+        assert_eq!(unknown.span.map(|span| span.start.column), Some(15));
+    }
+
+    #[test]
+    fn test_engine_construction() {
+        let error = DewSchemaLanguageEngine::try_new(r#"{"args": "#, HashMap::new())
+            .err()
+            .unwrap();
+        assert_eq!(error.kind, DslErrorKind::InvalidRoot);
+
+        let mut host_functions: HashMap<String, DslFunction> = HashMap::new();
+        host_functions.insert(
+            "limit".into(),
+            Box::new(|_, _| Ok(DewSchemaLanguageResult::Number(100.into()))),
+        );
+
+        let first = json!({ "args": { "amount": 50 } });
+        let mut engine = DewSchemaLanguageEngine::from_value_ref(&first, host_functions);
+        assert_eq!(
+            engine.evaluate("$.args.amount <= limit()".to_string()),
+            Ok(DewSchemaLanguageResult::Boolean(true))
+        );
+
+        engine.set_root(json!({ "args": { "amount": 150 } }));
+        assert_eq!(
+            engine.evaluate("$.args.amount <= limit()".to_string()),
+            Ok(DewSchemaLanguageResult::Boolean(false))
+        );
+
+        let engine = engine.with_root(json!({ "args": { "amount": 100 } }));
+        assert_eq!(engine.root(), &json!({ "args": { "amount": 100 } }));
+        assert_eq!(
+            engine.evaluate("$.args.amount <= limit()".to_string()),
+            Ok(DewSchemaLanguageResult::Boolean(true))
+        );
+    }
+
+    #[test]
+    fn test_canonical_values() {
+        let mut host_functions: HashMap<String, DslFunction> = HashMap::new();
+        host_functions.insert(
+            "wrapped_null".into(),
+            Box::new(|_, _| Ok(DewSchemaLanguageResult::Value(Value::Null))),
+        );
+        host_functions.insert(
+            "wrapped_string".into(),
+            Box::new(|_, _| Ok(DewSchemaLanguageResult::Value(json!("a")))),
+        );
+
+        let input: Value =
+            serde_json::from_str(r#"{ "amounts": [1.50, 2], "memo": { "a": 1.0 } }"#).unwrap();
+        let engine = DewSchemaLanguageEngine::from_value(input, host_functions);
+        let evaluate = |expression: &str| engine.evaluate(expression.to_string()).unwrap();
+        let yes = DewSchemaLanguageResult::Boolean(true);
+        let no = DewSchemaLanguageResult::Boolean(false);
+
+        // `in` compares array elements in canonical form
+        assert_eq!(evaluate(r#""a".in(array("a", "b"))"#), yes);
+        assert_eq!(evaluate(r#"1.5.in($.amounts)"#), yes);
+        assert_eq!(evaluate(r#""2".in($.amounts)"#), no);
+        assert_eq!(evaluate(r#"wrapped_string().in(array("a"))"#), yes);
+
+        // `equal` is numeric for numbers at any depth and false across types
+        assert_eq!(evaluate(r#"$.amounts.equal(array(1.5, 2.00))"#), yes);
+        assert_eq!(evaluate(r#"$.memo.equal("{\"a\": 1}".json())"#), yes);
+        assert_eq!(evaluate(r#"1.equal("1")"#), no);
+        assert_eq!(evaluate(r#"array(1).equal(array(1, 2))"#), no);
+
+        // `json` and host functions never produce wrapped scalars
+        assert_eq!(evaluate(r#""null".json()"#), DewSchemaLanguageResult::Null);
+        assert_eq!(evaluate(r#""\"x\"".json() == "x""#), yes);
+        assert_eq!(evaluate(r#""1e2".json() == 100"#), yes);
+        assert_eq!(evaluate("wrapped_null()"), DewSchemaLanguageResult::Null);
+        assert_eq!(
+            evaluate("wrapped_string()"),
+            DewSchemaLanguageResult::String("a".into())
+        );
+
+        assert_eq!(DewSchemaLanguageResult::Value(json!(1)), number("1.0"));
+        assert_ne!(
+            DewSchemaLanguageResult::Null,
+            DewSchemaLanguageResult::Undefined
+        );
+    }
+
+    #[test]
+    fn test_let_bindings() {
+        let mut host_functions: HashMap<String, DslFunction> = HashMap::new();
+        host_functions.insert(
+            "limit".into(),
+            Box::new(|_, _| Ok(DewSchemaLanguageResult::Number(100.into()))),
+        );
+
+        let input = json!({ "args": { "amounts": [10, 20, 30], "fee": 5 } });
+        let engine = DewSchemaLanguageEngine::from_value(input, host_functions);
+        let evaluate = |expression: &str| engine.evaluate(expression.to_string());
+
+        assert_eq!(
+            evaluate(
+                "let fee = $.args.fee in let total = $.args.amounts.get_index(0) + fee in \
+                 total <= limit() && total == 15"
+            ),
+            Ok(DewSchemaLanguageResult::Boolean(true))
+        );
+
+        // inner bindings shadow outer ones and end with their body
+        assert_eq!(
+            evaluate("let x = 1 in (let x = 2 in x) + x"),
+            Ok(number("3"))
+        );
+        assert_eq!(
+            evaluate("(let x = 1 in x) + x").unwrap_err().kind,
+            DslErrorKind::UnknownIdentifier
+        );
+
+        // a binding keeps the outer element reachable from a nested iteration
+        assert_eq!(
+            evaluate(
+                "$.args.amounts.every(let amount = item in \
+                 array(1, 2).every(amount >= item * 5))"
+            ),
+            Ok(DewSchemaLanguageResult::Boolean(true))
+        );
+        assert_eq!(
+            evaluate(
+                "$.args.amounts.filter(let outer = item in $.args.amounts.some(item > outer))"
+            ),
+            Ok(DewSchemaLanguageResult::Value(json!([10, 20])))
+        );
+    }
+
+    #[test]
+    fn test_named_iteration_variables() {
+        let input = json!({
+            "receipts": [
+                { "receiver_id": "usdc.near", "actions": [
+                    { "method": "ft_transfer", "receiver_id": "usdc.near" },
+                    { "method": "storage_deposit", "receiver_id": "usdc.near" }
+                ] },
+                { "receiver_id": "wrap.near", "actions": [
+                    { "method": "near_deposit", "receiver_id": "wrap.near" }
+                ] }
+            ]
+        });
+        let engine = DewSchemaLanguageEngine::from_value(input, HashMap::new());
+        let evaluate = |expression: &str| engine.evaluate(expression.to_string());
+
+        assert_eq!(
+            evaluate("$.receipts.every(r => r.actions.every(a => a.receiver_id == r.receiver_id))"),
+            Ok(DewSchemaLanguageResult::Boolean(true))
+        );
+
+        // `index` is bound next to `item`, and a named index keeps the outer one reachable
+        assert_eq!(
+            evaluate("$.receipts.map(item.actions.length() + index)"),
+            Ok(DewSchemaLanguageResult::Value(json!([2, 2])))
+        );
+        assert_eq!(
+            evaluate("$.receipts.map((r, i) => r.actions.map(a => i * 10 + index))"),
+            Ok(DewSchemaLanguageResult::Value(json!([[0, 1], [10]])))
+        );
+        assert_eq!(
+            evaluate("$.receipts.map((r) => r.actions.length())"),
+            Ok(DewSchemaLanguageResult::Value(json!([2, 1])))
+        );
+
+        // a named element is not also bound as `item`
+        assert_eq!(
+            evaluate("$.receipts.every(r => item)").unwrap_err().kind,
+            DslErrorKind::UnknownIdentifier
+        );
+        assert_eq!(
+            evaluate("r => r").unwrap_err().kind,
+            DslErrorKind::TypeMismatch
+        );
+    }
+
+    #[test]
+    fn test_conditionals() {
+        let mut host_functions: HashMap<String, DslFunction> = HashMap::new();
+        host_functions.insert(
+            "fail".into(),
+            Box::new(|_, _| Err("must not be evaluated".into())),
+        );
+
+        let input = json!({ "method_name": "ft_transfer", "args": { "amount": 40 } });
+        let engine = DewSchemaLanguageEngine::from_value(input, host_functions);
+        let evaluate = |expression: &str| engine.evaluate(expression.to_string());
+
+        // only the chosen branch is evaluated
+        assert_eq!(
+            evaluate(r#"if($.args.amount > 10, "large", fail())"#),
+            Ok(DewSchemaLanguageResult::String("large".into()))
+        );
+        assert_eq!(
+            evaluate("($.args.amount > 100).if(fail(), $.args.amount * 2)"),
+            Ok(number("80"))
+        );
+        assert_eq!(
+            evaluate(r#"if($.args.amount, 1, 2)"#).unwrap_err().kind,
+            DslErrorKind::TypeMismatch
+        );
+
+        let limit = r#"$.args.amount <= $.method_name.match(
+            "ft_transfer", 50,
+            "storage_deposit", fail(),
+            0
+        )"#;
+        assert_eq!(evaluate(limit), Ok(DewSchemaLanguageResult::Boolean(true)));
+        assert_eq!(
+            evaluate(r#""near_deposit".match("ft_transfer", 50, 0)"#),
+            Ok(number("0"))
+        );
+        assert_eq!(
+            evaluate(r#""near_deposit".match("ft_transfer", 50)"#),
+            Ok(DewSchemaLanguageResult::Undefined)
+        );
+    }
+
+    #[test]
+    fn test_array_and_object_literals() {
+        let input = json!({
+            "receiver_id": "usdc.near",
+            "args": { "amount": 250 }
+        });
+        let engine = DewSchemaLanguageEngine::from_value(input, HashMap::new());
+        let evaluate = |expression: &str| engine.evaluate(expression.to_string());
+
+        assert_eq!(
+            evaluate(r#"$.receiver_id.in(["usdc.near", "usdt.near",])"#),
+            Ok(DewSchemaLanguageResult::Boolean(true))
+        );
+        assert_eq!(
+            evaluate(r#"{ "usdc.near": { max: 100 }, wrap: [1, [2]] }"#),
+            Ok(DewSchemaLanguageResult::Value(
+                json!({ "usdc.near": { "max": 100 }, "wrap": [1, [2]] })
+            ))
+        );
+        assert_eq!(
+            evaluate(r#"{ limits: [$.args.amount * 2, null_value] }"#)
+                .unwrap_err()
+                .kind,
+            DslErrorKind::UnknownIdentifier
+        );
+        assert_eq!(
+            evaluate("let limits = { usdc: 500, usdt: 100 } in $.args.amount <= limits.usdc"),
+            Ok(DewSchemaLanguageResult::Boolean(true))
+        );
+        assert_eq!(
+            evaluate("[$.args.missing]").unwrap_err().kind,
+            DslErrorKind::TypeMismatch
+        );
+    }
+
+    #[test]
+    fn test_bracket_indexing() {
+        let input = json!({
+            "args": [{ "amount": 10 }, { "amount": 20 }],
+            "balances": { "usdc.near": 500, "0xdeadbeef": 7, "my-token": 3 },
+            "key": "usdc.near"
+        });
+        let engine = DewSchemaLanguageEngine::from_value(input, HashMap::new());
+        let evaluate = |expression: &str| engine.evaluate(expression.to_string());
+
+        assert_eq!(evaluate("$.args[0].amount"), Ok(number("10")));
+        assert_eq!(evaluate("$.args[-1].amount"), Ok(number("20")));
+        assert_eq!(evaluate(r#"$.balances["usdc.near"]"#), Ok(number("500")));
+        assert_eq!(evaluate(r#"$.balances["0xdeadbeef"]"#), Ok(number("7")));
+        assert_eq!(evaluate("$.balances[$.key] + 1"), Ok(number("501")));
+        assert_eq!(
+            evaluate(r#"$.balances["missing"]"#),
+            Ok(DewSchemaLanguageResult::Undefined)
+        );
+
+        let out_of_bounds = evaluate("$.args[-3]").unwrap_err();
+        assert_eq!(out_of_bounds.kind, DslErrorKind::Evaluation);
+        assert_eq!(out_of_bounds.path.as_deref(), Some("$.args[-3]"));
+        assert_eq!(
+            evaluate(r#"$.args["0"]"#).unwrap_err().kind,
+            DslErrorKind::TypeMismatch
+        );
+        assert_eq!(
+            evaluate("$.key[0]").unwrap_err().kind,
+            DslErrorKind::TypeMismatch
+        );
+    }
+
+    #[test]
+    fn test_undefined_handling() {
+        let input = json!({
+            "args": { "amount": 10, "memo": null, "nested": { "ids": [1, 2] } }
+        });
+        let engine = DewSchemaLanguageEngine::from_value(input, HashMap::new());
+        let evaluate = |expression: &str| engine.evaluate(expression.to_string());
+        let yes = DewSchemaLanguageResult::Boolean(true);
+        let no = DewSchemaLanguageResult::Boolean(false);
+
+        // `?.` on null or undefined skips the rest of the chain instead of failing
+        assert_eq!(evaluate("$.args.nested?.ids?.[1]"), Ok(number("2")));
+        assert_eq!(
+            evaluate("$.args.missing?.ids?.[0]?.gte(1)"),
+            Ok(DewSchemaLanguageResult::Undefined)
+        );
+        assert_eq!(
+            evaluate("$.args.missing?.ids[0].length()"),
+            Ok(DewSchemaLanguageResult::Undefined)
+        );
+        let null_root = DewSchemaLanguageEngine::from_value(Value::Null, HashMap::new());
+        assert_eq!(
+            null_root.evaluate("$?.missing.length()".to_string()),
+            Ok(DewSchemaLanguageResult::Undefined)
+        );
+        // `?.` only guards the value before it, here `$`, which is present
+        assert_eq!(
+            evaluate("$?.missing.length()").unwrap_err().kind,
+            DslErrorKind::TypeMismatch
+        );
+        // operators apply to the result of the optional chain
+        assert_eq!(
+            evaluate("$.args.missing?.fee == 1"),
+            Ok(DewSchemaLanguageResult::Boolean(false))
+        );
+        assert_eq!(
+            evaluate("$.args.memo?.text"),
+            Ok(DewSchemaLanguageResult::Undefined)
+        );
+        assert_eq!(
+            evaluate("$.args.memo.text").unwrap_err().kind,
+            DslErrorKind::TypeMismatch
+        );
+        assert_eq!(
+            evaluate("$.args.missing.text").unwrap_err().kind,
+            DslErrorKind::TypeMismatch
+        );
+
+        // defaults
+        assert_eq!(
+            evaluate("($.args.missing?.fee).default(0) + 1"),
+            Ok(number("1"))
+        );
+        assert_eq!(evaluate("$.args.amount.default(0)"), Ok(number("10")));
+        assert_eq!(
+            evaluate("$.args.memo.default(\"none\")"),
+            Ok(DewSchemaLanguageResult::String("none".into()))
+        );
+        assert_eq!(
+            evaluate("coalesce($.args.missing, $.args.memo, $.args.amount)"),
+            Ok(number("10"))
+        );
+        assert_eq!(
+            evaluate("$.args.missing.coalesce($.args.memo)"),
+            Ok(DewSchemaLanguageResult::Undefined)
+        );
+        assert_eq!(evaluate("$.args.missing.is_defined()"), Ok(no.clone()));
+        assert_eq!(evaluate("$.args.memo.is_defined()"), Ok(yes.clone()));
+        assert_eq!(evaluate("$.args.memo.is_null()"), Ok(yes.clone()));
+        assert_eq!(evaluate("$.args.missing.is_null()"), Ok(no));
+
+        // equality and `match` accept undefined, other built-ins reject it
+        assert_eq!(evaluate("$.args.missing == $.args.other"), Ok(yes.clone()));
+        assert_eq!(evaluate("$.args.missing != $.args.memo"), Ok(yes));
+        assert_eq!(evaluate("$.args.missing.match(1, 2, 3)"), Ok(number("3")));
+        assert_eq!(
+            evaluate("$?.args?.missing >= 1").unwrap_err().kind,
+            DslErrorKind::TypeMismatch
+        );
+    }
+
+    #[test]
+    fn test_undefined_propagation_through_builtins() {
+        let engine = DewSchemaLanguageEngine::from_value(json!({}), HashMap::new());
+
+        for method_name in MethodRegistry::global().names() {
+            if crate::methods::registry::UNDEFINED_AWARE_METHODS.contains(&method_name) {
+                continue;
+            }
+
+            let on_callee = engine
+                .evaluate(format!("$.missing.{}(1)", method_name))
+                .unwrap_err();
+            assert_eq!(
+                on_callee.kind,
+                DslErrorKind::TypeMismatch,
+                "{}",
+                method_name
+            );
+            assert!(on_callee.message.contains("undefined"), "{}", on_callee);
+
+            let as_argument = engine
+                .evaluate(format!("1.{}($.missing)", method_name))
+                .unwrap_err();
+            assert!(
+                as_argument.message.contains("undefined")
+                    || matches!(
+                        MethodRegistry::global().get(method_name),
+                        Some(BuiltinMethod::Lazy(_))
+                    ),
+                "{}: {}",
+                method_name,
+                as_argument
+            );
+        }
+    }
+
+    #[test]
+    fn test_type_inspection() {
+        let input = json!({
+            "args": { "amount": 1.5, "count": 3, "memo": null, "ids": [], "meta": {}, "ok": true, "to": "bob.near" }
+        });
+        let engine = DewSchemaLanguageEngine::from_value(input, HashMap::new());
+        let evaluate = |expression: &str| engine.evaluate(expression.to_string());
+        let string = |s: &str| Ok(DewSchemaLanguageResult::String(s.into()));
+        let yes = Ok(DewSchemaLanguageResult::Boolean(true));
+        let no = Ok(DewSchemaLanguageResult::Boolean(false));
+
+        assert_eq!(evaluate("$.args.amount.type_of()"), string("number"));
+        assert_eq!(evaluate("$.args.to.type_of()"), string("string"));
+        assert_eq!(evaluate("$.args.ok.type_of()"), string("boolean"));
+        assert_eq!(evaluate("$.args.ids.type_of()"), string("array"));
+        assert_eq!(evaluate("$.args.meta.type_of()"), string("object"));
+        assert_eq!(evaluate("$.args.memo.type_of()"), string("null"));
+        assert_eq!(evaluate("$.args.missing.type_of()"), string("undefined"));
+
+        assert_eq!(evaluate("$.args.to.is_string()"), yes);
+        assert_eq!(evaluate("$.args.amount.is_number()"), yes);
+        assert_eq!(evaluate("$.args.amount.is_integer()"), no);
+        assert_eq!(evaluate("$.args.count.is_integer()"), yes);
+        assert_eq!(evaluate("$.args.ok.is_bool()"), yes);
+        assert_eq!(evaluate("$.args.ids.is_array()"), yes);
+        assert_eq!(evaluate("$.args.meta.is_object()"), yes);
+        assert_eq!(evaluate("$.args.ids.is_object()"), no);
+        assert_eq!(evaluate("$.args.missing.is_string()"), no);
+
+        assert_eq!(evaluate(r#"$.args.to.assert_type("string")"#), yes);
+        assert_eq!(
+            evaluate(r#"$.args.memo.assert_type("string", "null")"#),
+            yes
+        );
+        let error = evaluate(r#"$.args.amount.assert_type("integer")"#).unwrap_err();
+        assert_eq!(error.kind, DslErrorKind::AssertionFailed);
+        assert_eq!(error.message, "1.5 is of type number, expected integer");
+        assert_eq!(
+            evaluate(r#"$.args.to.assert_type("number", "null")"#)
+                .unwrap_err()
+                .message,
+            "\"bob.near\" is of type string, expected number or null"
+        );
+        assert_eq!(
+            evaluate(r#"$.args.missing.assert_type("string")"#)
+                .unwrap_err()
+                .kind,
+            DslErrorKind::AssertionFailed
+        );
+        let error = evaluate(r#"$.args.to.assert_type("text")"#).unwrap_err();
+        assert_eq!(error.kind, DslErrorKind::TypeMismatch);
+        assert_eq!(
+            error.message,
+            "'assert_type' expects type names, one of array, boolean, integer, null, number, object, string, undefined, got text"
+        );
+    }
+
+    #[test]
+    fn test_assertion_messages() {
+        let engine = DewSchemaLanguageEngine::from_value(
+            json!({ "amount": 500, "deposit": "5000000000000000000000000", "owner": "alice.near" }),
+            HashMap::new(),
+        );
+        let message = |expression: &str| {
+            let error = engine.evaluate(expression.to_string()).unwrap_err();
+            assert_eq!(error.kind, DslErrorKind::AssertionFailed, "{}", expression);
+            error.message
+        };
+
+        assert_eq!(message("2.assert_equal(3)"), "2 is not equal to 3");
+        assert_eq!(
+            message("$.amount.assert_lte(100.5)"),
+            "500 is not less than or equal to 100.5"
+        );
+        assert_eq!(
+            message("$.deposit.to_number().assert_gte(1e25)"),
+            "5000000000000000000000000 is not greater than or equal to 10000000000000000000000000"
+        );
+        assert_eq!(
+            message("$.owner.assert_case_insensitive_equal(\"bob.near\")"),
+            "\"alice.near\" is not case insensitively equal to \"bob.near\""
+        );
+        assert_eq!(
+            message("{ a: [1, true, \"x\"] }.assert_equal({ a: [] })"),
+            "{\"a\":[1,true,\"x\"]} is not equal to {\"a\":[]}"
+        );
+    }
+
+    #[test]
+    fn test_collect_assertion_failures() {
+        let engine = DewSchemaLanguageEngine::from_value(
+            json!({ "a": 2, "b": 5, "c": 1, "list": [0, 1, -1] }),
+            HashMap::new(),
+        );
+        let expression = "$.a.assert_equal(1) && $.b.assert_gte(10) && $.c.assert_lte(0)";
+
+        // a plain evaluation stops at the first failure
+        let error = engine.evaluate(expression.to_string()).unwrap_err();
+        assert_eq!(error.kind, DslErrorKind::AssertionFailed);
+
+        let evaluation = engine.evaluate_collecting(expression.to_string());
+        assert_eq!(
+            evaluation.result,
+            Ok(DewSchemaLanguageResult::Boolean(true))
+        );
+        assert!(!evaluation.is_ok());
+        assert_eq!(
+            evaluation
+                .violations
+                .iter()
+                .map(|violation| violation.path.as_deref())
+                .collect::<Vec<_>>(),
+            vec![
+                Some("$.a.assert_equal(…)"),
+                Some("$.b.assert_gte(…)"),
+                Some("$.c.assert_lte(…)"),
+            ]
+        );
+        assert!(evaluation.violations[1]
+            .message
+            .contains("is not greater than or equal to"));
+        assert!(evaluation
+            .violations
+            .iter()
+            .all(|violation| violation.kind == DslErrorKind::AssertionFailed));
+        let span = evaluation.violations[1].span.unwrap();
+        assert_eq!(
+            &expression[span.start.offset..span.end.offset],
+            "assert_gte(10)"
+        );
+
+        // a missing field fails its assertion instead of stopping the evaluation
+        let evaluation = engine.evaluate_collecting(
+            "$.missing.assert_equal(1) && $.b.assert_lte($.other) && $.list.length().assert_equal(2)"
+                .to_string(),
+        );
+        assert_eq!(
+            evaluation.result,
+            Ok(DewSchemaLanguageResult::Boolean(true))
+        );
+        assert_eq!(
+            evaluation
+                .violations
+                .iter()
+                .map(|violation| (violation.kind, violation.message.as_str()))
+                .collect::<Vec<_>>(),
+            vec![
+                (DslErrorKind::AssertionFailed, "undefined is not equal to 1"),
+                (
+                    DslErrorKind::AssertionFailed,
+                    "5 is not less than or equal to undefined"
+                ),
+                (DslErrorKind::AssertionFailed, "3 is not equal to 2"),
+            ]
+        );
+
+        // assertions inside iterations are checked for every element
+        let evaluation = engine.evaluate_collecting("$.list.every(item.assert_gte(0))".to_string());
+        assert_eq!(
+            evaluation.result,
+            Ok(DewSchemaLanguageResult::Boolean(true))
+        );
+        assert_eq!(evaluation.violations.len(), 1);
+        assert_eq!(
+            evaluation.violations[0].path.as_deref(),
+            Some("item.assert_gte(…)")
+        );
+
+        // other errors still stop evaluation, keeping the violations found before them
+        let evaluation =
+            engine.evaluate_collecting("$.a.assert_equal(1) && $.missing.length() > 0".to_string());
+        assert_eq!(
+            evaluation.result.unwrap_err().kind,
+            DslErrorKind::TypeMismatch
+        );
+        assert_eq!(evaluation.violations.len(), 1);
+
+        let evaluation = engine.evaluate_collecting("$.a.assert_equal(".to_string());
+        assert_eq!(evaluation.result.unwrap_err().kind, DslErrorKind::Parse);
+        assert!(evaluation.violations.is_empty());
+
+        assert!(engine
+            .evaluate_collecting("$.a.assert_equal(2)".to_string())
+            .is_ok());
+    }
+
+    #[test]
+    fn test_evaluate_traced() {
+        let engine = DewSchemaLanguageEngine::from_value(
+            json!({ "amount": 500, "to": "bob" }),
+            HashMap::new(),
+        );
+        let trace = engine
+            .evaluate_traced(r#"$.amount <= 100 && $.to == "bob""#.to_string())
+            .unwrap();
+
+        assert_eq!(trace.result(), &Ok(DewSchemaLanguageResult::Boolean(false)));
+        assert_eq!(
+            trace.render_tree(),
+            [
+                r#"$.amount <= 100 && $.to == "bob" => false"#,
+                r#"  $ => {"amount":500,"to":"bob"}"#,
+                r#"  amount on {"amount":500,"to":"bob"} => 500"#,
+                "  <= 100 on 500 with 100 => false",
+                "    100 => 100",
+                r#"  && $.to == "bob" on false => false"#,
+                "",
+            ]
+            .join("\n")
+        );
+
+        let comparison = &trace.root.children[2];
+        assert_eq!(comparison.callee.as_deref(), Some("500"));
+        assert_eq!(comparison.args, vec![Ok("100".to_string())]);
+        assert_eq!(comparison.span.start.column, 10);
+
+        let json = trace.to_json();
+        assert_eq!(json["source"], json!(r#"$.amount <= 100 && $.to == "bob""#));
+        assert_eq!(json["result"], json!("false"));
+        assert_eq!(json["children"][2]["args"], json!([{ "result": "100" }]));
+        assert_eq!(json["children"][2]["callee"], json!("500"));
+        assert_eq!(
+            json["children"][2]["start"],
+            json!({ "offset": 9, "line": 1, "column": 10 })
+        );
+
+        // iteration methods record one argument evaluation per element
+        let trace = engine
+            .evaluate_traced("[1, 2].map(x => x * 10)".to_string())
+            .unwrap();
+        let map = &trace.root.children[1];
+        assert_eq!(map.args, vec![Ok("10".to_string()), Ok("20".to_string())]);
+        assert_eq!(map.children[0].source, "x * 10");
+
+        // an argument that failed keeps its place
+        let trace = engine
+            .evaluate_traced("$.amount.add(1, $.to.negate())".to_string())
+            .unwrap();
+        let add = &trace.root.children[2];
+        assert_eq!(add.args.len(), 2);
+        assert_eq!(add.args[0], Ok("1".to_string()));
+        assert_eq!(
+            add.args[1].as_ref().unwrap_err().kind,
+            DslErrorKind::TypeMismatch
+        );
+        assert!(trace.render_tree().contains(" with 1, error: "));
+
+        // values are cut instead of copied into every node
+        let long: Vec<u32> = (0..10_000).collect();
+        let engine = DewSchemaLanguageEngine::from_value(json!({ "long": long }), HashMap::new());
+        let trace = engine
+            .evaluate_traced("$.long.length()".to_string())
+            .unwrap();
+        let root = trace.root.children[0].result.as_ref().unwrap();
+        assert!(root.starts_with(r#"{"long":[0,1,2,"#) && root.ends_with('…'));
+        assert_eq!(root.chars().count(), 81);
+        assert_eq!(trace.result(), &Ok(number("10000")));
+
+        // errors end up in the trace, on the node that failed and its ancestors
+        let trace = engine
+            .evaluate_traced("$.to.length() > $.missing.length()".to_string())
+            .unwrap();
+        assert_eq!(
+            trace.result().as_ref().unwrap_err().kind,
+            DslErrorKind::TypeMismatch
+        );
+        assert!(trace
+            .render_tree()
+            .contains("length() on undefined => error: "));
+        assert_eq!(trace.to_json()["error"]["kind"], json!("TypeMismatch"));
+
+        assert_eq!(
+            engine
+                .evaluate_traced("$.amount.nope()".to_string())
+                .unwrap_err()
+                .kind,
+            DslErrorKind::UnknownMethod
+        );
+    }
+
+    #[test]
+    fn test_limits() {
+        let engine =
+            DewSchemaLanguageEngine::from_value(json!({ "list": [1, 2, 3, 4, 5] }), HashMap::new());
+        let error_kind = |engine: &DewSchemaLanguageEngine, expression: &str| {
+            engine.evaluate(expression.to_string()).unwrap_err().kind
+        };
+
+        // the default nesting limit leaves room on the stack to evaluate that deep
+        let max_depth = DslLimits::DEFAULT_MAX_NESTING_DEPTH;
+        let nested = format!(
+            "1{}{}",
+            ".add(1".repeat(max_depth - 1),
+            ")".repeat(max_depth - 1)
+        );
+        assert_eq!(
+            engine.evaluate(nested.clone()).unwrap(),
+            number(&max_depth.to_string())
+        );
+        assert_eq!(
+            error_kind(&engine, &format!("1.add({})", nested)),
+            DslErrorKind::NestingTooDeep
+        );
+
+        let limited = DewSchemaLanguageEngine::from_value(engine.root().clone(), HashMap::new())
+            .with_limits(DslLimits {
+                max_expression_length: 40,
+                max_nesting_depth: 4,
+                max_evaluation_steps: 30,
+                max_collection_size: 3,
+                max_number_digits: 30,
+                ..DslLimits::default()
+            });
+
+        let error = limited
+            .evaluate("$.list.length() > 0 && $.list.length() < 100".to_string())
+            .unwrap_err();
+        assert_eq!(error.kind, DslErrorKind::ExpressionTooLong);
+        assert_eq!(
+            error.message,
+            "Expression is 44 bytes long, the limit is 40"
+        );
+
+        assert_eq!(
+            error_kind(&limited, "[[[[1]]]]"),
+            DslErrorKind::NestingTooDeep
+        );
+
+        // each element of an iteration costs steps
+        assert!(limited
+            .evaluate("$.list.every(item > 0)".to_string())
+            .is_ok());
+        let error = limited
+            .evaluate("$.list.every(item > 0 && item < 9)".to_string())
+            .unwrap_err();
+        assert_eq!(error.kind, DslErrorKind::StepLimitExceeded);
+        assert_eq!(error.message, "Evaluation exceeded the limit of 30 steps");
+
+        // collections created by the expression are limited, the root's are not
+        assert!(limited.evaluate("$.list.length() == 5".to_string()).is_ok());
+        assert_eq!(
+            error_kind(&limited, "[1, 2, 3, 4]"),
+            DslErrorKind::CollectionTooLarge
+        );
+        assert_eq!(
+            error_kind(&limited, "{a: 1, b: 2, c: 3, d: 4}"),
+            DslErrorKind::CollectionTooLarge
+        );
+        let error = limited
+            .evaluate("$.list.filter(item > 1).length()".to_string())
+            .unwrap_err();
+        assert_eq!(error.kind, DslErrorKind::CollectionTooLarge);
+        assert_eq!(error.path.as_deref(), Some("$.list.filter(…)"));
+
+        // number size is checked for literals, results and numbers read from JSON
+        let error = limited.compile("1e40 > 0").unwrap_err();
+        assert_eq!(error.kind, DslErrorKind::NumberTooLarge);
+        assert_eq!(error.message, "Number spans 41 digits, the limit is 30");
+        assert_eq!(error.span.map(|span| span.start.offset), Some(0));
+        assert!(limited.evaluate("1e29 > 0".to_string()).is_ok());
+        let error = limited
+            .evaluate("$.list.length() * 1e29 * 100".to_string())
+            .unwrap_err();
+        assert_eq!(error.kind, DslErrorKind::NumberTooLarge);
+        assert_eq!(
+            error.path.as_deref(),
+            Some("$.list.length().multiply(…).multiply(…)")
+        );
+        assert_eq!(
+            error_kind(
+                &limited.with_root(serde_json::from_str(r#"{ "big": 1e40 }"#).unwrap()),
+                "$.big > 0"
+            ),
+            DslErrorKind::NumberTooLarge
+        );
+
+        assert_eq!(DslLimits::unlimited().max_nesting_depth, max_depth);
+    }
+
+    #[test]
+    fn test_gas_metering() {
+        let mut host_functions: HashMap<String, DslFunction> = HashMap::new();
+        host_functions.insert("limit".into(), Box::new(|_, _| Ok(number("100"))));
+        host_functions.insert("fee".into(), Box::new(|_, _| Ok(number("1"))));
+        let engine =
+            DewSchemaLanguageEngine::from_value(json!({ "list": [1, 2, 3, 4, 5] }), host_functions)
+                .with_host_function_gas_costs(HashMap::from([(
+                    "limit".to_string(),
+                    DslGasCost::constant(5),
+                )]));
+        let gas_used = |engine: &DewSchemaLanguageEngine, expression: &str| {
+            engine.evaluate_metered(expression.to_string()).gas_used
+        };
+
+        // fields and literals are free, method calls and operators are not
+        assert_eq!(gas_used(&engine, "$.list"), 0);
+        assert_eq!(gas_used(&engine, "$.list.length()"), 1);
+        // linear costs grow with the bytes of strings, the digits and exponent of numbers,
+        // and the elements of collections
+        assert_eq!(gas_used(&engine, "\"abc\".concat(\"de\")"), 1 + 5);
+        assert_eq!(gas_used(&engine, "12.5 + 1"), 1 + (3 + 1) + 1);
+        assert_eq!(gas_used(&engine, "1e3 > 0"), 1 + (1 + 3) + 1);
+        assert_eq!(gas_used(&engine, "$.list.in(3)"), 1 + 5 * (1 + 1) + 1);
+        // lazy arguments are charged once per evaluation
+        assert_eq!(
+            gas_used(&engine, "$.list.map(item.add(1))"),
+            1 + 5 * (1 + 1) + 5 * (1 + 1 + 1)
+        );
+        // host functions are charged their declared cost, or the default one
+        assert_eq!(gas_used(&engine, "limit()"), 5);
+        assert_eq!(gas_used(&engine, "fee()"), 1);
+
+        let limited = DewSchemaLanguageEngine::from_value(engine.root().clone(), HashMap::new())
+            .with_limits(DslLimits {
+                max_gas: 10,
+                ..DslLimits::default()
+            });
+        assert!(limited.evaluate("$.list.length() > 0".to_string()).is_ok());
+
+        let evaluation = limited.evaluate_metered("$.list.map(item.add(1))".to_string());
+        let error = evaluation.result.unwrap_err();
+        assert_eq!(error.kind, DslErrorKind::OutOfGas);
+        assert_eq!(error.message, "Evaluation ran out of gas, the budget is 10");
+        assert_eq!(evaluation.gas_used, 10);
+
+        // huge magnitudes cost as much as the digits they span
+        let error = DewSchemaLanguageEngine::from_value(json!({}), HashMap::new())
+            .with_limits(DslLimits {
+                max_gas: 100,
+                ..DslLimits::unlimited()
+            })
+            .evaluate("1e9000 + 1 > 0".to_string())
+            .unwrap_err();
+        assert_eq!(error.kind, DslErrorKind::OutOfGas);
+
+        // compile errors use no gas
+        assert_eq!(gas_used(&limited, "$.list.map("), 0);
+    }
+
+    #[test]
+    fn test_host_context() {
+        struct SpendingDatabase {
+            spent: HashMap<String, u64>,
+            queries: usize,
+        }
+
+        let mut context_host_functions: HashMap<String, DslContextFunction> = HashMap::new();
+        context_host_functions.insert(
+            "daily_spent".into(),
+            Box::new(|_, _, context| {
+                let account_id = context.root()["account_id"]
+                    .as_str()
+                    .ok_or("the root has no account_id")?
+                    .to_string();
+                let database = context
+                    .data::<SpendingDatabase>()
+                    .ok_or("no spending database")?;
+                database.queries += 1;
+                let spent = database.spent.get(&account_id).copied().unwrap_or(0);
+
+                Ok(DewSchemaLanguageResult::Number(spent.into()))
+            }),
+        );
+        context_host_functions.insert(
+            "within_allowance".into(),
+            Box::new(|params, callee, context| {
+                let allowance = context
+                    .binding("allowance")
+                    .cloned()
+                    .unwrap_or(DewSchemaLanguageResult::Null);
+                let total = context.call_method("add", callee, params)?;
+
+                context.call_method("lte", Some(&total), vec![allowance])
+            }),
+        );
+        context_host_functions.insert(
+            "binding_names".into(),
+            Box::new(|_, _, context| {
+                let names = context
+                    .bindings()
+                    .into_iter()
+                    .map(|(name, _)| Value::String(name.to_string()))
+                    .collect();
+
+                Ok(DewSchemaLanguageResult::Value(Value::Array(names)))
+            }),
+        );
+        context_host_functions.insert(
+            "call_builtin".into(),
+            Box::new(|params, callee, context| match params.first() {
+                Some(DewSchemaLanguageResult::String(method_name)) => {
+                    context.call_method(method_name, callee, Vec::new())
+                }
+                _ => Err("expected a method name".into()),
+            }),
+        );
+
+        let engine = DewSchemaLanguageEngine::from_value(
+            json!({ "account_id": "alice.near", "amount": 30 }),
+            HashMap::new(),
+        )
+        .with_context_host_functions(context_host_functions);
+        let mut database = SpendingDatabase {
+            spent: HashMap::from([("alice.near".to_string(), 60)]),
+            queries: 0,
+        };
+
+        assert_eq!(
+            engine
+                .evaluate_with_data(
+                    "let allowance = 100 in daily_spent().within_allowance($.amount)".to_string(),
+                    &mut database,
+                )
+                .unwrap(),
+            DewSchemaLanguageResult::Boolean(true)
+        );
+        assert_eq!(
+            engine
+                .evaluate_with_data(
+                    "let allowance = 80 in [1, 2].every(daily_spent().within_allowance($.amount))"
+                        .to_string(),
+                    &mut database,
+                )
+                .unwrap(),
+            DewSchemaLanguageResult::Boolean(false)
+        );
+        // the database was lent to every call
+        assert_eq!(database.queries, 2);
+
+        let error = engine.evaluate("daily_spent()".to_string()).unwrap_err();
+        assert_eq!(error.kind, DslErrorKind::Host);
+        assert_eq!(error.message, "no spending database");
+
+        assert_eq!(
+            engine
+                .evaluate("let a = 1 in [2].map(a => binding_names())".to_string())
+                .unwrap(),
+            DewSchemaLanguageResult::Value(json!([["a", "index"]]))
+        );
+
+        // built-ins that evaluate their own arguments cannot be called with values
+        let registry = MethodRegistry::global();
+        let lazy_methods = registry
+            .names()
+            .filter(|name| matches!(registry.get(name), Some(BuiltinMethod::Lazy(_))));
+        for method_name in lazy_methods {
+            let error = engine
+                .evaluate(format!("[true].call_builtin({:?})", method_name))
+                .unwrap_err();
+            assert_eq!(error.kind, DslErrorKind::Evaluation, "{}", method_name);
+            assert_eq!(
+                error.message,
+                format!(
+                    "'{}' method evaluates its own arguments and cannot be called from a host function",
+                    method_name
+                )
+            );
+        }
+        assert_eq!(
+            engine
+                .evaluate("[true].call_builtin(\"length\")".to_string())
+                .unwrap(),
+            number("1")
+        );
     }
 }
@@ -0,0 +1,270 @@
+use std::fmt;
+
+/// A location in the expression source: a byte offset plus the 1-based line and column
+/// (counted in characters) it falls on.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub struct Position {
+    pub offset: usize,
+    pub line: usize,
+    pub column: usize,
+}
+
+impl Position {
+    pub fn new(offset: usize, line: usize, column: usize) -> Self {
+        Position {
+            offset,
+            line,
+            column,
+        }
+    }
+}
+
+/// A range of the expression source, `start` inclusive and `end` exclusive.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub struct Span {
+    pub start: Position,
+    pub end: Position,
+}
+
+impl Span {
+    pub fn new(start: Position, end: Position) -> Self {
+        Span { start, end }
+    }
+
+    /// Smallest span covering both `self` and `other`.
+    pub fn merge(self, other: Span) -> Span {
+        Span {
+            start: if other.start.offset < self.start.offset {
+                other.start
+            } else {
+                self.start
+            },
+            end: if other.end.offset > self.end.offset {
+                other.end
+            } else {
+                self.end
+            },
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum DslErrorKind {
+    /// The root object handed to the engine is not valid JSON.
+    InvalidRoot,
+    /// A schema document is malformed.
+    InvalidSchema,
+    /// A policy has a rule without a name or two rules with the same name.
+    InvalidPolicy,
+    /// The source contains a character or literal the tokenizer does not understand.
+    Lex,
+    /// The tokens do not form a valid expression.
+    Parse,
+    /// A method is neither built in nor registered as a host function.
+    UnknownMethod,
+    /// A bare identifier is not `$`, `true`, `false` or a bound variable.
+    UnknownIdentifier,
+    /// A method was called with the wrong number of arguments.
+    Arity,
+    /// A method was called on, or with, a value of the wrong type.
+    TypeMismatch,
+    /// An `assert_*` method found its condition to be false.
+    AssertionFailed,
+    /// A host function returned an error.
+    Host,
+    /// Any other failure while evaluating, e.g. division by zero or an index out of bounds.
+    Evaluation,
+    /// The expression is longer than the engine's `max_expression_length`.
+    ExpressionTooLong,
+    /// The expression nests deeper than the engine's `max_nesting_depth`.
+    NestingTooDeep,
+    /// Evaluation took more than the engine's `max_evaluation_steps`.
+    StepLimitExceeded,
+    /// An array or object produced during evaluation has more than the engine's
+    /// `max_collection_size` elements.
+    CollectionTooLarge,
+    /// A number spans more digits than the engine's `max_number_digits`.
+    NumberTooLarge,
+    /// Evaluation used more gas than the engine's `max_gas`.
+    OutOfGas,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct DslError {
+    pub kind: DslErrorKind,
+    pub message: String,
+    /// Where in the source the error happened, when known.
+    pub span: Option<Span>,
+    /// The method chain being evaluated when the error happened, e.g. `$.args.amount.gte(…)`.
+    pub path: Option<String>,
+}
+
+impl DslError {
+    pub fn new(kind: DslErrorKind, message: impl Into<String>) -> Self {
+        DslError {
+            kind,
+            message: message.into(),
+            span: None,
+            path: None,
+        }
+    }
+
+    pub fn invalid_root(message: impl Into<String>) -> Self {
+        Self::new(DslErrorKind::InvalidRoot, message)
+    }
+
+    pub fn invalid_schema(message: impl Into<String>) -> Self {
+        Self::new(DslErrorKind::InvalidSchema, message)
+    }
+
+    pub fn invalid_policy(message: impl Into<String>) -> Self {
+        Self::new(DslErrorKind::InvalidPolicy, message)
+    }
+
+    pub fn lex(message: impl Into<String>, span: Span) -> Self {
+        Self::new(DslErrorKind::Lex, message).with_span(span)
+    }
+
+    pub fn parse(message: impl Into<String>) -> Self {
+        Self::new(DslErrorKind::Parse, message)
+    }
+
+    pub fn unknown_method(method_name: &str) -> Self {
+        Self::new(
+            DslErrorKind::UnknownMethod,
+            format!("Unknown method: {}", method_name),
+        )
+    }
+
+    pub fn unknown_identifier(identifier: &str) -> Self {
+        Self::new(
+            DslErrorKind::UnknownIdentifier,
+            format!("Unknown identifier: {}", identifier),
+        )
+    }
+
+    pub fn arity(message: impl Into<String>) -> Self {
+        Self::new(DslErrorKind::Arity, message)
+    }
+
+    pub fn type_mismatch(message: impl Into<String>) -> Self {
+        Self::new(DslErrorKind::TypeMismatch, message)
+    }
+
+    pub fn assertion_failed(message: impl Into<String>) -> Self {
+        Self::new(DslErrorKind::AssertionFailed, message)
+    }
+
+    pub fn host(message: impl Into<String>) -> Self {
+        Self::new(DslErrorKind::Host, message)
+    }
+
+    pub fn evaluation(message: impl Into<String>) -> Self {
+        Self::new(DslErrorKind::Evaluation, message)
+    }
+
+    pub fn expression_too_long(length: usize, max_length: usize) -> Self {
+        Self::new(
+            DslErrorKind::ExpressionTooLong,
+            format!(
+                "Expression is {} bytes long, the limit is {}",
+                length, max_length
+            ),
+        )
+    }
+
+    pub fn nesting_too_deep(max_depth: usize) -> Self {
+        Self::new(
+            DslErrorKind::NestingTooDeep,
+            format!("Expression nests deeper than {} levels", max_depth),
+        )
+    }
+
+    pub fn step_limit_exceeded(max_steps: usize) -> Self {
+        Self::new(
+            DslErrorKind::StepLimitExceeded,
+            format!("Evaluation exceeded the limit of {} steps", max_steps),
+        )
+    }
+
+    pub fn collection_too_large(size: usize, max_size: usize) -> Self {
+        Self::new(
+            DslErrorKind::CollectionTooLarge,
+            format!(
+                "Collection of {} elements exceeds the limit of {}",
+                size, max_size
+            ),
+        )
+    }
+
+    pub fn number_too_large(digits: u64, max_digits: u64) -> Self {
+        Self::new(
+            DslErrorKind::NumberTooLarge,
+            format!(
+                "Number spans {} digits, the limit is {}",
+                digits, max_digits
+            ),
+        )
+    }
+
+    pub fn out_of_gas(max_gas: u64) -> Self {
+        Self::new(
+            DslErrorKind::OutOfGas,
+            format!("Evaluation ran out of gas, the budget is {}", max_gas),
+        )
+    }
+
+    pub fn with_span(mut self, span: Span) -> Self {
+        self.span = Some(span);
+        self
+    }
+
+    /// Sets the span unless a more precise one was already recorded deeper in the tree.
+    pub fn or_span(mut self, span: Span) -> Self {
+        self.span.get_or_insert(span);
+        self
+    }
+
+    /// Sets the path unless a more precise one was already recorded deeper in the tree.
+    pub fn or_path(mut self, path: impl FnOnce() -> String) -> Self {
+        if self.path.is_none() {
+            self.path = Some(path());
+        }
+        self
+    }
+}
+
+impl fmt::Display for DslError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.message)?;
+
+        if let Some(path) = &self.path {
+            write!(f, " (in {})", path)?;
+        }
+
+        if let Some(span) = &self.span {
+            write!(
+                f,
+                " at line {}, column {}",
+                span.start.line, span.start.column
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+impl std::error::Error for DslError {}
+
+/// Errors raised with a plain message, typically by host functions, are host errors.
+impl From<String> for DslError {
+    fn from(message: String) -> Self {
+        DslError::host(message)
+    }
+}
+
+impl From<&str> for DslError {
+    fn from(message: &str) -> Self {
+        DslError::host(message)
+    }
+}
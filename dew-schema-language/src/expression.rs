@@ -1,7 +1,21 @@
-use crate::tokenizer::DewSchemaLanguageToken;
+use crate::{
+    error::{DslError, Position, Span},
+    limits::DslLimits,
+    tokenizer::{DewSchemaLanguageToken, SpannedToken},
+};
+
+/// A node of the parsed expression together with the part of the source it was parsed from.
+///
+/// Equality compares the tree structure only and ignores spans, so `a >= 5` equals the
+/// `a.gte(5)` it desugars to.
+#[derive(Debug, Clone, Eq)]
+pub struct DewSchemaLanguageExpression {
+    pub kind: DewSchemaLanguageExpressionKind,
+    pub span: Span,
+}
 
 #[derive(Debug, PartialEq, Eq, Clone)]
-pub enum DewSchemaLanguageExpression {
+pub enum DewSchemaLanguageExpressionKind {
     Number(String),
     StringLiteral(String),
     Identifier(String),
@@ -10,35 +24,257 @@ pub enum DewSchemaLanguageExpression {
         args: Vec<DewSchemaLanguageExpression>,
     },
     Chain(Vec<DewSchemaLanguageExpression>),
+    /// `[key]` after a callee in a chain: an array element by integer index (negative indices
+    /// count from the end) or an object property by string key.
+    Index(Box<DewSchemaLanguageExpression>),
+    /// A chain element reached through `?.`: when its callee is `null` or `undefined`, the
+    /// rest of the chain is skipped and the chain evaluates to `undefined`.
+    Optional(Box<DewSchemaLanguageExpression>),
+    /// `[a, b, ...]`
+    Array(Vec<DewSchemaLanguageExpression>),
+    /// `{ key: value, ... }`, with keys in source order.
+    Object(Vec<(String, DewSchemaLanguageExpression)>),
+    /// `let name = value in body`: evaluates `body` with `name` bound to the value.
+    Let {
+        name: String,
+        value: Box<DewSchemaLanguageExpression>,
+        body: Box<DewSchemaLanguageExpression>,
+    },
+    /// `item => body` or `(item, index) => body`, passed to iteration methods to name the
+    /// element, and optionally its index, bound while evaluating `body`.
+    Lambda {
+        parameters: Vec<String>,
+        body: Box<DewSchemaLanguageExpression>,
+    },
+}
+
+impl PartialEq for DewSchemaLanguageExpression {
+    fn eq(&self, other: &Self) -> bool {
+        self.kind == other.kind
+    }
+}
+
+impl DewSchemaLanguageExpression {
+    pub fn new(kind: DewSchemaLanguageExpressionKind, span: Span) -> Self {
+        DewSchemaLanguageExpression { kind, span }
+    }
+
+    /// Renders a chain as a readable path for error messages, e.g. `$.args.amount.gte(…)`.
+    pub fn path(chain: &[DewSchemaLanguageExpression]) -> String {
+        let mut path = String::new();
+
+        for expression in chain {
+            let segment = match &expression.kind {
+                DewSchemaLanguageExpressionKind::Number(n) => n.clone(),
+                DewSchemaLanguageExpressionKind::StringLiteral(s) => format!("{:?}", s),
+                DewSchemaLanguageExpressionKind::Identifier(identifier) => identifier.clone(),
+                DewSchemaLanguageExpressionKind::Call { method_name, args } if args.is_empty() => {
+                    format!("{}()", method_name)
+                }
+                DewSchemaLanguageExpressionKind::Call { method_name, .. } => {
+                    format!("{}(…)", method_name)
+                }
+                DewSchemaLanguageExpressionKind::Chain(chain) => Self::path(chain),
+                DewSchemaLanguageExpressionKind::Index(key) => {
+                    // indexing attaches to its callee without a dot
+                    path.push_str(&format!("[{}]", Self::path(std::slice::from_ref(key))));
+                    continue;
+                }
+                DewSchemaLanguageExpressionKind::Optional(element) => {
+                    path.push_str("?.");
+                    path.push_str(&Self::path(std::slice::from_ref(element)));
+                    continue;
+                }
+                DewSchemaLanguageExpressionKind::Array(_) => "[…]".to_string(),
+                DewSchemaLanguageExpressionKind::Object(_) => "{…}".to_string(),
+                DewSchemaLanguageExpressionKind::Let { name, .. } => {
+                    format!("let {} = … in …", name)
+                }
+                DewSchemaLanguageExpressionKind::Lambda { parameters, .. } => {
+                    format!("({}) => …", parameters.join(", "))
+                }
+            };
+
+            if !path.is_empty() {
+                path.push('.');
+            }
+            path.push_str(&segment);
+        }
+
+        path
+    }
+}
+
+/// Names that always refer to the root object, a literal or a keyword and cannot be rebound.
+const RESERVED_NAMES: [&str; 5] = ["$", "true", "false", "let", "in"];
+
+/// The element iteration methods bind, which would hide a `let` of the same name inside them.
+const ITERATION_NAME: &str = "item";
+
+/// Binding power of prefix operators, higher than every infix operator.
+const PREFIX_BINDING_POWER: u8 = 13;
+
+/// Maps an infix operator token to the method it desugars to and its left and right
+/// binding powers. Every operator is left-associative.
+fn infix_binding_power(token: &DewSchemaLanguageToken) -> Option<(&'static str, u8, u8)> {
+    let operator = match token {
+        DewSchemaLanguageToken::Or => ("or", 1, 2),
+        DewSchemaLanguageToken::And => ("and", 3, 4),
+        DewSchemaLanguageToken::Equal => ("equal", 5, 6),
+        DewSchemaLanguageToken::NotEqual => ("not_equal", 5, 6),
+        DewSchemaLanguageToken::LessThan => ("lt", 7, 8),
+        DewSchemaLanguageToken::LessThanOrEqual => ("lte", 7, 8),
+        DewSchemaLanguageToken::GreaterThan => ("gt", 7, 8),
+        DewSchemaLanguageToken::GreaterThanOrEqual => ("gte", 7, 8),
+        DewSchemaLanguageToken::Plus => ("add", 9, 10),
+        DewSchemaLanguageToken::Minus => ("subtract", 9, 10),
+        DewSchemaLanguageToken::Asterisk => ("multiply", 11, 12),
+        DewSchemaLanguageToken::Slash => ("divide", 11, 12),
+        DewSchemaLanguageToken::Percent => ("modulo", 11, 12),
+        _ => return None,
+    };
+
+    Some(operator)
+}
+
+/// Negates a number literal, so `- -5` reads back as `5`.
+fn negate_literal(n: &str) -> String {
+    match n.strip_prefix('-') {
+        Some(positive) => positive.to_string(),
+        None => format!("-{}", n),
+    }
+}
+
+/// Appends a method call covering `call_span` to `callee`, so `a.b + c` becomes `a.b.add(c)`.
+fn method_call(
+    callee: DewSchemaLanguageExpression,
+    method_name: &str,
+    args: Vec<DewSchemaLanguageExpression>,
+    call_span: Span,
+) -> DewSchemaLanguageExpression {
+    let span = callee.span.merge(call_span);
+    let call = DewSchemaLanguageExpression::new(
+        DewSchemaLanguageExpressionKind::Call {
+            method_name: method_name.to_string(),
+            args,
+        },
+        call_span,
+    );
+
+    match callee.kind {
+        // an optional chain stops as a whole, so the operator must stay outside of it
+        DewSchemaLanguageExpressionKind::Chain(mut chain)
+            if !chain.iter().any(|element| {
+                matches!(element.kind, DewSchemaLanguageExpressionKind::Optional(_))
+            }) =>
+        {
+            chain.push(call);
+            DewSchemaLanguageExpression::new(DewSchemaLanguageExpressionKind::Chain(chain), span)
+        }
+        _ => DewSchemaLanguageExpression::new(
+            DewSchemaLanguageExpressionKind::Chain(vec![callee, call]),
+            span,
+        ),
+    }
 }
 
 pub struct DewSchemaLanguageParser {
-    tokens: Vec<DewSchemaLanguageToken>,
+    tokens: Vec<SpannedToken>,
     position: usize,
+    /// How many expressions are being parsed around the current token.
+    depth: usize,
+    max_depth: usize,
 }
 
 #[allow(dead_code)]
 impl DewSchemaLanguageParser {
-    pub fn consume(input: &str) -> Result<DewSchemaLanguageExpression, String> {
-        let tokens = crate::tokenizer::tokenize(input)?;
+    /// Parses a whole expression, nesting at most [`DslLimits::DEFAULT_MAX_NESTING_DEPTH`]
+    /// levels deep.
+    pub fn consume(input: &str) -> Result<DewSchemaLanguageExpression, DslError> {
+        Self::consume_with_max_depth(input, DslLimits::DEFAULT_MAX_NESTING_DEPTH)
+    }
+
+    /// Like [`Self::consume`], failing with [`DslErrorKind::NestingTooDeep`] when
+    /// sub-expressions nest more than `max_depth` levels deep.
+    ///
+    /// [`DslErrorKind::NestingTooDeep`]: crate::error::DslErrorKind::NestingTooDeep
+    pub fn consume_with_max_depth(
+        input: &str,
+        max_depth: usize,
+    ) -> Result<DewSchemaLanguageExpression, DslError> {
+        Self::consume_with_limits(
+            input,
+            &DslLimits {
+                max_nesting_depth: max_depth,
+                ..DslLimits::default()
+            },
+        )
+    }
+
+    /// Like [`Self::consume`], applying the nesting depth and number size of `limits`.
+    pub fn consume_with_limits(
+        input: &str,
+        limits: &DslLimits,
+    ) -> Result<DewSchemaLanguageExpression, DslError> {
+        let tokens =
+            crate::tokenizer::tokenize_with_max_number_digits(input, limits.max_number_digits)?;
         let mut parser = Self::new(tokens);
-        parser.parse()
+        parser.max_depth = limits.max_nesting_depth;
+        let expression = parser.parse()?;
+
+        match parser.peek() {
+            Some(token) => {
+                Err(parser.error_at(format!("Unexpected token: {:?}", token), parser.position))
+            }
+            None => Ok(expression),
+        }
     }
 
-    pub fn new(tokens: Vec<DewSchemaLanguageToken>) -> Self {
+    pub fn new(tokens: Vec<SpannedToken>) -> Self {
         DewSchemaLanguageParser {
             tokens,
             position: 0,
+            depth: 0,
+            max_depth: DslLimits::DEFAULT_MAX_NESTING_DEPTH,
+        }
+    }
+
+    /// Span of the token at `index`, or an empty span at the end of input past the last token.
+    fn span_at(&self, index: usize) -> Span {
+        match (self.tokens.get(index), self.tokens.last()) {
+            (Some(token), _) => token.span,
+            (None, Some(last)) => Span::new(last.span.end, last.span.end),
+            (None, None) => {
+                let start = Position::new(0, 1, 1);
+                Span::new(start, start)
+            }
         }
     }
 
+    /// Span of the most recently consumed token.
+    fn previous_span(&self) -> Span {
+        self.span_at(self.position.saturating_sub(1))
+    }
+
+    /// Parse error pointing at the token at `index`, or at the end of input past the last token.
+    fn error_at(&self, message: String, index: usize) -> DslError {
+        DslError::parse(message).with_span(self.span_at(index))
+    }
+
     fn peek(&self) -> Option<&DewSchemaLanguageToken> {
-        self.tokens.get(self.position)
+        self.peek_at(0)
+    }
+
+    /// Looks `offset` tokens past the current one without consuming anything.
+    fn peek_at(&self, offset: usize) -> Option<&DewSchemaLanguageToken> {
+        self.tokens
+            .get(self.position + offset)
+            .map(|spanned| &spanned.token)
     }
 
     fn next(&mut self) -> Option<DewSchemaLanguageToken> {
         if self.position < self.tokens.len() {
-            let token = self.tokens[self.position].clone();
+            let token = self.tokens[self.position].token.clone();
             self.position += 1;
             Some(token)
         } else {
@@ -46,118 +282,479 @@ impl DewSchemaLanguageParser {
         }
     }
 
-    fn expect(&mut self, expected_token: DewSchemaLanguageToken) -> Result<(), String> {
+    fn expect(&mut self, expected_token: DewSchemaLanguageToken) -> Result<(), DslError> {
         match self.next() {
             Some(token) if token == expected_token => Ok(()),
-            Some(token) => Err(format!("Expected {:?}, got {:?}", expected_token, token)),
-            None => Err(format!("Expected {:?}, but got EOF", expected_token)),
+            Some(token) => Err(self.error_at(
+                format!("Expected {:?}, got {:?}", expected_token, token),
+                self.position - 1,
+            )),
+            None => Err(self.error_at(
+                format!("Expected {:?}, but got EOF", expected_token),
+                self.position,
+            )),
         }
     }
 
     /// Entry point
-    pub fn parse(&mut self) -> Result<DewSchemaLanguageExpression, String> {
+    pub fn parse(&mut self) -> Result<DewSchemaLanguageExpression, DslError> {
+        self.parse_expression(0)
+    }
+
+    /// Parses a sub-expression one level deeper than the current one. Every recursion of
+    /// the parser goes through here, which bounds it by `max_depth`.
+    fn parse_expression(
+        &mut self,
+        min_binding_power: u8,
+    ) -> Result<DewSchemaLanguageExpression, DslError> {
+        if self.depth >= self.max_depth {
+            return Err(
+                DslError::nesting_too_deep(self.max_depth).with_span(self.span_at(self.position))
+            );
+        }
+
+        self.depth += 1;
+        let expression = self.parse_operators(min_binding_power);
+        self.depth -= 1;
+
+        expression
+    }
+
+    /// Pratt parser for prefix and infix operators. Operators desugar to method calls
+    /// on their left operand, e.g. `a >= 5 && b` becomes `a.gte(5).and(b)`.
+    fn parse_operators(
+        &mut self,
+        min_binding_power: u8,
+    ) -> Result<DewSchemaLanguageExpression, DslError> {
+        let mut lhs = match self.peek() {
+            Some(DewSchemaLanguageToken::Not) => {
+                self.next(); // consume '!'
+                let operator_span = self.previous_span();
+                let operand = self.parse_expression(PREFIX_BINDING_POWER)?;
+                method_call(operand, "not", vec![], operator_span)
+            }
+            Some(DewSchemaLanguageToken::Minus) => {
+                self.next(); // consume '-'
+                let operator_span = self.previous_span();
+                let operand = self.parse_expression(PREFIX_BINDING_POWER)?;
+
+                match operand.kind {
+                    // a negated literal stays a literal; `-5.add(1)` negates the whole chain
+                    DewSchemaLanguageExpressionKind::Number(n) => DewSchemaLanguageExpression::new(
+                        DewSchemaLanguageExpressionKind::Number(negate_literal(&n)),
+                        operator_span.merge(operand.span),
+                    ),
+                    _ => method_call(operand, "negate", vec![], operator_span),
+                }
+            }
+            Some(DewSchemaLanguageToken::Identifier(keyword))
+                if keyword == "let" && self.peek_at(2) == Some(&DewSchemaLanguageToken::Assign) =>
+            {
+                self.parse_let()?
+            }
+            Some(DewSchemaLanguageToken::Identifier(_))
+                if self.peek_at(1) == Some(&DewSchemaLanguageToken::Arrow) =>
+            {
+                self.parse_lambda()?
+            }
+            Some(DewSchemaLanguageToken::LeftParenthesis) if self.is_lambda_parameter_list() => {
+                self.parse_lambda()?
+            }
+            _ => self.parse_chain()?,
+        };
+
+        while let Some((method_name, left_binding_power, right_binding_power)) =
+            self.peek().and_then(infix_binding_power)
+        {
+            if left_binding_power < min_binding_power {
+                break;
+            }
+
+            self.next(); // consume operator
+            let operator_span = self.previous_span();
+            let rhs = self.parse_expression(right_binding_power)?;
+            let call_span = operator_span.merge(rhs.span);
+            lhs = method_call(lhs, method_name, vec![rhs], call_span);
+        }
+
+        Ok(lhs)
+    }
+
+    /// Parses `let name = value in body`. The body extends as far to the right as possible.
+    fn parse_let(&mut self) -> Result<DewSchemaLanguageExpression, DslError> {
+        self.next(); // consume 'let'
+        let start = self.previous_span();
+
+        let name = match self.next() {
+            Some(DewSchemaLanguageToken::Identifier(name)) => name,
+            other => {
+                return Err(self.error_at(
+                    format!("Expected a name after 'let', got {:?}", other),
+                    self.position - 1,
+                ))
+            }
+        };
+
+        if RESERVED_NAMES.contains(&name.as_str()) || name == ITERATION_NAME {
+            return Err(self.error_at(
+                format!("Cannot bind reserved name '{}'", name),
+                self.position - 1,
+            ));
+        }
+
+        self.expect(DewSchemaLanguageToken::Assign)?;
+        let value = self.parse()?;
+
+        match self.next() {
+            Some(DewSchemaLanguageToken::Identifier(keyword)) if keyword == "in" => {}
+            other => {
+                let index = match other {
+                    Some(_) => self.position - 1,
+                    None => self.position,
+                };
+
+                return Err(self.error_at(
+                    format!(
+                        "Expected 'in' after the value of '{}', got {:?}",
+                        name, other
+                    ),
+                    index,
+                ));
+            }
+        }
+
+        let body = self.parse()?;
+        let span = start.merge(body.span);
+
+        Ok(DewSchemaLanguageExpression::new(
+            DewSchemaLanguageExpressionKind::Let {
+                name,
+                value: Box::new(value),
+                body: Box::new(body),
+            },
+            span,
+        ))
+    }
+
+    /// Whether the tokens ahead are `(name, ...) =>` with one or more names, rather than a
+    /// parenthesized group.
+    fn is_lambda_parameter_list(&self) -> bool {
+        if self.peek() != Some(&DewSchemaLanguageToken::LeftParenthesis) {
+            return false;
+        }
+
+        let mut offset = 1;
+        loop {
+            match (self.peek_at(offset), self.peek_at(offset + 1)) {
+                (
+                    Some(DewSchemaLanguageToken::Identifier(_)),
+                    Some(DewSchemaLanguageToken::Comma),
+                ) => offset += 2,
+                (
+                    Some(DewSchemaLanguageToken::Identifier(_)),
+                    Some(DewSchemaLanguageToken::RightParenthesis),
+                ) => return self.peek_at(offset + 2) == Some(&DewSchemaLanguageToken::Arrow),
+                _ => return false,
+            }
+        }
+    }
+
+    /// Parses `name => body`, `(name) => body` or `(name, name) => body`. Like `let`, the
+    /// body extends as far to the right as possible.
+    fn parse_lambda(&mut self) -> Result<DewSchemaLanguageExpression, DslError> {
+        let start = self.span_at(self.position);
+        let mut parameters = Vec::new();
+
+        while let Some(token) = self.next() {
+            match token {
+                DewSchemaLanguageToken::Identifier(name) => {
+                    if RESERVED_NAMES.contains(&name.as_str()) {
+                        return Err(self.error_at(
+                            format!("Cannot bind reserved name '{}'", name),
+                            self.position - 1,
+                        ));
+                    }
+                    // iteration methods bind an element and its position, nothing else
+                    if parameters.len() == 2 {
+                        return Err(self.error_at(
+                            "A lambda takes at most two parameters, the element and its index"
+                                .to_string(),
+                            self.position - 1,
+                        ));
+                    }
+                    if parameters.contains(&name) {
+                        return Err(self.error_at(
+                            format!("Duplicate lambda parameter '{}'", name),
+                            self.position - 1,
+                        ));
+                    }
+                    parameters.push(name);
+                }
+                DewSchemaLanguageToken::Arrow => break,
+                // the parameter list shape was checked before parsing
+                _ => {}
+            }
+        }
+
+        let body = self.parse()?;
+        let span = start.merge(body.span);
+
+        Ok(DewSchemaLanguageExpression::new(
+            DewSchemaLanguageExpressionKind::Lambda {
+                parameters,
+                body: Box::new(body),
+            },
+            span,
+        ))
+    }
+
+    fn parse_chain(&mut self) -> Result<DewSchemaLanguageExpression, DslError> {
         let mut chain = Vec::new();
         chain.push(self.parse_atom()?);
 
-        while let Some(DewSchemaLanguageToken::Dot) = self.peek() {
-            self.next(); // consume dot
-            chain.push(self.parse_atom()?);
+        loop {
+            match self.peek() {
+                Some(DewSchemaLanguageToken::Dot) => {
+                    self.next(); // consume dot
+                    chain.push(self.parse_atom()?);
+                }
+                Some(DewSchemaLanguageToken::LeftBracket) => {
+                    chain.push(self.parse_index()?);
+                }
+                Some(DewSchemaLanguageToken::QuestionDot) => {
+                    self.next(); // consume '?.'
+                    let start = self.previous_span();
+                    let element = match self.peek() {
+                        Some(DewSchemaLanguageToken::LeftBracket) => self.parse_index()?,
+                        _ => self.parse_atom()?,
+                    };
+                    let span = start.merge(element.span);
+
+                    chain.push(DewSchemaLanguageExpression::new(
+                        DewSchemaLanguageExpressionKind::Optional(Box::new(element)),
+                        span,
+                    ));
+                }
+                _ => break,
+            }
         }
 
         if chain.len() == 1 {
             Ok(chain.remove(0))
         } else {
-            Ok(DewSchemaLanguageExpression::Chain(chain))
+            let span = chain[0].span.merge(chain[chain.len() - 1].span);
+            Ok(DewSchemaLanguageExpression::new(
+                DewSchemaLanguageExpressionKind::Chain(chain),
+                span,
+            ))
         }
     }
 
-    fn parse_atom(&mut self) -> Result<DewSchemaLanguageExpression, String> {
-        match self.next() {
-            Some(DewSchemaLanguageToken::Number(n)) => Ok(DewSchemaLanguageExpression::Number(n)),
+    /// Parses comma separated arguments up to and including the closing parenthesis.
+    fn parse_args(&mut self) -> Result<Vec<DewSchemaLanguageExpression>, DslError> {
+        let mut args = Vec::new();
+
+        if let Some(DewSchemaLanguageToken::RightParenthesis) = self.peek() {
+            self.next(); // consume ')'
+            return Ok(args);
+        }
+
+        loop {
+            args.push(self.parse()?);
+            match self.peek() {
+                Some(DewSchemaLanguageToken::Comma) => {
+                    self.next(); // consume ','
+                }
+                Some(DewSchemaLanguageToken::RightParenthesis) => {
+                    self.next(); // consume ')'
+                    return Ok(args);
+                }
+                other => {
+                    return Err(self.error_at(
+                        format!("Unexpected token in args: {:?}", other),
+                        self.position,
+                    ))
+                }
+            }
+        }
+    }
+
+    /// Parses `[key]` following a callee.
+    fn parse_index(&mut self) -> Result<DewSchemaLanguageExpression, DslError> {
+        self.expect(DewSchemaLanguageToken::LeftBracket)?;
+        let start = self.previous_span();
+        let key = self.parse()?;
+        self.expect(DewSchemaLanguageToken::RightBracket)?;
+
+        Ok(DewSchemaLanguageExpression::new(
+            DewSchemaLanguageExpressionKind::Index(Box::new(key)),
+            start.merge(self.previous_span()),
+        ))
+    }
+
+    /// Parses comma separated items up to and including `closing`, allowing a trailing comma.
+    fn parse_sequence(
+        &mut self,
+        closing: DewSchemaLanguageToken,
+        description: &str,
+        mut parse_item: impl FnMut(&mut Self) -> Result<(), DslError>,
+    ) -> Result<(), DslError> {
+        loop {
+            if self.peek() == Some(&closing) {
+                self.next(); // consume closing token
+                return Ok(());
+            }
+
+            parse_item(self)?;
+
+            match self.peek() {
+                Some(DewSchemaLanguageToken::Comma) => {
+                    self.next(); // consume ','
+                }
+                Some(token) if *token == closing => {}
+                other => {
+                    return Err(self.error_at(
+                        format!("Unexpected token in {} literal: {:?}", description, other),
+                        self.position,
+                    ))
+                }
+            }
+        }
+    }
+
+    fn parse_atom(&mut self) -> Result<DewSchemaLanguageExpression, DslError> {
+        let token = self.next();
+        let start = self.previous_span();
+
+        let kind = match token {
+            Some(DewSchemaLanguageToken::Number(n)) => DewSchemaLanguageExpressionKind::Number(n),
             Some(DewSchemaLanguageToken::StringLiteral(s)) => {
-                Ok(DewSchemaLanguageExpression::StringLiteral(s))
+                DewSchemaLanguageExpressionKind::StringLiteral(s)
             }
             Some(DewSchemaLanguageToken::LeftParenthesis) => {
-                let mut args = Vec::new();
+                DewSchemaLanguageExpressionKind::Call {
+                    method_name: "".to_string(),
+                    args: self.parse_args()?,
+                }
+            }
+            Some(DewSchemaLanguageToken::LeftBracket) => {
+                let mut elements = Vec::new();
 
-                if let Some(DewSchemaLanguageToken::RightParenthesis) = self.peek() {
-                    self.next(); // consume ')'
-                } else {
-                    loop {
-                        args.push(self.parse()?);
-                        match self.peek() {
-                            Some(DewSchemaLanguageToken::Comma) => {
-                                self.next(); // consume ','
-                            }
-                            Some(DewSchemaLanguageToken::RightParenthesis) => {
-                                self.next(); // consume ')'
-                                break;
-                            }
-                            other => return Err(format!("Unexpected token in args: {:?}", other)),
+                self.parse_sequence(DewSchemaLanguageToken::RightBracket, "array", |parser| {
+                    elements.push(parser.parse()?);
+                    Ok(())
+                })?;
+
+                DewSchemaLanguageExpressionKind::Array(elements)
+            }
+            Some(DewSchemaLanguageToken::LeftBrace) => {
+                let mut entries: Vec<(String, DewSchemaLanguageExpression)> = Vec::new();
+
+                self.parse_sequence(DewSchemaLanguageToken::RightBrace, "object", |parser| {
+                    let key = match parser.next() {
+                        Some(DewSchemaLanguageToken::Identifier(key))
+                        | Some(DewSchemaLanguageToken::StringLiteral(key)) => key,
+                        other => {
+                            return Err(parser.error_at(
+                                format!("Expected an object key, got {:?}", other),
+                                parser.position.saturating_sub(1),
+                            ))
                         }
+                    };
+
+                    if entries.iter().any(|(existing, _)| *existing == key) {
+                        return Err(parser.error_at(
+                            format!("Duplicate object key '{}'", key),
+                            parser.position - 1,
+                        ));
                     }
-                }
 
-                Ok(DewSchemaLanguageExpression::Call {
-                    method_name: "".to_string(),
-                    args,
-                })
+                    parser.expect(DewSchemaLanguageToken::Colon)?;
+                    entries.push((key, parser.parse()?));
+                    Ok(())
+                })?;
+
+                DewSchemaLanguageExpressionKind::Object(entries)
             }
             Some(DewSchemaLanguageToken::Identifier(name)) => {
                 // function call or just identifier
                 if let Some(DewSchemaLanguageToken::LeftParenthesis) = self.peek() {
                     self.next(); // consume '('
-                    let mut args = Vec::new();
-
-                    if let Some(DewSchemaLanguageToken::RightParenthesis) = self.peek() {
-                        self.next(); // consume ')'
-                    } else {
-                        loop {
-                            args.push(self.parse()?);
-                            match self.peek() {
-                                Some(DewSchemaLanguageToken::Comma) => {
-                                    self.next(); // consume ','
-                                }
-                                Some(DewSchemaLanguageToken::RightParenthesis) => {
-                                    self.next(); // consume ')'
-                                    break;
-                                }
-                                other => {
-                                    return Err(format!("Unexpected token in args: {:?}", other))
-                                }
-                            }
-                        }
-                    }
 
-                    Ok(DewSchemaLanguageExpression::Call {
+                    DewSchemaLanguageExpressionKind::Call {
                         method_name: name,
-                        args,
-                    })
+                        args: self.parse_args()?,
+                    }
                 } else {
-                    Ok(DewSchemaLanguageExpression::Identifier(name))
+                    DewSchemaLanguageExpressionKind::Identifier(name)
                 }
             }
-            other => Err(format!("Unexpected token: {:?}", other)),
-        }
+            other => {
+                // point at the consumed token, or at the end of input when there was none
+                let index = match other {
+                    Some(_) => self.position - 1,
+                    None => self.position,
+                };
+
+                return Err(self.error_at(format!("Unexpected token: {:?}", other), index));
+            }
+        };
+
+        Ok(DewSchemaLanguageExpression::new(
+            kind,
+            start.merge(self.previous_span()),
+        ))
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::error::DslErrorKind;
+
+    fn node(kind: DewSchemaLanguageExpressionKind) -> DewSchemaLanguageExpression {
+        DewSchemaLanguageExpression::new(kind, Span::default())
+    }
+
+    fn number(n: &str) -> DewSchemaLanguageExpression {
+        node(DewSchemaLanguageExpressionKind::Number(n.into()))
+    }
+
+    fn string(s: &str) -> DewSchemaLanguageExpression {
+        node(DewSchemaLanguageExpressionKind::StringLiteral(s.into()))
+    }
+
+    fn identifier(name: &str) -> DewSchemaLanguageExpression {
+        node(DewSchemaLanguageExpressionKind::Identifier(name.into()))
+    }
+
+    fn call(
+        method_name: &str,
+        args: Vec<DewSchemaLanguageExpression>,
+    ) -> DewSchemaLanguageExpression {
+        node(DewSchemaLanguageExpressionKind::Call {
+            method_name: method_name.into(),
+            args,
+        })
+    }
+
+    fn chain(chain: Vec<DewSchemaLanguageExpression>) -> DewSchemaLanguageExpression {
+        node(DewSchemaLanguageExpressionKind::Chain(chain))
+    }
+
+    fn span(start: (usize, usize, usize), end: (usize, usize, usize)) -> Span {
+        Span::new(
+            Position::new(start.0, start.1, start.2),
+            Position::new(end.0, end.1, end.2),
+        )
+    }
 
     #[test]
     fn test_parse_simple() {
         let input = r#"foo.bar(123, "hello")"#;
-        let expected = DewSchemaLanguageExpression::Chain(vec![
-            DewSchemaLanguageExpression::Identifier("foo".into()),
-            DewSchemaLanguageExpression::Call {
-                method_name: "bar".into(),
-                args: vec![
-                    DewSchemaLanguageExpression::Number("123".into()),
-                    DewSchemaLanguageExpression::StringLiteral("hello".into()),
-                ],
-            },
+        let expected = chain(vec![
+            identifier("foo"),
+            call("bar", vec![number("123"), string("hello")]),
         ]);
         let result = DewSchemaLanguageParser::consume(input).unwrap();
         assert_eq!(result, expected);
@@ -170,16 +767,10 @@ mod tests {
             "Hello, World!"
         , 42)
     )"#;
-        let expected = DewSchemaLanguageExpression::Call {
-            method_name: "foo".into(),
-            args: vec![DewSchemaLanguageExpression::Call {
-                method_name: "bar".into(),
-                args: vec![
-                    DewSchemaLanguageExpression::StringLiteral("Hello, World!".into()),
-                    DewSchemaLanguageExpression::Number("42".into()),
-                ],
-            }],
-        };
+        let expected = call(
+            "foo",
+            vec![call("bar", vec![string("Hello, World!"), number("42")])],
+        );
         let result = DewSchemaLanguageParser::consume(input).unwrap();
         assert_eq!(result, expected);
     }
@@ -187,13 +778,7 @@ mod tests {
     #[test]
     fn test_parse_simple_number() {
         let input = r#"add(42, 38)"#;
-        let expected = DewSchemaLanguageExpression::Call {
-            method_name: "add".into(),
-            args: vec![
-                DewSchemaLanguageExpression::Number("42".into()),
-                DewSchemaLanguageExpression::Number("38".into()),
-            ],
-        };
+        let expected = call("add", vec![number("42"), number("38")]);
         let result = DewSchemaLanguageParser::consume(input).unwrap();
         assert_eq!(result, expected);
     }
@@ -201,12 +786,10 @@ mod tests {
     #[test]
     fn test_parse_simple_negative_number() {
         let input = r#"-42.sub(-20)"#;
-        let expected = DewSchemaLanguageExpression::Chain(vec![
-            DewSchemaLanguageExpression::Number("-42".into()),
-            DewSchemaLanguageExpression::Call {
-                method_name: "sub".into(),
-                args: vec![DewSchemaLanguageExpression::Number("-20".into())],
-            },
+        let expected = chain(vec![
+            number("42"),
+            call("sub", vec![number("-20")]),
+            call("negate", vec![]),
         ]);
         let result = DewSchemaLanguageParser::consume(input).unwrap();
         assert_eq!(result, expected);
@@ -215,12 +798,10 @@ mod tests {
     #[test]
     fn test_parse_complex_numbers() {
         let input = r#"-3.2e3.mul(1e20)"#;
-        let expected = DewSchemaLanguageExpression::Chain(vec![
-            DewSchemaLanguageExpression::Number("-3.2e3".into()),
-            DewSchemaLanguageExpression::Call {
-                method_name: "mul".into(),
-                args: vec![DewSchemaLanguageExpression::Number("1e20".into())],
-            },
+        let expected = chain(vec![
+            number("3.2e3"),
+            call("mul", vec![number("1e20")]),
+            call("negate", vec![]),
         ]);
         let result = DewSchemaLanguageParser::consume(input).unwrap();
         assert_eq!(result, expected);
@@ -229,13 +810,290 @@ mod tests {
     #[test]
     fn test_parse_string_with_escape() {
         let input = r#"alert("hello, \"world\"!")"#;
-        let expected = DewSchemaLanguageExpression::Call {
-            method_name: "alert".into(),
-            args: vec![DewSchemaLanguageExpression::StringLiteral(
-                r#"hello, "world"!"#.into(),
-            )],
+        let expected = call("alert", vec![string(r#"hello, "world"!"#)]);
+        let result = DewSchemaLanguageParser::consume(input).unwrap();
+        assert_eq!(result, expected);
+    }
+
+    #[test]
+    fn test_parse_infix_precedence() {
+        let input = r#"$.x >= 5 && $.y + 1 * 2 <= 3 || !$.z"#;
+        let expected = DewSchemaLanguageParser::consume(
+            r#"$.x.gte(5).and($.y.add(1.multiply(2)).lte(3)).or($.z.not())"#,
+        )
+        .unwrap();
+        let result = DewSchemaLanguageParser::consume(input).unwrap();
+        assert_eq!(result, expected);
+    }
+
+    #[test]
+    fn test_parse_infix_left_associative() {
+        let input = r#"10 - 4 - 3"#;
+        let expected = chain(vec![
+            number("10"),
+            call("subtract", vec![number("4")]),
+            call("subtract", vec![number("3")]),
+        ]);
+        let result = DewSchemaLanguageParser::consume(input).unwrap();
+        assert_eq!(result, expected);
+    }
+
+    #[test]
+    fn test_parse_parenthesized_infix() {
+        let input = r#"($.a + 1) * -$.b"#;
+        let expected =
+            DewSchemaLanguageParser::consume(r#"($.a.add(1)).multiply($.b.negate())"#).unwrap();
+        let result = DewSchemaLanguageParser::consume(input).unwrap();
+        assert_eq!(result, expected);
+    }
+
+    #[test]
+    fn test_parse_trailing_tokens() {
+        let result = DewSchemaLanguageParser::consume(r#"$.a $.b"#);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_parse_error_spans() {
+        let error = DewSchemaLanguageParser::consume(r#"$.a.gte(1 2)"#).unwrap_err();
+        assert_eq!(error.kind, crate::error::DslErrorKind::Parse);
+        assert_eq!(error.span, Some(span((10, 1, 11), (11, 1, 12))));
+
+        let error = DewSchemaLanguageParser::consume(r#"$.a.gte("#).unwrap_err();
+        assert_eq!(error.kind, crate::error::DslErrorKind::Parse);
+        assert_eq!(error.span, Some(span((8, 1, 9), (8, 1, 9))));
+    }
+
+    #[test]
+    fn test_parse_node_spans() {
+        let input = "$.a >= 5 &&\n  $.b.equal(\"x\")";
+        let expression = DewSchemaLanguageParser::consume(input).unwrap();
+        assert_eq!(expression.span, span((0, 1, 1), (28, 2, 17)));
+
+        let DewSchemaLanguageExpressionKind::Chain(nodes) = &expression.kind else {
+            panic!("expected a chain, got {:?}", expression);
+        };
+        // `$`, `a`, `>= 5`, `&& $.b.equal("x")`
+        assert_eq!(nodes[1].span, span((2, 1, 3), (3, 1, 4)));
+        assert_eq!(nodes[2].span, span((4, 1, 5), (8, 1, 9)));
+        assert_eq!(nodes[3].span, span((9, 1, 10), (28, 2, 17)));
+
+        let DewSchemaLanguageExpressionKind::Call { args, .. } = &nodes[3].kind else {
+            panic!("expected a call, got {:?}", nodes[3]);
+        };
+        assert_eq!(args[0].span, span((14, 2, 3), (28, 2, 17)));
+    }
+
+    #[test]
+    fn test_parse_let() {
+        let input = r#"let total = $.a + 1 in total <= 10 && total > 0"#;
+        let expected = node(DewSchemaLanguageExpressionKind::Let {
+            name: "total".into(),
+            value: Box::new(DewSchemaLanguageParser::consume("$.a.add(1)").unwrap()),
+            body: Box::new(
+                DewSchemaLanguageParser::consume("total.lte(10).and(total.gt(0))").unwrap(),
+            ),
+        });
+        let result = DewSchemaLanguageParser::consume(input).unwrap();
+        assert_eq!(result, expected);
+        assert_eq!(result.span, span((0, 1, 1), (47, 1, 48)));
+
+        // `let` without a binding stays an ordinary identifier
+        assert_eq!(
+            DewSchemaLanguageParser::consume("$.let").unwrap(),
+            chain(vec![identifier("$"), identifier("let")])
+        );
+
+        assert!(DewSchemaLanguageParser::consume("let x = 1 x").is_err());
+        assert!(DewSchemaLanguageParser::consume("let true = 1 in true").is_err());
+        for input in [
+            "let in = 1 in in",
+            "let let = 1 in let",
+            "let $ = 1 in $",
+            "let item = 1 in item",
+        ] {
+            let error = DewSchemaLanguageParser::consume(input).unwrap_err();
+            assert_eq!(error.kind, DslErrorKind::Parse, "{}", input);
+            assert!(
+                error.message.starts_with("Cannot bind reserved name"),
+                "{}",
+                input
+            );
+        }
+        assert!(DewSchemaLanguageParser::consume("in => 1").is_err());
+    }
+
+    #[test]
+    fn test_parse_lambda() {
+        let input = r#"$.batches.every(b => b.transfers.every((t, i) => t.to.in(b.allowed)))"#;
+        let inner = node(DewSchemaLanguageExpressionKind::Lambda {
+            parameters: vec!["t".into(), "i".into()],
+            body: Box::new(DewSchemaLanguageParser::consume("t.to.in(b.allowed)").unwrap()),
+        });
+        let outer = node(DewSchemaLanguageExpressionKind::Lambda {
+            parameters: vec!["b".into()],
+            body: Box::new(chain(vec![
+                identifier("b"),
+                identifier("transfers"),
+                call("every", vec![inner]),
+            ])),
+        });
+        let expected = chain(vec![
+            identifier("$"),
+            identifier("batches"),
+            call("every", vec![outer]),
+        ]);
+        let result = DewSchemaLanguageParser::consume(input).unwrap();
+        assert_eq!(result, expected);
+
+        // a single parameter may be parenthesized
+        assert_eq!(
+            DewSchemaLanguageParser::consume("$.ids.map((a) => a + 1)").unwrap(),
+            DewSchemaLanguageParser::consume("$.ids.map(a => a + 1)").unwrap()
+        );
+        let error = DewSchemaLanguageParser::consume("$.ids.map((a, i, x) => a)").unwrap_err();
+        assert_eq!(
+            error.message,
+            "A lambda takes at most two parameters, the element and its index"
+        );
+        assert_eq!(error.span, Some(span((17, 1, 18), (18, 1, 19))));
+        let error = DewSchemaLanguageParser::consume("$.ids.map((a, a) => a)").unwrap_err();
+        assert_eq!(error.kind, DslErrorKind::Parse);
+        assert_eq!(error.message, "Duplicate lambda parameter 'a'");
+        assert_eq!(error.span, Some(span((14, 1, 15), (15, 1, 16))));
+
+        // a parenthesized group is not mistaken for a parameter list
+        assert_eq!(
+            DewSchemaLanguageParser::consume("(a).add(1)").unwrap(),
+            chain(vec![
+                call("", vec![identifier("a")]),
+                call("add", vec![number("1")])
+            ])
+        );
+        assert!(DewSchemaLanguageParser::consume("$ => 1").is_err());
+    }
+
+    #[test]
+    fn test_parse_literals() {
+        let input = r#"{ "usdc.near": [1, 2,], limit: { max: $.a + 1 }, }"#;
+        let expected = node(DewSchemaLanguageExpressionKind::Object(vec![
+            (
+                "usdc.near".into(),
+                node(DewSchemaLanguageExpressionKind::Array(vec![
+                    number("1"),
+                    number("2"),
+                ])),
+            ),
+            (
+                "limit".into(),
+                node(DewSchemaLanguageExpressionKind::Object(vec![(
+                    "max".into(),
+                    DewSchemaLanguageParser::consume("$.a.add(1)").unwrap(),
+                )])),
+            ),
+        ]));
+        let result = DewSchemaLanguageParser::consume(input).unwrap();
+        assert_eq!(result, expected);
+
+        assert_eq!(
+            DewSchemaLanguageParser::consume("[].length()").unwrap(),
+            chain(vec![
+                node(DewSchemaLanguageExpressionKind::Array(vec![])),
+                call("length", vec![]),
+            ])
+        );
+
+        assert!(DewSchemaLanguageParser::consume("[1 2]").is_err());
+        assert!(DewSchemaLanguageParser::consume("[1,,]").is_err());
+        assert!(DewSchemaLanguageParser::consume("{a: 1, a: 2}").is_err());
+        assert!(DewSchemaLanguageParser::consume("{1: 2}").is_err());
+    }
+
+    #[test]
+    fn test_parse_index() {
+        let input = r#"$.args[0]["my-key"].amount[$.keys[-1]]"#;
+        let index = |key| node(DewSchemaLanguageExpressionKind::Index(Box::new(key)));
+        let expected = chain(vec![
+            identifier("$"),
+            identifier("args"),
+            index(number("0")),
+            index(string("my-key")),
+            identifier("amount"),
+            index(chain(vec![
+                identifier("$"),
+                identifier("keys"),
+                index(number("-1")),
+            ])),
+        ]);
+        let result = DewSchemaLanguageParser::consume(input).unwrap();
+        assert_eq!(result, expected);
+
+        let DewSchemaLanguageExpressionKind::Chain(nodes) = &result.kind else {
+            panic!("expected a chain, got {:?}", result);
         };
+        assert_eq!(
+            DewSchemaLanguageExpression::path(nodes),
+            r#"$.args[0]["my-key"].amount[$.keys[-1]]"#
+        );
+        assert_eq!(nodes[2].span, span((6, 1, 7), (9, 1, 10)));
+
+        assert!(DewSchemaLanguageParser::consume("$.args[0").is_err());
+    }
+
+    #[test]
+    fn test_parse_optional_chaining() {
+        let input = r#"$.a?.b?.[0]?.gte(1)"#;
+        let optional = |element| node(DewSchemaLanguageExpressionKind::Optional(Box::new(element)));
+        let expected = chain(vec![
+            identifier("$"),
+            identifier("a"),
+            optional(identifier("b")),
+            optional(node(DewSchemaLanguageExpressionKind::Index(Box::new(
+                number("0"),
+            )))),
+            optional(call("gte", vec![number("1")])),
+        ]);
         let result = DewSchemaLanguageParser::consume(input).unwrap();
         assert_eq!(result, expected);
+
+        let DewSchemaLanguageExpressionKind::Chain(nodes) = &result.kind else {
+            panic!("expected a chain, got {:?}", result);
+        };
+        assert_eq!(
+            DewSchemaLanguageExpression::path(nodes),
+            "$.a?.b?.[0]?.gte(…)"
+        );
+
+        // operators apply to the result of an optional chain instead of joining it
+        let result = DewSchemaLanguageParser::consume("$.a?.b >= 1").unwrap();
+        let expected = chain(vec![
+            chain(vec![
+                identifier("$"),
+                identifier("a"),
+                optional(identifier("b")),
+            ]),
+            call("gte", vec![number("1")]),
+        ]);
+        assert_eq!(result, expected);
+    }
+
+    #[test]
+    fn test_parse_nesting_depth() {
+        assert!(DewSchemaLanguageParser::consume_with_max_depth("(1)", 2).is_ok());
+        assert!(DewSchemaLanguageParser::consume_with_max_depth("f(1, [2])", 3).is_ok());
+
+        let error = DewSchemaLanguageParser::consume_with_max_depth("f(1, [2])", 2).unwrap_err();
+        assert_eq!(error.kind, DslErrorKind::NestingTooDeep);
+        assert_eq!(error.message, "Expression nests deeper than 2 levels");
+        assert_eq!(error.span, Some(span((6, 1, 7), (7, 1, 8))));
+
+        // deep input fails cleanly instead of overflowing the stack
+        let input = format!("{}1{}", "(".repeat(100_000), ")".repeat(100_000));
+        let error = DewSchemaLanguageParser::consume(&input).unwrap_err();
+        assert_eq!(error.kind, DslErrorKind::NestingTooDeep);
+
+        let input = format!("{}true", "!".repeat(100_000));
+        let error = DewSchemaLanguageParser::consume(&input).unwrap_err();
+        assert_eq!(error.kind, DslErrorKind::NestingTooDeep);
     }
 }
@@ -0,0 +1,78 @@
+//! Deterministic metering of evaluation. Every built-in method and host function has a
+//! [`DslGasCost`], charged each time it is called; the engine adds them up and stops once
+//! the `max_gas` budget of its [`DslLimits`](crate::limits::DslLimits) is exhausted.
+
+use serde_json::Value;
+
+use crate::{
+    engine::DewSchemaLanguageResult,
+    number::{self, digit_count},
+};
+
+/// Gas charged for one call: `base`, plus `per_unit` for every unit of input size.
+///
+/// The input size of a call is the size of its callee plus that of its evaluated arguments.
+/// A string counts its length in bytes, a number its [`digit_count`], an array or object
+/// one unit per element or entry plus the size of each, and any other value nothing. Arguments of lazy methods, such as
+/// the condition of `every`, are charged separately as they are evaluated.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct DslGasCost {
+    pub base: u64,
+    pub per_unit: u64,
+}
+
+impl DslGasCost {
+    /// The cost of host functions that do not declare one.
+    pub const DEFAULT_HOST_FUNCTION: DslGasCost = DslGasCost::constant(1);
+
+    /// A cost that does not depend on the size of the input.
+    pub const fn constant(base: u64) -> Self {
+        DslGasCost { base, per_unit: 0 }
+    }
+
+    /// A cost that grows with the size of the input.
+    pub const fn linear(base: u64, per_unit: u64) -> Self {
+        DslGasCost { base, per_unit }
+    }
+
+    /// The gas charged for calling a method on `callee` with `args`.
+    pub fn charge(
+        &self,
+        callee: Option<&DewSchemaLanguageResult>,
+        args: &[DewSchemaLanguageResult],
+    ) -> u64 {
+        if self.per_unit == 0 {
+            return self.base;
+        }
+
+        let size = callee
+            .into_iter()
+            .chain(args)
+            .fold(0u64, |size, value| size.saturating_add(result_size(value)));
+
+        self.base.saturating_add(self.per_unit.saturating_mul(size))
+    }
+}
+
+fn result_size(value: &DewSchemaLanguageResult) -> u64 {
+    match value {
+        DewSchemaLanguageResult::String(s) => s.len() as u64,
+        DewSchemaLanguageResult::Number(n) => digit_count(n),
+        DewSchemaLanguageResult::Value(value) => json_size(value),
+        _ => 0,
+    }
+}
+
+fn json_size(value: &Value) -> u64 {
+    match value {
+        Value::String(s) => s.len() as u64,
+        Value::Number(n) => number::from_json_number(n).map_or(0, |n| digit_count(&n)),
+        Value::Array(array) => array.iter().fold(0u64, |size, element| {
+            size.saturating_add(1 + json_size(element))
+        }),
+        Value::Object(object) => object.values().fold(0u64, |size, value| {
+            size.saturating_add(1 + json_size(value))
+        }),
+        _ => 0,
+    }
+}
@@ -0,0 +1,780 @@
+//! Typed host functions. A [`DslSignature`] declares the callee, parameter and return
+//! types of a host function, so the engine checks arity and types before calling it
+//! instead of every function checking its own. Closures over native Rust types, such as
+//! `Fn(&str, f64) -> bool`, implement [`DslNativeFunction`] and [`DslNativeMethod`] and get
+//! their signature and argument conversions derived from their parameter and return types.
+
+use std::fmt;
+
+use serde_json::Value;
+
+use crate::{
+    engine::{DewSchemaLanguageResult, DslFunction, DslFunctionCallee, DslFunctionParams},
+    error::DslError,
+    methods::types,
+    number::{self, BigDecimal, ToPrimitive},
+};
+
+/// Type of a host function's callee, parameter or return value.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum DslType {
+    /// Any value, including `undefined`.
+    Any,
+    Number,
+    /// A number without a fractional part.
+    Integer,
+    String,
+    Boolean,
+    Array,
+    Object,
+    Null,
+}
+
+impl DslType {
+    /// The name `type_of` and `assert_type` use for the type, or `any`.
+    pub fn name(&self) -> &'static str {
+        match self {
+            DslType::Any => "any",
+            DslType::Number => "number",
+            DslType::Integer => "integer",
+            DslType::String => "string",
+            DslType::Boolean => "boolean",
+            DslType::Array => "array",
+            DslType::Object => "object",
+            DslType::Null => "null",
+        }
+    }
+
+    pub fn matches(&self, value: &DewSchemaLanguageResult) -> bool {
+        match self {
+            DslType::Any => true,
+            other => types::is_of_type(value, other.name()),
+        }
+    }
+}
+
+impl fmt::Display for DslType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// Declared types of a host function, checked by the engine around every call.
+///
+/// A signature without a callee type is for functions called on their own, `fee(1)`;
+/// one with a callee type is for methods called on a value, `$.amount.to_usd()`.
+/// Displays as e.g. `string.(number, ...string) -> boolean`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DslSignature {
+    callee: Option<DslType>,
+    params: Vec<DslType>,
+    variadic: Option<DslType>,
+    returns: DslType,
+}
+
+impl DslSignature {
+    /// A function without callee or parameters, returning any value.
+    pub fn new() -> Self {
+        DslSignature {
+            callee: None,
+            params: Vec::new(),
+            variadic: None,
+            returns: DslType::Any,
+        }
+    }
+
+    /// Makes the function a method that must be called on a value of type `callee`.
+    pub fn with_callee(mut self, callee: DslType) -> Self {
+        self.callee = Some(callee);
+        self
+    }
+
+    /// Appends a required parameter.
+    pub fn with_param(mut self, param: DslType) -> Self {
+        self.params.push(param);
+        self
+    }
+
+    /// Accepts any number of further arguments of type `variadic` after the parameters.
+    pub fn with_variadic(mut self, variadic: DslType) -> Self {
+        self.variadic = Some(variadic);
+        self
+    }
+
+    pub fn with_returns(mut self, returns: DslType) -> Self {
+        self.returns = returns;
+        self
+    }
+
+    pub fn callee(&self) -> Option<DslType> {
+        self.callee
+    }
+
+    pub fn params(&self) -> &[DslType] {
+        &self.params
+    }
+
+    pub fn variadic(&self) -> Option<DslType> {
+        self.variadic
+    }
+
+    pub fn returns(&self) -> DslType {
+        self.returns
+    }
+
+    /// Checks the number of arguments, which is already known when compiling.
+    pub fn check_arity(&self, method_name: &str, arg_count: usize) -> Result<(), DslError> {
+        let required = self.params.len();
+
+        if arg_count == required || (arg_count > required && self.variadic.is_some()) {
+            return Ok(());
+        }
+
+        let expected = match (required, self.variadic.is_some()) {
+            (0, _) => "no arguments".to_string(),
+            (1, false) => "exactly one argument".to_string(),
+            (1, true) => "at least one argument".to_string(),
+            (required, false) => format!("exactly {} arguments", required),
+            (required, true) => format!("at least {} arguments", required),
+        };
+
+        Err(DslError::arity(format!(
+            "'{}' method expects {}, got {}",
+            method_name, expected, arg_count
+        )))
+    }
+
+    /// Checks the callee and evaluated arguments of a call before it is made.
+    pub fn check_call(
+        &self,
+        method_name: &str,
+        callee: DslFunctionCallee,
+        args: &[DewSchemaLanguageResult],
+    ) -> Result<(), DslError> {
+        self.check_arity(method_name, args.len())?;
+
+        match (self.callee, callee) {
+            (None, Some(callee)) => {
+                return Err(DslError::type_mismatch(format!(
+                    "'{}' cannot be called on a value, got {}",
+                    method_name,
+                    callee.type_name()
+                )))
+            }
+            (Some(expected), None) => {
+                return Err(DslError::type_mismatch(format!(
+                    "'{}' method must be called on a value of type {}",
+                    method_name, expected
+                )))
+            }
+            (Some(expected), Some(callee)) if !expected.matches(callee) => {
+                return Err(DslError::type_mismatch(format!(
+                    "'{}' method expects a callee of type {}, got {}",
+                    method_name,
+                    expected,
+                    callee.type_name()
+                )))
+            }
+            _ => {}
+        }
+
+        let param_types = self
+            .params
+            .iter()
+            .copied()
+            .chain(std::iter::repeat(self.variadic.unwrap_or(DslType::Any)));
+
+        for (position, (arg, expected)) in args.iter().zip(param_types).enumerate() {
+            if !expected.matches(arg) {
+                return Err(DslError::type_mismatch(format!(
+                    "'{}' method expects argument {} of type {}, got {}",
+                    method_name,
+                    position + 1,
+                    expected,
+                    arg.type_name()
+                )));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Checks the value a host function returned.
+    pub fn check_result(
+        &self,
+        method_name: &str,
+        result: &DewSchemaLanguageResult,
+    ) -> Result<(), DslError> {
+        if self.returns.matches(result) {
+            return Ok(());
+        }
+
+        Err(DslError::type_mismatch(format!(
+            "'{}' host function returned a value of type {}, expected {}",
+            method_name,
+            result.type_name(),
+            self.returns
+        )))
+    }
+}
+
+impl Default for DslSignature {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Display for DslSignature {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(callee) = self.callee {
+            write!(f, "{}.", callee)?;
+        }
+
+        let params = self
+            .params
+            .iter()
+            .map(DslType::to_string)
+            .chain(self.variadic.map(|variadic| format!("...{}", variadic)))
+            .collect::<Vec<_>>();
+
+        write!(f, "({}) -> {}", params.join(", "), self.returns)
+    }
+}
+
+/// A native type a host function can take as callee or argument.
+pub trait FromDsl: Sized {
+    /// The type declared in derived signatures; values of other types never reach
+    /// [`FromDsl::from_dsl`] through the engine.
+    const TYPE: DslType;
+
+    fn from_dsl(value: DewSchemaLanguageResult) -> Result<Self, DslError>;
+}
+
+/// A native type a host function can return.
+pub trait IntoDsl {
+    /// The return type declared in derived signatures.
+    const TYPE: DslType;
+
+    fn into_dsl(self) -> Result<DewSchemaLanguageResult, DslError>;
+}
+
+fn unexpected(expected: DslType, value: &DewSchemaLanguageResult) -> DslError {
+    DslError::type_mismatch(format!(
+        "Expected a value of type {}, got {}",
+        expected,
+        value.type_name()
+    ))
+}
+
+impl FromDsl for DewSchemaLanguageResult {
+    const TYPE: DslType = DslType::Any;
+
+    fn from_dsl(value: DewSchemaLanguageResult) -> Result<Self, DslError> {
+        Ok(value)
+    }
+}
+
+impl IntoDsl for DewSchemaLanguageResult {
+    const TYPE: DslType = DslType::Any;
+
+    fn into_dsl(self) -> Result<DewSchemaLanguageResult, DslError> {
+        self.normalize()
+    }
+}
+
+impl FromDsl for String {
+    const TYPE: DslType = DslType::String;
+
+    fn from_dsl(value: DewSchemaLanguageResult) -> Result<Self, DslError> {
+        match value {
+            DewSchemaLanguageResult::String(s) => Ok(s),
+            other => Err(unexpected(<Self as FromDsl>::TYPE, &other)),
+        }
+    }
+}
+
+impl IntoDsl for String {
+    const TYPE: DslType = DslType::String;
+
+    fn into_dsl(self) -> Result<DewSchemaLanguageResult, DslError> {
+        Ok(DewSchemaLanguageResult::String(self))
+    }
+}
+
+impl IntoDsl for &str {
+    const TYPE: DslType = DslType::String;
+
+    fn into_dsl(self) -> Result<DewSchemaLanguageResult, DslError> {
+        Ok(DewSchemaLanguageResult::String(self.to_string()))
+    }
+}
+
+impl FromDsl for bool {
+    const TYPE: DslType = DslType::Boolean;
+
+    fn from_dsl(value: DewSchemaLanguageResult) -> Result<Self, DslError> {
+        match value {
+            DewSchemaLanguageResult::Boolean(b) => Ok(b),
+            other => Err(unexpected(<Self as FromDsl>::TYPE, &other)),
+        }
+    }
+}
+
+impl IntoDsl for bool {
+    const TYPE: DslType = DslType::Boolean;
+
+    fn into_dsl(self) -> Result<DewSchemaLanguageResult, DslError> {
+        Ok(DewSchemaLanguageResult::Boolean(self))
+    }
+}
+
+impl FromDsl for BigDecimal {
+    const TYPE: DslType = DslType::Number;
+
+    fn from_dsl(value: DewSchemaLanguageResult) -> Result<Self, DslError> {
+        match value {
+            DewSchemaLanguageResult::Number(n) => Ok(n),
+            other => Err(unexpected(<Self as FromDsl>::TYPE, &other)),
+        }
+    }
+}
+
+impl IntoDsl for BigDecimal {
+    const TYPE: DslType = DslType::Number;
+
+    fn into_dsl(self) -> Result<DewSchemaLanguageResult, DslError> {
+        Ok(DewSchemaLanguageResult::Number(self))
+    }
+}
+
+/// Converts to the nearest `f64`, which loses precision for large or long numbers; take a
+/// `BigDecimal` or an integer type for amounts.
+impl FromDsl for f64 {
+    const TYPE: DslType = DslType::Number;
+
+    fn from_dsl(value: DewSchemaLanguageResult) -> Result<Self, DslError> {
+        let n = BigDecimal::from_dsl(value)?;
+
+        n.to_f64()
+            .ok_or_else(|| DslError::type_mismatch(format!("{} does not fit in f64", n)))
+    }
+}
+
+impl IntoDsl for f64 {
+    const TYPE: DslType = DslType::Number;
+
+    fn into_dsl(self) -> Result<DewSchemaLanguageResult, DslError> {
+        Ok(DewSchemaLanguageResult::Number(number::from_f64(self)?))
+    }
+}
+
+macro_rules! impl_dsl_integer {
+    ($($native:ty => $to_native:ident),*) => {
+        $(
+            impl FromDsl for $native {
+                const TYPE: DslType = DslType::Integer;
+
+                fn from_dsl(value: DewSchemaLanguageResult) -> Result<Self, DslError> {
+                    let n = BigDecimal::from_dsl(value)?;
+
+                    n.is_integer()
+                        .then(|| n.$to_native())
+                        .flatten()
+                        .ok_or_else(|| {
+                            DslError::type_mismatch(format!(
+                                "{} is not an integer that fits in {}",
+                                n,
+                                stringify!($native)
+                            ))
+                        })
+                }
+            }
+
+            impl IntoDsl for $native {
+                const TYPE: DslType = DslType::Integer;
+
+                fn into_dsl(self) -> Result<DewSchemaLanguageResult, DslError> {
+                    Ok(DewSchemaLanguageResult::Number(BigDecimal::from(self)))
+                }
+            }
+        )*
+    };
+}
+
+impl_dsl_integer!(i64 => to_i64, u64 => to_u64, u128 => to_u128);
+
+/// Any value with a JSON form, i.e. anything but `undefined`.
+impl FromDsl for Value {
+    const TYPE: DslType = DslType::Any;
+
+    fn from_dsl(value: DewSchemaLanguageResult) -> Result<Self, DslError> {
+        value.to_json()
+    }
+}
+
+impl IntoDsl for Value {
+    const TYPE: DslType = DslType::Any;
+
+    fn into_dsl(self) -> Result<DewSchemaLanguageResult, DslError> {
+        DewSchemaLanguageResult::from_json(&self)
+    }
+}
+
+/// An array whose every element converts to `T`.
+impl<T: FromDsl> FromDsl for Vec<T> {
+    const TYPE: DslType = DslType::Array;
+
+    fn from_dsl(value: DewSchemaLanguageResult) -> Result<Self, DslError> {
+        match value {
+            DewSchemaLanguageResult::Value(Value::Array(elements)) => elements
+                .iter()
+                .map(|element| {
+                    let element = DewSchemaLanguageResult::from_json(element)?;
+
+                    if !T::TYPE.matches(&element) {
+                        return Err(unexpected(T::TYPE, &element));
+                    }
+
+                    T::from_dsl(element)
+                })
+                .collect(),
+            other => Err(unexpected(<Self as FromDsl>::TYPE, &other)),
+        }
+    }
+}
+
+impl<T: IntoDsl> IntoDsl for Vec<T> {
+    const TYPE: DslType = DslType::Array;
+
+    fn into_dsl(self) -> Result<DewSchemaLanguageResult, DslError> {
+        let elements = self
+            .into_iter()
+            .map(|element| element.into_dsl()?.to_json())
+            .collect::<Result<_, _>>()?;
+
+        Ok(DewSchemaLanguageResult::Value(Value::Array(elements)))
+    }
+}
+
+/// Lets native functions fail, e.g. `Fn(String) -> Result<bool, String>`; the error is
+/// reported as a [`DslErrorKind::Host`](crate::error::DslErrorKind::Host) error when it
+/// is a string.
+impl<T: IntoDsl, E: Into<DslError>> IntoDsl for Result<T, E> {
+    const TYPE: DslType = T::TYPE;
+
+    fn into_dsl(self) -> Result<DewSchemaLanguageResult, DslError> {
+        self.map_err(Into::into)?.into_dsl()
+    }
+}
+
+/// A Rust closure usable as a typed host function, implemented for every `Fn` of up to
+/// six [`FromDsl`] arguments returning an [`IntoDsl`] value. `Args` is the tuple of
+/// argument types and only serves to tell the implementations apart.
+pub trait DslNativeFunction<Args>: 'static {
+    /// The signature derived from the argument and return types.
+    fn signature(&self) -> DslSignature;
+
+    /// Wraps the closure into a host function that converts its arguments.
+    fn into_host_function(self) -> DslFunction;
+}
+
+/// A Rust closure usable as a typed host method, which receives its callee as the first
+/// argument. Only implemented for closures of one to six arguments, so a closure without
+/// a parameter for the callee does not compile as a method:
+///
+/// ```compile_fail
+/// # use std::collections::HashMap;
+/// # use dew_schema_language::engine::DewSchemaLanguageEngine;
+/// DewSchemaLanguageEngine::from_value(serde_json::json!({}), HashMap::new())
+///     .with_native_host_method("vault_id", || "dewvault.near");
+/// ```
+pub trait DslNativeMethod<Args>: 'static {
+    /// The signature derived from the argument and return types.
+    fn signature(&self) -> DslSignature;
+
+    /// Wraps the closure into a host function that converts its callee and arguments.
+    fn into_host_function(self) -> DslFunction;
+}
+
+/// Implements [`DslNativeFunction`] and, given at least one argument, [`DslNativeMethod`] for
+/// closures of the listed arguments. Every argument is either an owned [`FromDsl`] type or a
+/// borrowed `&str`, so one implementation is generated per combination. A borrowed argument
+/// is marked as `&'static str` in `Args` and converted through a `String` that lives for the
+/// call.
+macro_rules! impl_dsl_native {
+    ($($arg:ident $value:ident),*) => {
+        impl_dsl_native!(@classify [] [] [] [] []; $($arg $value),*);
+    };
+    (
+        @classify [$($generic:ident)*] [$($marker:ty,)*] [$($param:ty,)*]
+        [$($dsl_type:expr,)*] [$($convert:tt)*];
+        $arg:ident $value:ident $(, $rest_arg:ident $rest_value:ident)*
+    ) => {
+        impl_dsl_native!(
+            @classify [$($generic)* $arg] [$($marker,)* $arg,] [$($param,)* $arg,]
+            [$($dsl_type,)* $arg::TYPE,] [$($convert)* ($value $arg::from_dsl, $value)];
+            $($rest_arg $rest_value),*
+        );
+        impl_dsl_native!(
+            @classify [$($generic)*] [$($marker,)* &'static str,] [$($param,)* &'dsl str,]
+            [$($dsl_type,)* DslType::String,] [$($convert)* ($value String::from_dsl, &$value)];
+            $($rest_arg $rest_value),*
+        );
+    };
+    (
+        @classify [$($generic:ident)*] [$($marker:ty,)*] [$($param:ty,)*]
+        [$($dsl_type:expr,)*] [$(($value:ident $from:path, $pass:expr))*];
+    ) => {
+        impl<F, R, $($generic),*> DslNativeFunction<($($marker,)*)> for F
+        where
+            F: for<'dsl> Fn($($param),*) -> R + 'static,
+            R: IntoDsl,
+            $($generic: FromDsl,)*
+        {
+            fn signature(&self) -> DslSignature {
+                DslSignature {
+                    callee: None,
+                    params: vec![$($dsl_type),*],
+                    variadic: None,
+                    returns: R::TYPE,
+                }
+            }
+
+            #[allow(unused_mut, unused_variables)]
+            fn into_host_function(self) -> DslFunction {
+                Box::new(
+                    move |params: DslFunctionParams, _callee: DslFunctionCallee| {
+                        let mut values = params.into_iter();
+                        $(let $value = $from(next_argument(&mut values)?)?;)*
+
+                        self($($pass),*).into_dsl()
+                    },
+                )
+            }
+        }
+
+        impl_dsl_native!(
+            @method [$($generic)*] [$($marker,)*] [$($param,)*] [$($dsl_type,)*]
+            [$(($value $from, $pass))*]
+        );
+    };
+    (@method [$($generic:ident)*] [] [] [] []) => {};
+    (
+        @method [$($generic:ident)*] [$($marker:ty,)*] [$($param:ty,)*]
+        [$callee_type:expr, $($dsl_type:expr,)*] [$(($value:ident $from:path, $pass:expr))*]
+    ) => {
+        impl<F, R, $($generic),*> DslNativeMethod<($($marker,)*)> for F
+        where
+            F: for<'dsl> Fn($($param),*) -> R + 'static,
+            R: IntoDsl,
+            $($generic: FromDsl,)*
+        {
+            fn signature(&self) -> DslSignature {
+                DslSignature {
+                    callee: Some($callee_type),
+                    params: vec![$($dsl_type),*],
+                    variadic: None,
+                    returns: R::TYPE,
+                }
+            }
+
+            fn into_host_function(self) -> DslFunction {
+                Box::new(
+                    move |params: DslFunctionParams, callee: DslFunctionCallee| {
+                        let mut values = callee.cloned().into_iter().chain(params);
+                        $(let $value = $from(next_argument(&mut values)?)?;)*
+
+                        self($($pass),*).into_dsl()
+                    },
+                )
+            }
+        }
+    };
+}
+
+fn next_argument(
+    values: &mut impl Iterator<Item = DewSchemaLanguageResult>,
+) -> Result<DewSchemaLanguageResult, DslError> {
+    values
+        .next()
+        .ok_or_else(|| DslError::arity("Native host function is missing an argument"))
+}
+
+impl_dsl_native!();
+impl_dsl_native!(A a);
+impl_dsl_native!(A a, B b);
+impl_dsl_native!(A a, B b, C c);
+impl_dsl_native!(A a, B b, C c, D d);
+impl_dsl_native!(A a, B b, C c, D d, E e);
+impl_dsl_native!(A a, B b, C c, D d, E e, G g);
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use serde_json::json;
+
+    use super::*;
+    use crate::{engine::DewSchemaLanguageEngine, error::DslErrorKind};
+
+    #[test]
+    fn test_signature_checks() {
+        let signature = DslSignature::new()
+            .with_callee(DslType::String)
+            .with_param(DslType::Integer)
+            .with_variadic(DslType::String)
+            .with_returns(DslType::Boolean);
+        let text = DewSchemaLanguageResult::String("alice.near".into());
+        let two = DewSchemaLanguageResult::Number(2.into());
+
+        assert_eq!(
+            signature.to_string(),
+            "string.(integer, ...string) -> boolean"
+        );
+        assert!(signature
+            .check_call("f", Some(&text), &[two.clone(), text.clone(), text.clone()])
+            .is_ok());
+
+        let error = signature.check_call("f", Some(&text), &[]).unwrap_err();
+        assert_eq!(error.kind, DslErrorKind::Arity);
+        assert_eq!(
+            error.message,
+            "'f' method expects at least one argument, got 0"
+        );
+
+        let error = signature
+            .check_call("f", None, std::slice::from_ref(&two))
+            .unwrap_err();
+        assert_eq!(error.kind, DslErrorKind::TypeMismatch);
+        assert_eq!(
+            error.message,
+            "'f' method must be called on a value of type string"
+        );
+
+        let error = signature
+            .check_call("f", Some(&text), &[two.clone(), two.clone()])
+            .unwrap_err();
+        assert_eq!(
+            error.message,
+            "'f' method expects argument 2 of type string, got number"
+        );
+
+        let error = signature.check_result("f", &text).unwrap_err();
+        assert_eq!(error.kind, DslErrorKind::TypeMismatch);
+        assert_eq!(
+            error.message,
+            "'f' host function returned a value of type string, expected boolean"
+        );
+
+        let error = DslSignature::new()
+            .with_param(DslType::Any)
+            .check_call("g", Some(&text), &[two])
+            .unwrap_err();
+        assert_eq!(error.message, "'g' cannot be called on a value, got string");
+    }
+
+    #[test]
+    fn test_native_host_functions() {
+        let engine = DewSchemaLanguageEngine::from_value(
+            json!({ "account_id": "alice.near", "amounts": [10, 20, 30] }),
+            HashMap::new(),
+        )
+        .with_native_host_function("is_vault", |account_id: &str| account_id == "dewvault.near")
+        .with_native_host_function("within", |amount: f64, limit: f64| amount <= limit)
+        .with_native_host_function("may_spend", |account_id: &str, limit: f64| {
+            account_id.ends_with(".near") && limit <= 100.0
+        })
+        .with_native_host_function("total", |amounts: Vec<u128>| {
+            amounts.into_iter().sum::<u128>()
+        })
+        .with_native_host_method("has_suffix", |account_id: &str, suffix: String| {
+            account_id.ends_with(&suffix)
+        })
+        .with_native_host_function("lookup", |key: String| -> Result<String, String> {
+            Err(format!("no entry for {}", key))
+        })
+        .with_typed_host_function(
+            "any_of",
+            DslSignature::new()
+                .with_callee(DslType::String)
+                .with_variadic(DslType::String)
+                .with_returns(DslType::Boolean),
+            Box::new(|params, callee| {
+                Ok(DewSchemaLanguageResult::Boolean(
+                    params.iter().any(|p| Some(p) == callee),
+                ))
+            }),
+        );
+        let evaluate = |expression: &str| engine.evaluate(expression.to_string());
+
+        assert_eq!(
+            engine
+                .host_function_signature("within")
+                .unwrap()
+                .to_string(),
+            "(number, number) -> boolean"
+        );
+        assert_eq!(
+            engine
+                .host_function_signature("may_spend")
+                .unwrap()
+                .to_string(),
+            "(string, number) -> boolean"
+        );
+        assert_eq!(
+            evaluate("is_vault($.account_id)").unwrap(),
+            DewSchemaLanguageResult::Boolean(false)
+        );
+        assert_eq!(
+            evaluate("within(15, 20.5)").unwrap(),
+            DewSchemaLanguageResult::Boolean(true)
+        );
+        assert_eq!(
+            evaluate("total($.amounts) == 60").unwrap(),
+            DewSchemaLanguageResult::Boolean(true)
+        );
+        assert_eq!(
+            evaluate("may_spend($.account_id, 50)").unwrap(),
+            DewSchemaLanguageResult::Boolean(true)
+        );
+        assert_eq!(
+            evaluate("$.account_id.has_suffix(\".near\")").unwrap(),
+            DewSchemaLanguageResult::Boolean(true)
+        );
+        assert_eq!(
+            evaluate("$.account_id.any_of(\"bob.near\", \"alice.near\")").unwrap(),
+            DewSchemaLanguageResult::Boolean(true)
+        );
+
+        // arity is checked when compiling, types before calling
+        let error = engine.compile("within(1)").unwrap_err();
+        assert_eq!(error.kind, DslErrorKind::Arity);
+        assert_eq!(
+            error.message,
+            "'within' method expects exactly 2 arguments, got 1"
+        );
+        assert!(error.span.is_some());
+
+        let error = evaluate("within(1, $.account_id)").unwrap_err();
+        assert_eq!(error.kind, DslErrorKind::TypeMismatch);
+        assert_eq!(
+            error.message,
+            "'within' method expects argument 2 of type number, got string"
+        );
+
+        assert_eq!(
+            evaluate("is_vault(\"a\").has_suffix(\"b\")")
+                .unwrap_err()
+                .kind,
+            DslErrorKind::TypeMismatch
+        );
+        assert_eq!(
+            evaluate("total([1.5])").unwrap_err().message,
+            "Expected a value of type integer, got number"
+        );
+
+        let error = evaluate("lookup(\"fee\")").unwrap_err();
+        assert_eq!(error.kind, DslErrorKind::Host);
+        assert_eq!(error.message, "no entry for fee");
+    }
+}
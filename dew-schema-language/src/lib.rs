@@ -1,4 +1,12 @@
 pub mod engine;
+pub mod error;
 pub mod expression;
+pub mod gas;
+pub mod host;
+pub mod limits;
 pub(crate) mod methods;
+pub mod number;
+pub mod policy;
+pub mod schema;
 pub mod tokenizer;
+pub mod trace;
@@ -0,0 +1,63 @@
+//! Bounds on the work an engine does for one expression, so that expressions from
+//! semi-trusted sources cannot exhaust the stack, the CPU or memory.
+
+/// Limits applied by an engine when compiling and evaluating expressions. Each one fails
+/// with its own [`DslErrorKind`](crate::error::DslErrorKind) when exceeded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DslLimits {
+    /// Longest expression, in bytes, that the engine compiles.
+    pub max_expression_length: usize,
+    /// Deepest nesting of sub-expressions, e.g. arguments, parentheses, literals and
+    /// `let` bodies. This bounds the recursion of the parser and the evaluator, so it must
+    /// stay low enough for the thread's stack.
+    pub max_nesting_depth: usize,
+    /// Most nodes evaluated for one evaluation, counting each element of an iteration.
+    pub max_evaluation_steps: usize,
+    /// Most elements of an array, or entries of an object, that a method call or a literal
+    /// may produce.
+    pub max_collection_size: usize,
+    /// Most gas one evaluation may use, see [`DslGasCost`](crate::gas::DslGasCost).
+    pub max_gas: u64,
+    /// Most digits a number may span, counted by [`digit_count`](crate::number::digit_count):
+    /// its mantissa digits plus the magnitude of its exponent. Checked for literals when
+    /// compiling, and for every number read from JSON, returned by a host function or
+    /// computed while evaluating.
+    pub max_number_digits: u64,
+}
+
+impl DslLimits {
+    pub const DEFAULT_MAX_EXPRESSION_LENGTH: usize = 64 * 1024;
+    pub const DEFAULT_MAX_NESTING_DEPTH: usize = 32;
+    pub const DEFAULT_MAX_EVALUATION_STEPS: usize = 1_000_000;
+    pub const DEFAULT_MAX_COLLECTION_SIZE: usize = 100_000;
+    /// Gas is metered but not limited by default.
+    pub const DEFAULT_MAX_GAS: u64 = u64::MAX;
+    /// Far beyond token amounts such as yoctoNEAR, which span 25 digits.
+    pub const DEFAULT_MAX_NUMBER_DIGITS: u64 = 1_000;
+
+    /// No limits except nesting depth, which is kept at its default to protect the stack.
+    /// Only for expressions from trusted sources.
+    pub fn unlimited() -> Self {
+        DslLimits {
+            max_expression_length: usize::MAX,
+            max_nesting_depth: Self::DEFAULT_MAX_NESTING_DEPTH,
+            max_evaluation_steps: usize::MAX,
+            max_collection_size: usize::MAX,
+            max_gas: u64::MAX,
+            max_number_digits: u64::MAX,
+        }
+    }
+}
+
+impl Default for DslLimits {
+    fn default() -> Self {
+        DslLimits {
+            max_expression_length: Self::DEFAULT_MAX_EXPRESSION_LENGTH,
+            max_nesting_depth: Self::DEFAULT_MAX_NESTING_DEPTH,
+            max_evaluation_steps: Self::DEFAULT_MAX_EVALUATION_STEPS,
+            max_collection_size: Self::DEFAULT_MAX_COLLECTION_SIZE,
+            max_gas: Self::DEFAULT_MAX_GAS,
+            max_number_digits: Self::DEFAULT_MAX_NUMBER_DIGITS,
+        }
+    }
+}
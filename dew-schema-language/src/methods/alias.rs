@@ -1,82 +1,161 @@
 use std::collections::HashMap;
 
-use crate::engine::{DewSchemaLanguageResult, DslFunction};
+use crate::{
+    engine::{DewSchemaLanguageResult, DslFunctionCallee, DslFunctionParams},
+    error::DslError,
+    gas::DslGasCost,
+    methods::{
+        registry::{DslBuiltinFunction, MethodRegistry},
+        types,
+    },
+};
 
-pub fn functions() -> HashMap<String, DslFunction> {
-    let mut map: HashMap<String, DslFunction> = HashMap::new();
+/// Gas charged by each method of this module. Comparisons grow with the size of their
+/// input.
+pub const GAS_COSTS: [(&str, DslGasCost); 5] = [
+    ("assert_case_insensitive_equal", DslGasCost::linear(1, 1)),
+    ("assert_equal", DslGasCost::linear(1, 1)),
+    ("assert_gte", DslGasCost::linear(1, 1)),
+    ("assert_lte", DslGasCost::linear(1, 1)),
+    ("assert_type", DslGasCost::constant(1)),
+];
+
+/// Whether the comparison `method_name` holds. An `undefined` callee or argument makes the
+/// assertion fail rather than the evaluation, so that collected assertions after it on a
+/// missing field are still checked.
+fn holds(
+    method_name: &str,
+    params: &DslFunctionParams,
+    callee: DslFunctionCallee,
+) -> Result<bool, DslError> {
+    let missing = matches!(callee, Some(DewSchemaLanguageResult::Undefined))
+        || params
+            .iter()
+            .any(|param| matches!(param, DewSchemaLanguageResult::Undefined));
+
+    if missing && !params.is_empty() {
+        return Ok(false);
+    }
+
+    Ok(
+        MethodRegistry::global().call(method_name, params.clone(), callee)?
+            == DewSchemaLanguageResult::Boolean(true),
+    )
+}
+
+pub fn functions() -> HashMap<String, DslBuiltinFunction> {
+    let mut map: HashMap<String, DslBuiltinFunction> = HashMap::new();
 
     map.insert(
         "assert_case_insensitive_equal".to_string(),
-        Box::new(|params, callee| {
-            let result = crate::methods::string::functions()
-                .get("case_insensitive_equal")
-                .unwrap()(params.clone(), callee)?;
-
-            if result != DewSchemaLanguageResult::Boolean(true) {
-                return Err(format!(
-                    "{:?} is not case insensitively equal to {:?}",
+        |params, callee| {
+            let holds = holds("case_insensitive_equal", &params, callee)?;
+
+            if !holds {
+                return Err(DslError::assertion_failed(format!(
+                    "{} is not case insensitively equal to {}",
                     callee.unwrap(),
                     params[0]
-                ));
+                )));
             }
 
             Ok(DewSchemaLanguageResult::Boolean(true))
-        }),
+        },
     );
 
-    map.insert(
-        "assert_equal".to_string(),
-        Box::new(|params, callee| {
-            let result =
-                crate::methods::cores::functions().get("equal").unwrap()(params.clone(), callee)?;
-
-            if result != DewSchemaLanguageResult::Boolean(true) {
-                return Err(format!(
-                    "{:?} is not equal to {:?}",
-                    callee.unwrap(),
-                    params[0]
-                ));
-            }
+    map.insert("assert_equal".to_string(), |params, callee| {
+        let holds = holds("equal", &params, callee)?;
 
-            Ok(DewSchemaLanguageResult::Boolean(true))
-        }),
-    );
+        if !holds {
+            return Err(DslError::assertion_failed(format!(
+                "{} is not equal to {}",
+                callee.unwrap(),
+                params[0]
+            )));
+        }
 
-    map.insert(
-        "assert_gte".to_string(),
-        Box::new(|params, callee| {
-            let result =
-                crate::methods::cores::functions().get("gte").unwrap()(params.clone(), callee)?;
-
-            if result != DewSchemaLanguageResult::Boolean(true) {
-                return Err(format!(
-                    "{:?} is not greater than or equal to {:?}",
-                    callee.unwrap(),
-                    params[0]
-                ));
-            }
+        Ok(DewSchemaLanguageResult::Boolean(true))
+    });
 
-            Ok(DewSchemaLanguageResult::Boolean(true))
-        }),
-    );
+    map.insert("assert_type".to_string(), |params, callee| {
+        if params.is_empty() {
+            return Err(DslError::arity(
+                "'assert_type' method expects at least one argument",
+            ));
+        }
 
-    map.insert(
-        "assert_lte".to_string(),
-        Box::new(|params, callee| {
-            let result =
-                crate::methods::cores::functions().get("lte").unwrap()(params.clone(), callee)?;
-
-            if result != DewSchemaLanguageResult::Boolean(true) {
-                return Err(format!(
-                    "{:?} is not less than or equal to {:?}",
-                    callee.unwrap(),
-                    params[0]
-                ));
+        let callee = match callee {
+            Some(callee) => callee,
+            None => return Err(DslError::type_mismatch("Cannot call 'assert_type' on null")),
+        };
+
+        let mut expected = Vec::new();
+        for param in &params {
+            match param {
+                DewSchemaLanguageResult::String(type_name)
+                    if types::TYPE_NAMES.contains(&type_name.as_str()) =>
+                {
+                    expected.push(type_name.as_str());
+                }
+                DewSchemaLanguageResult::String(type_name) => {
+                    return Err(DslError::type_mismatch(format!(
+                        "'assert_type' expects type names, one of {}, got {}",
+                        types::TYPE_NAMES.join(", "),
+                        type_name
+                    )));
+                }
+                _ => {
+                    return Err(DslError::type_mismatch(format!(
+                        "'assert_type' expects type names, one of {}, got {}",
+                        types::TYPE_NAMES.join(", "),
+                        param
+                    )));
+                }
             }
+        }
 
-            Ok(DewSchemaLanguageResult::Boolean(true))
-        }),
-    );
+        if !expected
+            .iter()
+            .any(|type_name| types::is_of_type(callee, type_name))
+        {
+            return Err(DslError::assertion_failed(format!(
+                "{} is of type {}, expected {}",
+                callee,
+                callee.type_name(),
+                expected.join(" or ")
+            )));
+        }
+
+        Ok(DewSchemaLanguageResult::Boolean(true))
+    });
+
+    map.insert("assert_gte".to_string(), |params, callee| {
+        let holds = holds("gte", &params, callee)?;
+
+        if !holds {
+            return Err(DslError::assertion_failed(format!(
+                "{} is not greater than or equal to {}",
+                callee.unwrap(),
+                params[0]
+            )));
+        }
+
+        Ok(DewSchemaLanguageResult::Boolean(true))
+    });
+
+    map.insert("assert_lte".to_string(), |params, callee| {
+        let holds = holds("lte", &params, callee)?;
+
+        if !holds {
+            return Err(DslError::assertion_failed(format!(
+                "{} is not less than or equal to {}",
+                callee.unwrap(),
+                params[0]
+            )));
+        }
+
+        Ok(DewSchemaLanguageResult::Boolean(true))
+    });
 
     map
 }
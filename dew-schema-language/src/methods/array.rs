@@ -1,162 +1,314 @@
 use std::collections::HashMap;
 
-use crate::engine::{DewSchemaLanguageResult, DslFunction};
+use crate::{
+    engine::{DewSchemaLanguageResult, DslLazyParams},
+    error::DslError,
+    gas::DslGasCost,
+    methods::registry::{DslBuiltinFunction, DslBuiltinLazyFunction},
+    number::{BigDecimal, ToPrimitive},
+};
 
-pub fn functions() -> HashMap<String, DslFunction> {
-    let mut map: HashMap<String, DslFunction> = HashMap::new();
+/// Element of `arr` at an integer `index`; negative indices count from the end.
+pub(crate) fn element_at(
+    arr: &[serde_json::Value],
+    index: &BigDecimal,
+) -> Result<DewSchemaLanguageResult, DslError> {
+    if !index.is_integer() {
+        return Err(DslError::type_mismatch("Index must be an integer"));
+    }
 
-    map.insert(
-        "array".to_string(),
-        Box::new(|args, callee| {
-            let mut result = Vec::new();
+    let flex_index = index
+        .to_i64()
+        .ok_or_else(|| DslError::evaluation("Index out of bounds"))?;
 
-            if callee.is_some() {
-                return Err(format!("Cannot call 'array' on other objects"));
-            }
+    if flex_index < 0 && flex_index.unsigned_abs() as usize > arr.len() {
+        return Err(DslError::evaluation("Index out of bounds"));
+    }
 
-            for arg in args {
-                match arg {
-                    DewSchemaLanguageResult::Value(value) => {
-                        result.push(value);
-                    }
-                    DewSchemaLanguageResult::Boolean(b) => {
-                        result.push(serde_json::Value::Bool(b));
-                    }
-                    DewSchemaLanguageResult::Number(n) => {
-                        result.push(serde_json::Value::Number(
-                            serde_json::Number::from_f64(n).ok_or("Invalid number")?,
-                        ));
-                    }
-                    DewSchemaLanguageResult::String(s) => {
-                        result.push(serde_json::Value::String(s));
-                    }
-                    DewSchemaLanguageResult::Null => {
-                        result.push(serde_json::Value::Null);
-                    }
-                    _ => {
-                        return Err(format!(
-                            "'array' method expects arguments to be arrays or primitive values"
-                        ));
-                    }
+    let index = if flex_index < 0 {
+        (arr.len() as i64 + flex_index) as usize
+    } else {
+        flex_index as usize
+    };
+
+    if index >= arr.len() {
+        return Err(DslError::evaluation("Index out of bounds"));
+    }
+
+    DewSchemaLanguageResult::from_json(&arr[index])
+}
+
+fn iteration_source<'a>(
+    method_name: &str,
+    params: &DslLazyParams,
+    callee: Option<&'a DewSchemaLanguageResult>,
+) -> Result<&'a Vec<serde_json::Value>, DslError> {
+    if params.len() != 1 {
+        return Err(DslError::arity(format!(
+            "'{}' method expects exactly one argument",
+            method_name
+        )));
+    }
+
+    match callee {
+        Some(DewSchemaLanguageResult::Value(serde_json::Value::Array(arr))) => Ok(arr),
+        Some(_) => Err(DslError::type_mismatch(format!(
+            "'{}' method can only be called on arrays",
+            method_name
+        ))),
+        None => Err(DslError::type_mismatch(format!(
+            "Cannot call '{}' on null",
+            method_name
+        ))),
+    }
+}
+
+fn expect_boolean(method_name: &str, result: DewSchemaLanguageResult) -> Result<bool, DslError> {
+    match result {
+        DewSchemaLanguageResult::Boolean(b) => Ok(b),
+        _ => Err(DslError::type_mismatch(format!(
+            "'{}' method expects its argument to evaluate to a boolean",
+            method_name
+        ))),
+    }
+}
+
+/// Gas charged by each method of this module. Methods that visit every element grow with
+/// the size of the array; the condition of an iteration is charged per evaluation on top.
+pub const GAS_COSTS: [(&str, DslGasCost); 10] = [
+    ("array", DslGasCost::linear(1, 1)),
+    ("count_if", DslGasCost::linear(1, 1)),
+    ("every", DslGasCost::linear(1, 1)),
+    ("filter", DslGasCost::linear(1, 1)),
+    ("find", DslGasCost::linear(1, 1)),
+    ("get_index", DslGasCost::constant(1)),
+    ("in", DslGasCost::linear(1, 1)),
+    ("length", DslGasCost::constant(1)),
+    ("map", DslGasCost::linear(1, 1)),
+    ("some", DslGasCost::linear(1, 1)),
+];
+
+pub fn functions() -> HashMap<String, DslBuiltinFunction> {
+    let mut map: HashMap<String, DslBuiltinFunction> = HashMap::new();
+
+    map.insert("array".to_string(), |args, callee| {
+        let mut result = Vec::new();
+
+        if callee.is_some() {
+            return Err(DslError::type_mismatch(
+                "Cannot call 'array' on other objects",
+            ));
+        }
+
+        for arg in args {
+            match arg {
+                DewSchemaLanguageResult::Error(_) | DewSchemaLanguageResult::Undefined => {
+                    return Err(DslError::type_mismatch(
+                        "'array' method expects arguments to be arrays or primitive values",
+                    ));
                 }
+                arg => result.push(arg.to_json()?),
             }
+        }
 
-            Ok(DewSchemaLanguageResult::Value(serde_json::Value::Array(
-                result,
-            )))
-        }),
-    );
-
-    map.insert(
-        "get_index".to_string(),
-        Box::new(|args, callee| {
-            if args.len() != 1 {
-                return Err(format!("'get_index' method expects exactly one argument"));
-            }
+        Ok(DewSchemaLanguageResult::Value(serde_json::Value::Array(
+            result,
+        )))
+    });
+
+    map.insert("get_index".to_string(), |args, callee| {
+        if args.len() != 1 {
+            return Err(DslError::arity(
+                "'get_index' method expects exactly one argument",
+            ));
+        }
+
+        if callee.is_none() {
+            return Err(DslError::type_mismatch("Cannot call 'get_index' on null"));
+        }
 
-            if callee.is_none() {
-                return Err(format!("Cannot call 'get_index' on null"));
+        let index = match &args[0] {
+            DewSchemaLanguageResult::Number(n) => n,
+            _ => {
+                return Err(DslError::type_mismatch(
+                    "'get_index' method expects a number as argument",
+                ));
             }
+        };
 
-            let flex_index = match &args[0] {
-                DewSchemaLanguageResult::Number(n) => {
-                    if n.fract() != 0.0 {
-                        return Err(format!("Index must be an integer"));
-                    }
+        match callee.unwrap() {
+            DewSchemaLanguageResult::Value(serde_json::Value::Array(arr)) => element_at(arr, index),
+            _ => Err(DslError::type_mismatch(
+                "'get_index' method can only be called on arrays",
+            )),
+        }
+    });
 
-                    *n as i64
-                }
-                _ => {
-                    return Err(format!("'get_index' method expects a number as argument"));
-                }
-            };
+    map.insert("length".to_string(), |args, callee| {
+        if !args.is_empty() {
+            return Err(DslError::arity("'length' method expects no arguments"));
+        }
 
-            match callee.unwrap() {
-                DewSchemaLanguageResult::Value(serde_json::Value::Array(arr)) => {
-                    if flex_index < 0 && (-flex_index) as usize > arr.len() {
-                        return Err(format!("Index out of bounds"));
-                    }
+        if callee.is_none() {
+            return Err(DslError::type_mismatch("Cannot call 'length' on null"));
+        }
 
-                    let index = if flex_index < 0 {
-                        (arr.len() as i64 + flex_index) as usize
-                    } else {
-                        flex_index as usize
-                    };
+        match callee.unwrap() {
+            DewSchemaLanguageResult::Value(serde_json::Value::Array(arr)) => Ok(
+                DewSchemaLanguageResult::Number(BigDecimal::from(arr.len() as u64)),
+            ),
+            DewSchemaLanguageResult::String(s) => Ok(DewSchemaLanguageResult::Number(
+                BigDecimal::from(s.chars().count() as u64),
+            )),
+            _ => Err(DslError::type_mismatch(
+                "'length' method expects an array or string as callee",
+            )),
+        }
+    });
 
-                    if index >= arr.len() {
-                        return Err(format!("Index out of bounds"));
-                    }
+    map.insert("in".to_string(), |args, callee| {
+        if args.len() != 1 {
+            return Err(DslError::arity("'in' method expects exactly one argument"));
+        }
+
+        if callee.is_none() {
+            return Err(DslError::type_mismatch("Cannot call 'in' on null"));
+        }
+
+        let callee = callee.unwrap();
+        let arg = &args[0];
 
-                    match &arr[index] {
-                        serde_json::Value::Null => Ok(DewSchemaLanguageResult::Null),
-                        serde_json::Value::Bool(b) => Ok(DewSchemaLanguageResult::Boolean(*b)),
-                        serde_json::Value::Number(n) => Ok(DewSchemaLanguageResult::Number(
-                            n.as_f64().ok_or("Invalid number")?,
-                        )),
-                        serde_json::Value::String(s) => {
-                            Ok(DewSchemaLanguageResult::String(s.clone()))
-                        }
-                        serde_json::Value::Array(_) | serde_json::Value::Object(_) => {
-                            Ok(DewSchemaLanguageResult::Value(arr[index].clone()))
-                        }
+        match arg {
+            DewSchemaLanguageResult::Value(serde_json::Value::Array(arr)) => {
+                for item in arr {
+                    if DewSchemaLanguageResult::from_json(item)? == *callee {
+                        return Ok(DewSchemaLanguageResult::Boolean(true));
                     }
                 }
-                _ => Err(format!("'get_index' method can only be called on arrays")),
-            }
-        }),
-    );
-
-    map.insert(
-        "length".to_string(),
-        Box::new(|args, callee| {
-            if !args.is_empty() {
-                return Err(format!("'length' method expects no arguments"));
+
+                Ok(DewSchemaLanguageResult::Boolean(false))
             }
+            _ => Err(DslError::type_mismatch(
+                "'in' method expects an array as argument",
+            )),
+        }
+    });
+
+    map
+}
+
+pub fn lazy_functions() -> HashMap<String, DslBuiltinLazyFunction> {
+    let mut map: HashMap<String, DslBuiltinLazyFunction> = HashMap::new();
+
+    map.insert("count_if".to_string(), |params, callee| {
+        let arr = iteration_source("count_if", &params, callee)?;
+        let mut count = 0;
 
-            if callee.is_none() {
-                return Err(format!("Cannot call 'length' on null"));
+        for (index, item) in arr.iter().enumerate() {
+            if expect_boolean(
+                "count_if",
+                params.evaluate_with_element(
+                    0,
+                    &DewSchemaLanguageResult::from_json(item)?,
+                    index,
+                )?,
+            )? {
+                count += 1;
             }
+        }
 
-            match callee.unwrap() {
-                DewSchemaLanguageResult::Value(serde_json::Value::Array(arr)) => {
-                    Ok(DewSchemaLanguageResult::Number(arr.len() as f64))
-                }
-                DewSchemaLanguageResult::String(s) => {
-                    Ok(DewSchemaLanguageResult::Number(s.chars().count() as f64))
-                }
-                _ => Err(format!(
-                    "'length' method expects an array or string as callee"
-                )),
+        Ok(DewSchemaLanguageResult::Number(BigDecimal::from(count)))
+    });
+
+    map.insert("every".to_string(), |params, callee| {
+        let arr = iteration_source("every", &params, callee)?;
+
+        for (index, item) in arr.iter().enumerate() {
+            if !expect_boolean(
+                "every",
+                params.evaluate_with_element(
+                    0,
+                    &DewSchemaLanguageResult::from_json(item)?,
+                    index,
+                )?,
+            )? {
+                return Ok(DewSchemaLanguageResult::Boolean(false));
             }
-        }),
-    );
-
-    map.insert(
-        "in".to_string(),
-        Box::new(|args, callee| {
-            if args.len() != 1 {
-                return Err(format!("'in' method expects exactly one argument"));
+        }
+
+        Ok(DewSchemaLanguageResult::Boolean(true))
+    });
+
+    map.insert("filter".to_string(), |params, callee| {
+        let arr = iteration_source("filter", &params, callee)?;
+        let mut result = Vec::new();
+
+        for (index, item) in arr.iter().enumerate() {
+            if expect_boolean(
+                "filter",
+                params.evaluate_with_element(
+                    0,
+                    &DewSchemaLanguageResult::from_json(item)?,
+                    index,
+                )?,
+            )? {
+                result.push(item.clone());
             }
+        }
 
-            if callee.is_none() {
-                return Err(format!("Cannot call 'in' on null"));
+        Ok(DewSchemaLanguageResult::Value(serde_json::Value::Array(
+            result,
+        )))
+    });
+
+    map.insert("find".to_string(), |params, callee| {
+        let arr = iteration_source("find", &params, callee)?;
+
+        for (index, item) in arr.iter().enumerate() {
+            let item = DewSchemaLanguageResult::from_json(item)?;
+            if expect_boolean("find", params.evaluate_with_element(0, &item, index)?)? {
+                return Ok(item);
             }
+        }
 
-            let callee = callee.unwrap();
-            let arg = &args[0];
+        Ok(DewSchemaLanguageResult::Undefined)
+    });
 
-            match arg {
-                DewSchemaLanguageResult::Value(serde_json::Value::Array(arr)) => {
-                    let contains = arr.iter().any(|item| {
-                        let item_dsl = DewSchemaLanguageResult::Value(item.clone());
-                        item_dsl == *callee
-                    });
-                    Ok(DewSchemaLanguageResult::Boolean(contains))
-                }
-                _ => Err(format!("'in' method expects an array as argument")),
+    map.insert("map".to_string(), |params, callee| {
+        let arr = iteration_source("map", &params, callee)?;
+        let mut result = Vec::new();
+
+        for (index, item) in arr.iter().enumerate() {
+            result.push(
+                params
+                    .evaluate_with_element(0, &DewSchemaLanguageResult::from_json(item)?, index)?
+                    .to_json()?,
+            );
+        }
+
+        Ok(DewSchemaLanguageResult::Value(serde_json::Value::Array(
+            result,
+        )))
+    });
+
+    map.insert("some".to_string(), |params, callee| {
+        let arr = iteration_source("some", &params, callee)?;
+
+        for (index, item) in arr.iter().enumerate() {
+            if expect_boolean(
+                "some",
+                params.evaluate_with_element(
+                    0,
+                    &DewSchemaLanguageResult::from_json(item)?,
+                    index,
+                )?,
+            )? {
+                return Ok(DewSchemaLanguageResult::Boolean(true));
             }
-        }),
-    );
+        }
+
+        Ok(DewSchemaLanguageResult::Boolean(false))
+    });
 
     map
 }
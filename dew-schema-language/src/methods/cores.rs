@@ -1,181 +1,389 @@
 use std::collections::HashMap;
 
-use crate::engine::{DewSchemaLanguageResult, DslFunction};
+use crate::{
+    engine::DewSchemaLanguageResult,
+    error::DslError,
+    gas::DslGasCost,
+    methods::registry::{DslBuiltinFunction, DslBuiltinLazyFunction},
+};
 
-pub fn functions() -> HashMap<String, DslFunction> {
-    let mut map: HashMap<String, DslFunction> = HashMap::new();
+/// Gas charged by each method of this module. Comparisons and `json` grow with the size of
+/// their input; parentheses are free.
+pub const GAS_COSTS: [(&str, DslGasCost); 17] = [
+    ("", DslGasCost::constant(0)),
+    ("and", DslGasCost::constant(1)),
+    ("coalesce", DslGasCost::constant(1)),
+    ("default", DslGasCost::constant(1)),
+    ("equal", DslGasCost::linear(1, 1)),
+    ("gt", DslGasCost::linear(1, 1)),
+    ("gte", DslGasCost::linear(1, 1)),
+    ("if", DslGasCost::constant(1)),
+    ("is_defined", DslGasCost::constant(1)),
+    ("is_null", DslGasCost::constant(1)),
+    ("json", DslGasCost::linear(1, 1)),
+    ("lt", DslGasCost::linear(1, 1)),
+    ("lte", DslGasCost::linear(1, 1)),
+    ("match", DslGasCost::linear(1, 1)),
+    ("not", DslGasCost::constant(1)),
+    ("not_equal", DslGasCost::linear(1, 1)),
+    ("or", DslGasCost::constant(1)),
+];
 
-    map.insert(
-        "".to_string(),
-        Box::new(|args, callee| {
-            if args.len() != 1 {
-                return Err(format!("Method expects exactly one argument"));
-            }
+pub fn functions() -> HashMap<String, DslBuiltinFunction> {
+    let mut map: HashMap<String, DslBuiltinFunction> = HashMap::new();
 
-            if callee.is_some() {
-                return Err(format!("Cannot call method on result"));
-            }
+    map.insert("".to_string(), |args, callee| {
+        if args.len() != 1 {
+            return Err(DslError::arity("Method expects exactly one argument"));
+        }
 
-            Ok(args[0].clone())
-        }),
-    );
+        if callee.is_some() {
+            return Err(DslError::type_mismatch("Cannot call method on result"));
+        }
 
-    map.insert(
-        "and".to_string(),
-        Box::new(|args, callee| {
-            if args.len() < 1 {
-                return Err(format!("'and' method expects at least one argument"));
-            }
+        Ok(args[0].clone())
+    });
+
+    map.insert("equal".to_string(), |args, callee| {
+        if args.len() != 1 {
+            return Err(DslError::arity(
+                "'equal' method expects exactly one argument",
+            ));
+        }
+
+        if callee.is_none() {
+            return Err(DslError::type_mismatch("Cannot call 'equal' on null"));
+        }
+
+        let is_equal = *callee.unwrap() == args[0];
+
+        Ok(DewSchemaLanguageResult::Boolean(is_equal))
+    });
+
+    map.insert("gt".to_string(), |args, callee| {
+        if args.len() != 1 {
+            return Err(DslError::arity("'gt' method expects exactly one argument"));
+        }
+
+        if callee.is_none() {
+            return Err(DslError::type_mismatch("Cannot call 'gt' on null"));
+        }
+
+        match (callee.unwrap(), &args[0]) {
+            (
+                DewSchemaLanguageResult::Number(callee_num),
+                DewSchemaLanguageResult::Number(arg_num),
+            ) => Ok(DewSchemaLanguageResult::Boolean(callee_num > arg_num)),
+            _ => Err(DslError::type_mismatch(
+                "'gt' method expects numeric arguments",
+            )),
+        }
+    });
+
+    map.insert("gte".to_string(), |args, callee| {
+        if args.len() != 1 {
+            return Err(DslError::arity("'gte' method expects exactly one argument"));
+        }
+
+        if callee.is_none() {
+            return Err(DslError::type_mismatch("Cannot call 'gte' on null"));
+        }
+
+        match (callee.unwrap(), &args[0]) {
+            (
+                DewSchemaLanguageResult::Number(callee_num),
+                DewSchemaLanguageResult::Number(arg_num),
+            ) => Ok(DewSchemaLanguageResult::Boolean(callee_num >= arg_num)),
+            _ => Err(DslError::type_mismatch(
+                "'gte' method expects numeric arguments",
+            )),
+        }
+    });
 
-            let callee = callee.unwrap_or(&DewSchemaLanguageResult::Boolean(true));
-            let mut result = true;
-
-            for arg in args {
-                match (callee, arg) {
-                    (
-                        DewSchemaLanguageResult::Boolean(callee_bool),
-                        DewSchemaLanguageResult::Boolean(arg_bool),
-                    ) => {
-                        result = result && *callee_bool && arg_bool;
-                    }
-                    _ => {
-                        return Err(format!("'and' method expects boolean arguments"));
-                    }
+    map.insert("is_defined".to_string(), |args, callee| {
+        if !args.is_empty() {
+            return Err(DslError::arity("'is_defined' method expects no arguments"));
+        }
+
+        match callee {
+            Some(callee) => Ok(DewSchemaLanguageResult::Boolean(
+                *callee != DewSchemaLanguageResult::Undefined,
+            )),
+            None => Err(DslError::type_mismatch("Cannot call 'is_defined' on null")),
+        }
+    });
+
+    map.insert("is_null".to_string(), |args, callee| {
+        if !args.is_empty() {
+            return Err(DslError::arity("'is_null' method expects no arguments"));
+        }
+
+        match callee {
+            Some(callee) => Ok(DewSchemaLanguageResult::Boolean(
+                *callee == DewSchemaLanguageResult::Null,
+            )),
+            None => Err(DslError::type_mismatch("Cannot call 'is_null' on null")),
+        }
+    });
+
+    map.insert("json".to_string(), |args, callee| {
+        if !args.is_empty() {
+            return Err(DslError::arity("'json' method expects no arguments"));
+        }
+
+        if callee.is_none() {
+            return Err(DslError::type_mismatch("Cannot call 'json' on null"));
+        }
+
+        match callee.unwrap() {
+            DewSchemaLanguageResult::String(s) => {
+                match serde_json::from_str::<serde_json::Value>(s) {
+                    Ok(json_value) => DewSchemaLanguageResult::from_json(&json_value),
+                    Err(e) => Err(DslError::evaluation(format!("Failed to parse JSON: {}", e))),
                 }
             }
+            _ => Err(DslError::type_mismatch(
+                "'json' method can only be called on strings",
+            )),
+        }
+    });
 
-            Ok(DewSchemaLanguageResult::Boolean(result))
-        }),
-    );
+    map.insert("lt".to_string(), |args, callee| {
+        if args.len() != 1 {
+            return Err(DslError::arity("'lt' method expects exactly one argument"));
+        }
 
-    map.insert(
-        "equal".to_string(),
-        Box::new(|args, callee| {
-            if args.len() != 1 {
-                return Err(format!("'equal' method expects exactly one argument"));
-            }
+        if callee.is_none() {
+            return Err(DslError::type_mismatch("Cannot call 'lt' on null"));
+        }
 
-            if callee.is_none() {
-                return Err(format!("Cannot call 'equal' on null"));
-            }
+        match (callee.unwrap(), &args[0]) {
+            (
+                DewSchemaLanguageResult::Number(callee_num),
+                DewSchemaLanguageResult::Number(arg_num),
+            ) => Ok(DewSchemaLanguageResult::Boolean(callee_num < arg_num)),
+            _ => Err(DslError::type_mismatch(
+                "'lt' method expects numeric arguments",
+            )),
+        }
+    });
 
-            let is_equal = *callee.unwrap() == args[0];
+    map.insert("lte".to_string(), |args, callee| {
+        if args.len() != 1 {
+            return Err(DslError::arity("'lte' method expects exactly one argument"));
+        }
 
-            Ok(DewSchemaLanguageResult::Boolean(is_equal))
-        }),
-    );
+        if callee.is_none() {
+            return Err(DslError::type_mismatch("Cannot call 'lte' on null"));
+        }
 
-    map.insert(
-        "gte".to_string(),
-        Box::new(|args, callee| {
-            if args.len() != 1 {
-                return Err(format!("'gte' method expects exactly one argument"));
-            }
+        match (callee.unwrap(), &args[0]) {
+            (
+                DewSchemaLanguageResult::Number(callee_num),
+                DewSchemaLanguageResult::Number(arg_num),
+            ) => Ok(DewSchemaLanguageResult::Boolean(callee_num <= arg_num)),
+            _ => Err(DslError::type_mismatch(
+                "'lte' method expects numeric arguments",
+            )),
+        }
+    });
 
-            if callee.is_none() {
-                return Err(format!("Cannot call 'gte' on null"));
-            }
+    map.insert("not".to_string(), |args, callee| {
+        if !args.is_empty() {
+            return Err(DslError::arity("'not' method expects no arguments"));
+        }
+
+        match callee {
+            Some(DewSchemaLanguageResult::Boolean(b)) => Ok(DewSchemaLanguageResult::Boolean(!b)),
+            Some(_) => Err(DslError::type_mismatch(
+                "'not' method can only be called on booleans",
+            )),
+            None => Err(DslError::type_mismatch("Cannot call 'not' on null")),
+        }
+    });
+
+    map.insert("not_equal".to_string(), |args, callee| {
+        if args.len() != 1 {
+            return Err(DslError::arity(
+                "'not_equal' method expects exactly one argument",
+            ));
+        }
+
+        if callee.is_none() {
+            return Err(DslError::type_mismatch("Cannot call 'not_equal' on null"));
+        }
 
-            match (callee.unwrap(), &args[0]) {
-                (
-                    DewSchemaLanguageResult::Number(callee_num),
-                    DewSchemaLanguageResult::Number(arg_num),
-                ) => Ok(DewSchemaLanguageResult::Boolean(callee_num >= arg_num)),
-                _ => Err(format!("'gte' method expects numeric arguments")),
+        let is_equal = *callee.unwrap() == args[0];
+
+        Ok(DewSchemaLanguageResult::Boolean(!is_equal))
+    });
+
+    map
+}
+
+/// Whether a value counts as present for `default` and `coalesce`: anything but `null` and
+/// `undefined`.
+fn is_present(value: &DewSchemaLanguageResult) -> bool {
+    !matches!(
+        value,
+        DewSchemaLanguageResult::Null | DewSchemaLanguageResult::Undefined
+    )
+}
+
+pub fn lazy_functions() -> HashMap<String, DslBuiltinLazyFunction> {
+    let mut map: HashMap<String, DslBuiltinLazyFunction> = HashMap::new();
+
+    map.insert("and".to_string(), |params, callee| {
+        if params.is_empty() {
+            return Err(DslError::arity(
+                "'and' method expects at least one argument",
+            ));
+        }
+
+        match callee {
+            Some(DewSchemaLanguageResult::Boolean(false)) => {
+                return Ok(DewSchemaLanguageResult::Boolean(false));
             }
-        }),
-    );
-
-    map.insert(
-        "json".to_string(),
-        Box::new(|args, callee| {
-            if !args.is_empty() {
-                return Err(format!("'json' method expects no arguments"));
+            Some(DewSchemaLanguageResult::Boolean(true)) | None => {}
+            Some(_) => {
+                return Err(DslError::type_mismatch(
+                    "'and' method expects boolean arguments",
+                ));
             }
+        }
 
-            if callee.is_none() {
-                return Err(format!("Cannot call 'json' on null"));
+        for index in 0..params.len() {
+            match params.evaluate(index)? {
+                DewSchemaLanguageResult::Boolean(false) => {
+                    return Ok(DewSchemaLanguageResult::Boolean(false));
+                }
+                DewSchemaLanguageResult::Boolean(true) => {}
+                _ => {
+                    return Err(DslError::type_mismatch(
+                        "'and' method expects boolean arguments",
+                    ));
+                }
             }
+        }
 
-            match callee.unwrap() {
-                DewSchemaLanguageResult::String(s) => {
-                    match serde_json::from_str::<serde_json::Value>(&s) {
-                        Ok(json_value) => match json_value {
-                            serde_json::Value::Bool(b) => Ok(DewSchemaLanguageResult::Boolean(b)),
-                            serde_json::Value::Number(n) => {
-                                if let Some(f) = n.as_f64() {
-                                    Ok(DewSchemaLanguageResult::Number(f))
-                                } else {
-                                    Err(format!("Number out of range"))
-                                }
-                            }
-                            serde_json::Value::String(s) => Ok(DewSchemaLanguageResult::String(s)),
-                            serde_json::Value::Array(arr) => Ok(DewSchemaLanguageResult::Value(
-                                serde_json::Value::Array(arr),
-                            )),
-                            serde_json::Value::Object(obj) => Ok(DewSchemaLanguageResult::Value(
-                                serde_json::Value::Object(obj),
-                            )),
-                            serde_json::Value::Null => {
-                                Ok(DewSchemaLanguageResult::Value(serde_json::Value::Null))
-                            }
-                        },
-                        Err(e) => Err(format!("Failed to parse JSON: {}", e)),
-                    }
-                }
-                _ => Err(format!("'json' method can only be called on strings")),
+        Ok(DewSchemaLanguageResult::Boolean(true))
+    });
+
+    map.insert("coalesce".to_string(), |params, callee| {
+        // the callee, if any, is the first candidate
+        if let Some(callee) = callee.filter(|callee| is_present(callee)) {
+            return Ok(callee.clone());
+        }
+
+        for index in 0..params.len() {
+            let candidate = params.evaluate(index)?;
+            if is_present(&candidate) {
+                return Ok(candidate);
+            }
+        }
+
+        Ok(DewSchemaLanguageResult::Undefined)
+    });
+
+    map.insert("default".to_string(), |params, callee| {
+        if params.len() != 1 {
+            return Err(DslError::arity(
+                "'default' method expects exactly one argument",
+            ));
+        }
+
+        match callee {
+            Some(callee) if is_present(callee) => Ok(callee.clone()),
+            Some(_) => params.evaluate(0),
+            None => Err(DslError::type_mismatch("Cannot call 'default' on null")),
+        }
+    });
+
+    map.insert("if".to_string(), |params, callee| {
+        // `if(cond, then, else)` or `cond.if(then, else)`
+        let (condition, then_index) = match callee {
+            None if params.len() == 3 => (params.evaluate(0)?, 1),
+            Some(condition) if params.len() == 2 => (condition.clone(), 0),
+            None => {
+                return Err(DslError::arity(
+                    "'if' method expects exactly three arguments",
+                ));
             }
-        }),
-    );
-
-    map.insert(
-        "lte".to_string(),
-        Box::new(|args, callee| {
-            if args.len() != 1 {
-                return Err(format!("'lte' method expects exactly one argument"));
+            Some(_) => {
+                return Err(DslError::arity(
+                    "'if' method called on a condition expects exactly two arguments",
+                ));
             }
+        };
 
-            if callee.is_none() {
-                return Err(format!("Cannot call 'lte' on null"));
+        match condition {
+            DewSchemaLanguageResult::Boolean(true) => params.evaluate(then_index),
+            DewSchemaLanguageResult::Boolean(false) => params.evaluate(then_index + 1),
+            _ => Err(DslError::type_mismatch(
+                "'if' method expects a boolean condition",
+            )),
+        }
+    });
+
+    map.insert("match".to_string(), |params, callee| {
+        // `value.match(case, result, case, result, ..., default?)`
+        if params.is_empty() {
+            return Err(DslError::arity(
+                "'match' method expects at least one argument",
+            ));
+        }
+
+        let value = match callee {
+            Some(value) => value,
+            None => return Err(DslError::type_mismatch("Cannot call 'match' on null")),
+        };
+
+        for case_index in (0..params.len() - 1).step_by(2) {
+            if params.evaluate(case_index)? == *value {
+                return params.evaluate(case_index + 1);
             }
+        }
 
-            match (callee.unwrap(), &args[0]) {
-                (
-                    DewSchemaLanguageResult::Number(callee_num),
-                    DewSchemaLanguageResult::Number(arg_num),
-                ) => Ok(DewSchemaLanguageResult::Boolean(callee_num <= arg_num)),
-                _ => Err(format!("'lte' method expects numeric arguments")),
+        if params.len() % 2 == 1 {
+            params.evaluate(params.len() - 1)
+        } else {
+            Ok(DewSchemaLanguageResult::Undefined)
+        }
+    });
+
+    map.insert("or".to_string(), |params, callee| {
+        if params.is_empty() {
+            return Err(DslError::arity("'or' method expects at least one argument"));
+        }
+
+        match callee {
+            Some(DewSchemaLanguageResult::Boolean(true)) => {
+                return Ok(DewSchemaLanguageResult::Boolean(true));
             }
-        }),
-    );
-
-    map.insert(
-        "or".to_string(),
-        Box::new(|args, callee| {
-            if args.len() < 1 {
-                return Err(format!("'or' method expects at least one argument"));
+            Some(DewSchemaLanguageResult::Boolean(false)) | None => {}
+            Some(_) => {
+                return Err(DslError::type_mismatch(
+                    "'or' method expects boolean arguments",
+                ));
             }
+        }
 
-            let callee = callee.unwrap_or(&DewSchemaLanguageResult::Boolean(false));
-            let mut result = false;
-
-            for arg in args {
-                match (callee, arg) {
-                    (
-                        DewSchemaLanguageResult::Boolean(callee_bool),
-                        DewSchemaLanguageResult::Boolean(arg_bool),
-                    ) => {
-                        result = result || *callee_bool || arg_bool;
-                    }
-                    _ => {
-                        return Err(format!("'or' method expects boolean arguments"));
-                    }
+        for index in 0..params.len() {
+            match params.evaluate(index)? {
+                DewSchemaLanguageResult::Boolean(true) => {
+                    return Ok(DewSchemaLanguageResult::Boolean(true));
+                }
+                DewSchemaLanguageResult::Boolean(false) => {}
+                _ => {
+                    return Err(DslError::type_mismatch(
+                        "'or' method expects boolean arguments",
+                    ));
                 }
             }
+        }
 
-            Ok(DewSchemaLanguageResult::Boolean(result))
-        }),
-    );
+        Ok(DewSchemaLanguageResult::Boolean(false))
+    });
 
     map
 }
@@ -1,142 +1,203 @@
 use std::collections::HashMap;
 
-use crate::engine::{DewSchemaLanguageResult, DslFunction};
-
-pub fn functions() -> HashMap<String, DslFunction> {
-    let mut map: HashMap<String, DslFunction> = HashMap::new();
-
-    map.insert(
-        "add".to_string(),
-        Box::new(|args, callee| {
-            if args.len() != 1 {
-                return Err(format!("'add' method expects exactly one argument"));
-            }
-
-            if callee.is_none() {
-                return Err(format!("Cannot call 'add' on null"));
-            }
-
-            match (callee.unwrap(), &args[0]) {
-                (
-                    DewSchemaLanguageResult::Number(callee_num),
-                    DewSchemaLanguageResult::Number(arg_num),
-                ) => Ok(DewSchemaLanguageResult::Number(callee_num + arg_num)),
-                _ => Err(format!("'add' method expects numeric arguments")),
-            }
-        }),
-    );
-
-    map.insert(
-        "divide".to_string(),
-        Box::new(|args, callee| {
-            if args.len() != 1 {
-                return Err(format!("'divide' method expects exactly one argument"));
-            }
-            if callee.is_none() {
-                return Err(format!("Cannot call 'divide' on null"));
-            }
-            match (callee.unwrap(), &args[0]) {
-                (
-                    DewSchemaLanguageResult::Number(callee_num),
-                    DewSchemaLanguageResult::Number(arg_num),
-                ) => {
-                    if *arg_num == 0.0 {
-                        return Err(format!("Division by zero is not allowed"));
-                    }
-                    Ok(DewSchemaLanguageResult::Number(callee_num / arg_num))
+use crate::{
+    engine::DewSchemaLanguageResult,
+    error::DslError,
+    gas::DslGasCost,
+    methods::registry::DslBuiltinFunction,
+    number::{self, BigDecimal},
+};
+
+use bigdecimal::Zero;
+
+/// Gas charged by each method of this module. Arithmetic grows with the digits of its
+/// operands, parsing a string with its length.
+pub const GAS_COSTS: [(&str, DslGasCost); 8] = [
+    ("add", DslGasCost::linear(1, 1)),
+    ("divide", DslGasCost::linear(1, 1)),
+    ("modulo", DslGasCost::linear(1, 1)),
+    ("multiply", DslGasCost::linear(1, 1)),
+    ("negate", DslGasCost::linear(1, 1)),
+    ("percent", DslGasCost::linear(1, 1)),
+    ("subtract", DslGasCost::linear(1, 1)),
+    ("to_number", DslGasCost::linear(1, 1)),
+];
+
+pub fn functions() -> HashMap<String, DslBuiltinFunction> {
+    let mut map: HashMap<String, DslBuiltinFunction> = HashMap::new();
+
+    map.insert("add".to_string(), |args, callee| {
+        if args.len() != 1 {
+            return Err(DslError::arity("'add' method expects exactly one argument"));
+        }
+
+        if callee.is_none() {
+            return Err(DslError::type_mismatch("Cannot call 'add' on null"));
+        }
+
+        match (callee.unwrap(), &args[0]) {
+            (
+                DewSchemaLanguageResult::Number(callee_num),
+                DewSchemaLanguageResult::Number(arg_num),
+            ) => Ok(DewSchemaLanguageResult::Number(callee_num + arg_num)),
+            _ => Err(DslError::type_mismatch(
+                "'add' method expects numeric arguments",
+            )),
+        }
+    });
+
+    map.insert("divide".to_string(), |args, callee| {
+        if args.len() != 1 {
+            return Err(DslError::arity(
+                "'divide' method expects exactly one argument",
+            ));
+        }
+        if callee.is_none() {
+            return Err(DslError::type_mismatch("Cannot call 'divide' on null"));
+        }
+        match (callee.unwrap(), &args[0]) {
+            (
+                DewSchemaLanguageResult::Number(callee_num),
+                DewSchemaLanguageResult::Number(arg_num),
+            ) => {
+                if arg_num.is_zero() {
+                    return Err(DslError::evaluation("Division by zero is not allowed"));
                 }
-                _ => Err(format!("'divide' method expects numeric arguments")),
-            }
-        }),
-    );
-
-    map.insert(
-        "multiply".to_string(),
-        Box::new(|args, callee| {
-            if args.len() != 1 {
-                return Err(format!("'multiply' method expects exactly one argument"));
-            }
-
-            if callee.is_none() {
-                return Err(format!("Cannot call 'multiply' on null"));
-            }
-
-            match (callee.unwrap(), &args[0]) {
-                (
-                    DewSchemaLanguageResult::Number(callee_num),
-                    DewSchemaLanguageResult::Number(arg_num),
-                ) => Ok(DewSchemaLanguageResult::Number(callee_num * arg_num)),
-                _ => Err(format!("'multiply' method expects numeric arguments")),
-            }
-        }),
-    );
-
-    map.insert(
-        "percent".to_string(),
-        Box::new(|args, callee| {
-            if args.len() != 1 {
-                return Err(format!("'percent' method expects exactly one argument"));
-            }
-
-            if callee.is_none() {
-                return Err(format!("Cannot call 'percent' on null"));
-            }
-
-            match (callee.unwrap(), &args[0]) {
-                (
-                    DewSchemaLanguageResult::Number(callee_num),
-                    DewSchemaLanguageResult::Number(arg_num),
-                ) => Ok(DewSchemaLanguageResult::Number(
-                    callee_num * arg_num / 100.0,
-                )),
-                _ => Err(format!("'percent' method expects numeric arguments")),
-            }
-        }),
-    );
-
-    map.insert(
-        "subtract".to_string(),
-        Box::new(|args, callee| {
-            if args.len() != 1 {
-                return Err(format!("'subtract' method expects exactly one argument"));
-            }
-
-            if callee.is_none() {
-                return Err(format!("Cannot call 'subtract' on null"));
-            }
-
-            match (callee.unwrap(), &args[0]) {
-                (
-                    DewSchemaLanguageResult::Number(callee_num),
-                    DewSchemaLanguageResult::Number(arg_num),
-                ) => Ok(DewSchemaLanguageResult::Number(callee_num - arg_num)),
-                _ => Err(format!("'subtract' method expects numeric arguments")),
-            }
-        }),
-    );
-
-    map.insert(
-        "to_number".to_string(),
-        Box::new(|args, callee| {
-            if !args.is_empty() {
-                return Err(format!("'to_number' method expects no arguments"));
-            }
-            if callee.is_none() {
-                return Err(format!("Cannot call 'to_number' on null"));
-            }
-
-            match callee.unwrap() {
-                DewSchemaLanguageResult::Number(n) => {
-                    Ok(DewSchemaLanguageResult::Number(n.clone()))
+                Ok(DewSchemaLanguageResult::Number(callee_num / arg_num))
+            }
+            _ => Err(DslError::type_mismatch(
+                "'divide' method expects numeric arguments",
+            )),
+        }
+    });
+
+    map.insert("modulo".to_string(), |args, callee| {
+        if args.len() != 1 {
+            return Err(DslError::arity(
+                "'modulo' method expects exactly one argument",
+            ));
+        }
+        if callee.is_none() {
+            return Err(DslError::type_mismatch("Cannot call 'modulo' on null"));
+        }
+        match (callee.unwrap(), &args[0]) {
+            (
+                DewSchemaLanguageResult::Number(callee_num),
+                DewSchemaLanguageResult::Number(arg_num),
+            ) => {
+                if arg_num.is_zero() {
+                    return Err(DslError::evaluation("Division by zero is not allowed"));
                 }
-                DewSchemaLanguageResult::String(s) => s
-                    .parse::<f64>()
-                    .map(DewSchemaLanguageResult::Number)
-                    .map_err(|_| format!("'to_number' method expects a number or string")),
-                _ => Err(format!("'to_number' method expects a number or string")),
-            }
-        }),
-    );
+                Ok(DewSchemaLanguageResult::Number(callee_num % arg_num))
+            }
+            _ => Err(DslError::type_mismatch(
+                "'modulo' method expects numeric arguments",
+            )),
+        }
+    });
+
+    map.insert("multiply".to_string(), |args, callee| {
+        if args.len() != 1 {
+            return Err(DslError::arity(
+                "'multiply' method expects exactly one argument",
+            ));
+        }
+
+        if callee.is_none() {
+            return Err(DslError::type_mismatch("Cannot call 'multiply' on null"));
+        }
+
+        match (callee.unwrap(), &args[0]) {
+            (
+                DewSchemaLanguageResult::Number(callee_num),
+                DewSchemaLanguageResult::Number(arg_num),
+            ) => Ok(DewSchemaLanguageResult::Number(callee_num * arg_num)),
+            _ => Err(DslError::type_mismatch(
+                "'multiply' method expects numeric arguments",
+            )),
+        }
+    });
+
+    map.insert("negate".to_string(), |args, callee| {
+        if !args.is_empty() {
+            return Err(DslError::arity("'negate' method expects no arguments"));
+        }
+
+        match callee {
+            Some(DewSchemaLanguageResult::Number(n)) => Ok(DewSchemaLanguageResult::Number(-n)),
+            Some(_) => Err(DslError::type_mismatch(
+                "'negate' method can only be called on numbers",
+            )),
+            None => Err(DslError::type_mismatch("Cannot call 'negate' on null")),
+        }
+    });
+
+    map.insert("percent".to_string(), |args, callee| {
+        if args.len() != 1 {
+            return Err(DslError::arity(
+                "'percent' method expects exactly one argument",
+            ));
+        }
+
+        if callee.is_none() {
+            return Err(DslError::type_mismatch("Cannot call 'percent' on null"));
+        }
+
+        match (callee.unwrap(), &args[0]) {
+            (
+                DewSchemaLanguageResult::Number(callee_num),
+                DewSchemaLanguageResult::Number(arg_num),
+            ) => Ok(DewSchemaLanguageResult::Number(
+                callee_num * arg_num / BigDecimal::from(100),
+            )),
+            _ => Err(DslError::type_mismatch(
+                "'percent' method expects numeric arguments",
+            )),
+        }
+    });
+
+    map.insert("subtract".to_string(), |args, callee| {
+        if args.len() != 1 {
+            return Err(DslError::arity(
+                "'subtract' method expects exactly one argument",
+            ));
+        }
+
+        if callee.is_none() {
+            return Err(DslError::type_mismatch("Cannot call 'subtract' on null"));
+        }
+
+        match (callee.unwrap(), &args[0]) {
+            (
+                DewSchemaLanguageResult::Number(callee_num),
+                DewSchemaLanguageResult::Number(arg_num),
+            ) => Ok(DewSchemaLanguageResult::Number(callee_num - arg_num)),
+            _ => Err(DslError::type_mismatch(
+                "'subtract' method expects numeric arguments",
+            )),
+        }
+    });
+
+    map.insert("to_number".to_string(), |args, callee| {
+        if !args.is_empty() {
+            return Err(DslError::arity("'to_number' method expects no arguments"));
+        }
+        if callee.is_none() {
+            return Err(DslError::type_mismatch("Cannot call 'to_number' on null"));
+        }
+
+        match callee.unwrap() {
+            DewSchemaLanguageResult::Number(n) => Ok(DewSchemaLanguageResult::Number(n.clone())),
+            DewSchemaLanguageResult::String(s) => number::parse_number(s)
+                .map(DewSchemaLanguageResult::Number)
+                .map_err(|_| {
+                    DslError::type_mismatch("'to_number' method expects a number or string")
+                }),
+            _ => Err(DslError::type_mismatch(
+                "'to_number' method expects a number or string",
+            )),
+        }
+    });
 
     map
 }
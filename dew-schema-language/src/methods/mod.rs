@@ -2,4 +2,6 @@ pub(crate) mod alias;
 pub(crate) mod array;
 pub(crate) mod cores;
 pub(crate) mod math;
+pub(crate) mod registry;
 pub(crate) mod string;
+pub(crate) mod types;
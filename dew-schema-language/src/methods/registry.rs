@@ -0,0 +1,195 @@
+use std::{collections::HashMap, sync::OnceLock};
+
+use crate::{
+    engine::{DewSchemaLanguageResult, DslFunctionCallee, DslFunctionParams, DslLazyParams},
+    error::DslError,
+    gas::DslGasCost,
+    methods::{alias, array, cores, math, string, types},
+};
+
+/// Built-in method receiving its arguments already evaluated.
+pub type DslBuiltinFunction =
+    fn(DslFunctionParams, DslFunctionCallee) -> Result<DewSchemaLanguageResult, DslError>;
+
+/// Built-in method receiving its arguments unevaluated, see [`DslLazyParams`].
+pub type DslBuiltinLazyFunction =
+    fn(DslLazyParams, DslFunctionCallee) -> Result<DewSchemaLanguageResult, DslError>;
+
+/// Built-in methods that give `undefined` a meaning of their own. Every other built-in fails
+/// when called on `undefined` or given an `undefined` argument, see
+/// [`MethodRegistry::reject_undefined`].
+pub(crate) const UNDEFINED_AWARE_METHODS: [&str; 20] = [
+    "",
+    "assert_case_insensitive_equal",
+    "assert_equal",
+    "assert_gte",
+    "assert_lte",
+    "assert_type",
+    "coalesce",
+    "default",
+    "equal",
+    "is_array",
+    "is_bool",
+    "is_defined",
+    "is_integer",
+    "is_null",
+    "is_number",
+    "is_object",
+    "is_string",
+    "match",
+    "not_equal",
+    "type_of",
+];
+
+#[derive(Clone, Copy)]
+pub enum BuiltinMethod {
+    Eager(DslBuiltinFunction),
+    Lazy(DslBuiltinLazyFunction),
+}
+
+/// Every built-in method, keyed by name. Built once per process and shared by all engines.
+///
+/// Method names are unique across the `methods` modules, so lookup order between them does
+/// not matter. Host functions registered on an engine always take precedence over built-ins.
+pub struct MethodRegistry {
+    methods: HashMap<String, BuiltinMethod>,
+    gas_costs: HashMap<String, DslGasCost>,
+}
+
+impl MethodRegistry {
+    pub fn global() -> &'static MethodRegistry {
+        static REGISTRY: OnceLock<MethodRegistry> = OnceLock::new();
+
+        REGISTRY.get_or_init(MethodRegistry::new)
+    }
+
+    fn new() -> Self {
+        let mut registry = Self {
+            methods: HashMap::new(),
+            gas_costs: [
+                &alias::GAS_COSTS[..],
+                &cores::GAS_COSTS,
+                &math::GAS_COSTS,
+                &array::GAS_COSTS,
+                &string::GAS_COSTS,
+                &types::GAS_COSTS,
+            ]
+            .concat()
+            .into_iter()
+            .map(|(method_name, cost)| (method_name.to_string(), cost))
+            .collect(),
+        };
+
+        let eager_functions = [
+            alias::functions(),
+            cores::functions(),
+            math::functions(),
+            array::functions(),
+            string::functions(),
+            types::functions(),
+        ];
+        for functions in eager_functions {
+            for (method_name, func) in functions {
+                registry.register(method_name, BuiltinMethod::Eager(func));
+            }
+        }
+
+        let lazy_functions = [cores::lazy_functions(), array::lazy_functions()];
+        for functions in lazy_functions {
+            for (method_name, func) in functions {
+                registry.register(method_name, BuiltinMethod::Lazy(func));
+            }
+        }
+
+        assert_eq!(
+            registry.gas_costs.len(),
+            registry.methods.len(),
+            "Every built-in method must declare exactly one gas cost"
+        );
+
+        registry
+    }
+
+    fn register(&mut self, method_name: String, method: BuiltinMethod) {
+        let previous = self.methods.insert(method_name.clone(), method);
+
+        assert!(
+            previous.is_none(),
+            "Built-in method '{}' is registered twice",
+            method_name
+        );
+        assert!(
+            self.gas_costs.contains_key(&method_name),
+            "Built-in method '{}' does not declare a gas cost",
+            method_name
+        );
+    }
+
+    pub fn get(&self, method_name: &str) -> Option<BuiltinMethod> {
+        self.methods.get(method_name).copied()
+    }
+
+    pub fn gas_cost(&self, method_name: &str) -> Option<DslGasCost> {
+        self.gas_costs.get(method_name).copied()
+    }
+
+    pub fn contains(&self, method_name: &str) -> bool {
+        self.methods.contains_key(method_name)
+    }
+
+    #[cfg(test)]
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.methods.keys().map(String::as_str)
+    }
+
+    /// Applies the `undefined` propagation rule: unless a built-in is listed in
+    /// [`UNDEFINED_AWARE_METHODS`], an `undefined` callee or evaluated argument is a type
+    /// mismatch naming the method, rather than whatever error the method would report for it.
+    pub fn reject_undefined(
+        &self,
+        method_name: &str,
+        callee: DslFunctionCallee,
+        args: &[DewSchemaLanguageResult],
+    ) -> Result<(), DslError> {
+        if UNDEFINED_AWARE_METHODS.contains(&method_name) {
+            return Ok(());
+        }
+
+        if let Some(DewSchemaLanguageResult::Undefined) = callee {
+            return Err(DslError::type_mismatch(format!(
+                "Cannot call '{}' on undefined, use '?.' or 'default' for missing values",
+                method_name
+            )));
+        }
+
+        if args
+            .iter()
+            .any(|arg| matches!(arg, DewSchemaLanguageResult::Undefined))
+        {
+            return Err(DslError::type_mismatch(format!(
+                "'{}' method received an undefined argument",
+                method_name
+            )));
+        }
+
+        Ok(())
+    }
+
+    /// Calls an eager built-in with already evaluated arguments, used by methods that
+    /// delegate to other built-ins.
+    pub fn call(
+        &self,
+        method_name: &str,
+        args: DslFunctionParams,
+        callee: DslFunctionCallee,
+    ) -> Result<DewSchemaLanguageResult, DslError> {
+        match self.get(method_name) {
+            Some(BuiltinMethod::Eager(func)) => func(args, callee),
+            Some(BuiltinMethod::Lazy(_)) => Err(DslError::evaluation(format!(
+                "Method '{}' cannot be called with evaluated arguments",
+                method_name
+            ))),
+            None => Err(DslError::unknown_method(method_name)),
+        }
+    }
+}
@@ -0,0 +1,105 @@
+use std::collections::HashMap;
+
+use crate::{
+    engine::DewSchemaLanguageResult, error::DslError, gas::DslGasCost,
+    methods::registry::DslBuiltinFunction,
+};
+
+/// Type names accepted by `assert_type`: everything `type_of` reports, plus `integer`.
+pub(crate) const TYPE_NAMES: [&str; 8] = [
+    "array",
+    "boolean",
+    "integer",
+    "null",
+    "number",
+    "object",
+    "string",
+    "undefined",
+];
+
+/// Whether `value` is of the type called `type_name`, one of [`TYPE_NAMES`].
+pub(crate) fn is_of_type(value: &DewSchemaLanguageResult, type_name: &str) -> bool {
+    match type_name {
+        "integer" => matches!(value, DewSchemaLanguageResult::Number(n) if n.is_integer()),
+        type_name => value.type_name() == type_name,
+    }
+}
+
+/// Shared body of the `is_*` methods.
+fn check_type(
+    method_name: &str,
+    type_name: &str,
+    args: &[DewSchemaLanguageResult],
+    callee: Option<&DewSchemaLanguageResult>,
+) -> Result<DewSchemaLanguageResult, DslError> {
+    if !args.is_empty() {
+        return Err(DslError::arity(format!(
+            "'{}' method expects no arguments",
+            method_name
+        )));
+    }
+
+    match callee {
+        Some(callee) => Ok(DewSchemaLanguageResult::Boolean(is_of_type(
+            callee, type_name,
+        ))),
+        None => Err(DslError::type_mismatch(format!(
+            "Cannot call '{}' on null",
+            method_name
+        ))),
+    }
+}
+
+/// Gas charged by each method of this module. Type checks only look at the outermost value.
+pub const GAS_COSTS: [(&str, DslGasCost); 7] = [
+    ("is_array", DslGasCost::constant(1)),
+    ("is_bool", DslGasCost::constant(1)),
+    ("is_integer", DslGasCost::constant(1)),
+    ("is_number", DslGasCost::constant(1)),
+    ("is_object", DslGasCost::constant(1)),
+    ("is_string", DslGasCost::constant(1)),
+    ("type_of", DslGasCost::constant(1)),
+];
+
+pub fn functions() -> HashMap<String, DslBuiltinFunction> {
+    let mut map: HashMap<String, DslBuiltinFunction> = HashMap::new();
+
+    map.insert("is_array".to_string(), |args, callee| {
+        check_type("is_array", "array", &args, callee)
+    });
+
+    map.insert("is_bool".to_string(), |args, callee| {
+        check_type("is_bool", "boolean", &args, callee)
+    });
+
+    map.insert("is_integer".to_string(), |args, callee| {
+        check_type("is_integer", "integer", &args, callee)
+    });
+
+    map.insert("is_number".to_string(), |args, callee| {
+        check_type("is_number", "number", &args, callee)
+    });
+
+    map.insert("is_object".to_string(), |args, callee| {
+        check_type("is_object", "object", &args, callee)
+    });
+
+    map.insert("is_string".to_string(), |args, callee| {
+        check_type("is_string", "string", &args, callee)
+    });
+
+    map.insert("type_of".to_string(), |args, callee| {
+        if !args.is_empty() {
+            return Err(DslError::arity("'type_of' method expects no arguments"));
+        }
+
+        match callee {
+            Some(callee) => Ok(DewSchemaLanguageResult::String(
+                callee.type_name().to_string(),
+            )),
+            None => Err(DslError::type_mismatch("Cannot call 'type_of' on null")),
+        }
+    });
+
+    map
+}
@@ -0,0 +1,62 @@
+//! Exact decimal numbers used by [`DewSchemaLanguageResult::Number`].
+//!
+//! Token amounts such as yoctoNEAR (10^24) or wei do not fit in an `f64`, so every number
+//! in the language is an arbitrary-precision [`BigDecimal`]. JSON numbers are read from
+//! their original text, which requires serde_json's `arbitrary_precision` feature.
+//!
+//! [`DewSchemaLanguageResult::Number`]: crate::engine::DewSchemaLanguageResult::Number
+
+use std::str::FromStr;
+
+use crate::error::DslError;
+
+pub use bigdecimal::{BigDecimal, ToPrimitive};
+
+/// Largest exponent, in either direction, of a number read from a literal or JSON. Work
+/// on a decimal grows with the digits it spans, so adding to or printing `1e100000000`
+/// would take seconds and hundreds of megabytes; such numbers are rejected when read.
+pub const MAX_EXPONENT: i64 = 10_000;
+
+/// Parses a number literal or numeric string, e.g. `"42"`, `"-3.2e3"` or `"1000000000000000000000000"`.
+/// Numbers whose exponent exceeds [`MAX_EXPONENT`] are rejected.
+pub fn parse_number(input: &str) -> Result<BigDecimal, DslError> {
+    let number = BigDecimal::from_str(input)
+        .map_err(|_| DslError::type_mismatch(format!("Invalid number: {}", input)))?;
+    if number.fractional_digit_count().unsigned_abs() > MAX_EXPONENT.unsigned_abs() {
+        return Err(DslError::type_mismatch(format!(
+            "Number {} is out of range, exponents are limited to {}",
+            input, MAX_EXPONENT
+        )));
+    }
+
+    Ok(number)
+}
+
+/// Size of a number for gas metering: the digits of its mantissa plus the magnitude of its
+/// exponent, which is how many digits decimal arithmetic on it has to go through.
+pub fn digit_count(input: &BigDecimal) -> u64 {
+    input
+        .digits()
+        .saturating_add(input.fractional_digit_count().unsigned_abs())
+}
+
+/// Converts an `f64` through its shortest round-trip representation, so `0.1` stays `0.1`.
+pub fn from_f64(input: f64) -> Result<BigDecimal, DslError> {
+    if !input.is_finite() {
+        return Err(DslError::type_mismatch(format!(
+            "Invalid number: {}",
+            input
+        )));
+    }
+
+    parse_number(&input.to_string())
+}
+
+pub(crate) fn from_json_number(input: &serde_json::Number) -> Result<BigDecimal, DslError> {
+    parse_number(&input.to_string())
+}
+
+pub(crate) fn to_json_number(input: &BigDecimal) -> Result<serde_json::Number, DslError> {
+    serde_json::Number::from_str(&input.to_plain_string())
+        .map_err(|_| DslError::type_mismatch(format!("Invalid number: {}", input)))
+}
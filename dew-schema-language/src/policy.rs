@@ -0,0 +1,439 @@
+//! Policies: named rules evaluated together against one root object, each with a severity
+//! that decides whether its failure denies the request or is only reported.
+
+use std::collections::HashSet;
+
+use serde_json::Value;
+
+use crate::{
+    engine::{CompiledExpression, DewSchemaLanguageEngine, DewSchemaLanguageResult},
+    error::DslError,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Severity {
+    /// A failure denies the request.
+    Deny,
+    /// A failure is reported but does not deny the request.
+    Warn,
+    /// Purely informational, e.g. to record which requests match a pattern.
+    Info,
+}
+
+/// A named expression that must evaluate to `true`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PolicyRule {
+    name: String,
+    expression: String,
+    severity: Severity,
+    description: Option<String>,
+    when: Option<String>,
+}
+
+impl PolicyRule {
+    /// Creates a rule with [`Severity::Deny`] that always applies.
+    pub fn new(name: impl Into<String>, expression: impl Into<String>) -> Self {
+        PolicyRule {
+            name: name.into(),
+            expression: expression.into(),
+            severity: Severity::Deny,
+            description: None,
+            when: None,
+        }
+    }
+
+    pub fn with_severity(mut self, severity: Severity) -> Self {
+        self.severity = severity;
+        self
+    }
+
+    pub fn with_description(mut self, description: impl Into<String>) -> Self {
+        self.description = Some(description.into());
+        self
+    }
+
+    /// Only applies the rule when `precondition` evaluates to `true`; otherwise it is skipped.
+    pub fn with_when(mut self, precondition: impl Into<String>) -> Self {
+        self.when = Some(precondition.into());
+        self
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn expression(&self) -> &str {
+        &self.expression
+    }
+
+    pub fn severity(&self) -> Severity {
+        self.severity
+    }
+
+    pub fn description(&self) -> Option<&str> {
+        self.description.as_deref()
+    }
+
+    pub fn when(&self) -> Option<&str> {
+        self.when.as_deref()
+    }
+}
+
+/// An ordered set of rules. By default a rule that cannot be evaluated denies the request,
+/// whatever its severity; see [`Policy::with_deny_on_error`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct Policy {
+    rules: Vec<PolicyRule>,
+    deny_on_error: bool,
+}
+
+impl Default for Policy {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Policy {
+    pub fn new() -> Self {
+        Policy {
+            rules: Vec::new(),
+            deny_on_error: true,
+        }
+    }
+
+    pub fn with_rule(mut self, rule: PolicyRule) -> Self {
+        self.rules.push(rule);
+        self
+    }
+
+    /// Whether a rule that errors denies the request. When `false`, only failed
+    /// [`Severity::Deny`] rules do, and errors are only reported.
+    pub fn with_deny_on_error(mut self, deny_on_error: bool) -> Self {
+        self.deny_on_error = deny_on_error;
+        self
+    }
+
+    pub fn rules(&self) -> &[PolicyRule] {
+        &self.rules
+    }
+
+    pub fn deny_on_error(&self) -> bool {
+        self.deny_on_error
+    }
+}
+
+/// Outcome of one rule.
+#[derive(Debug, Clone, PartialEq)]
+pub enum RuleStatus {
+    Passed,
+    Failed,
+    /// The rule or its precondition could not be evaluated or did not produce a boolean.
+    Errored(DslError),
+    /// The rule's `when` precondition was `false`.
+    Skipped,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RuleReport {
+    pub name: String,
+    pub severity: Severity,
+    pub description: Option<String>,
+    pub status: RuleStatus,
+}
+
+/// The outcome of every rule of a policy, in the order the rules were added.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PolicyReport {
+    rules: Vec<RuleReport>,
+    deny_on_error: bool,
+}
+
+impl PolicyReport {
+    pub fn rules(&self) -> &[RuleReport] {
+        &self.rules
+    }
+
+    pub fn get(&self, name: &str) -> Option<&RuleReport> {
+        self.rules.iter().find(|rule| rule.name == name)
+    }
+
+    /// Whether a [`Severity::Deny`] rule failed, or any rule errored when the policy denies
+    /// on errors.
+    pub fn is_denied(&self) -> bool {
+        self.rules.iter().any(|rule| match rule.status {
+            RuleStatus::Failed => rule.severity == Severity::Deny,
+            RuleStatus::Errored(_) => self.deny_on_error,
+            RuleStatus::Passed | RuleStatus::Skipped => false,
+        })
+    }
+
+    pub fn passed(&self) -> impl Iterator<Item = &RuleReport> {
+        self.with_status(|status| matches!(status, RuleStatus::Passed))
+    }
+
+    pub fn failed(&self) -> impl Iterator<Item = &RuleReport> {
+        self.with_status(|status| matches!(status, RuleStatus::Failed))
+    }
+
+    pub fn errored(&self) -> impl Iterator<Item = &RuleReport> {
+        self.with_status(|status| matches!(status, RuleStatus::Errored(_)))
+    }
+
+    pub fn skipped(&self) -> impl Iterator<Item = &RuleReport> {
+        self.with_status(|status| matches!(status, RuleStatus::Skipped))
+    }
+
+    fn with_status(
+        &self,
+        predicate: impl Fn(&RuleStatus) -> bool,
+    ) -> impl Iterator<Item = &RuleReport> {
+        self.rules
+            .iter()
+            .filter(move |rule| predicate(&rule.status))
+    }
+}
+
+/// A policy rule whose expressions have been compiled by the evaluator's engine.
+struct CompiledRule {
+    rule: PolicyRule,
+    expression: CompiledExpression,
+    when: Option<CompiledExpression>,
+}
+
+/// Evaluates every rule of a policy using an engine's methods and host functions.
+pub struct PolicyEvaluator<'policy_evaluator_lifetime> {
+    engine: &'policy_evaluator_lifetime DewSchemaLanguageEngine,
+    rules: Vec<CompiledRule>,
+    deny_on_error: bool,
+}
+
+impl<'policy_evaluator_lifetime> PolicyEvaluator<'policy_evaluator_lifetime> {
+    /// Compiles every rule and precondition of `policy`. Fails on an unnamed rule, a
+    /// duplicate name, or the first expression that does not compile; the error's path is
+    /// the name of the rule.
+    pub fn new(
+        engine: &'policy_evaluator_lifetime DewSchemaLanguageEngine,
+        policy: &Policy,
+    ) -> Result<Self, DslError> {
+        let mut names = HashSet::new();
+        let mut rules = Vec::with_capacity(policy.rules.len());
+
+        for rule in &policy.rules {
+            if rule.name.is_empty() {
+                return Err(DslError::invalid_policy("A policy rule must have a name"));
+            }
+            if !names.insert(rule.name.as_str()) {
+                return Err(DslError::invalid_policy(format!(
+                    "Duplicate policy rule '{}'",
+                    rule.name
+                )));
+            }
+
+            let compile = |expression: &str| {
+                engine
+                    .compile(expression)
+                    .map_err(|e| e.or_path(|| rule.name.clone()))
+            };
+
+            rules.push(CompiledRule {
+                rule: rule.clone(),
+                expression: compile(&rule.expression)?,
+                when: rule.when.as_deref().map(compile).transpose()?,
+            });
+        }
+
+        Ok(PolicyEvaluator {
+            engine,
+            rules,
+            deny_on_error: policy.deny_on_error,
+        })
+    }
+
+    /// Evaluates the policy against the engine's root object.
+    pub fn evaluate(&self) -> PolicyReport {
+        self.evaluate_root(self.engine.root())
+    }
+
+    /// Evaluates the policy against `root_object` instead of the engine's root.
+    pub fn evaluate_root(&self, root_object: &Value) -> PolicyReport {
+        let rules = self
+            .rules
+            .iter()
+            .map(|compiled| RuleReport {
+                name: compiled.rule.name.clone(),
+                severity: compiled.rule.severity,
+                description: compiled.rule.description.clone(),
+                status: self.evaluate_rule(compiled, root_object),
+            })
+            .collect();
+
+        PolicyReport {
+            rules,
+            deny_on_error: self.deny_on_error,
+        }
+    }
+
+    fn evaluate_rule(&self, compiled: &CompiledRule, root_object: &Value) -> RuleStatus {
+        if let Some(when) = &compiled.when {
+            match self.evaluate_condition(when, root_object) {
+                Ok(true) => {}
+                Ok(false) => return RuleStatus::Skipped,
+                Err(e) => return RuleStatus::Errored(e),
+            }
+        }
+
+        match self.evaluate_condition(&compiled.expression, root_object) {
+            Ok(true) => RuleStatus::Passed,
+            Ok(false) => RuleStatus::Failed,
+            Err(e) => RuleStatus::Errored(e),
+        }
+    }
+
+    fn evaluate_condition(
+        &self,
+        expression: &CompiledExpression,
+        root_object: &Value,
+    ) -> Result<bool, DslError> {
+        match self
+            .engine
+            .evaluate_compiled_with_root(expression, root_object)?
+        {
+            DewSchemaLanguageResult::Boolean(result) => Ok(result),
+            result => Err(DslError::type_mismatch(format!(
+                "A policy rule must evaluate to a boolean, got {}",
+                result.type_name()
+            ))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::DslErrorKind;
+    use serde_json::json;
+    use std::collections::HashMap;
+
+    fn engine() -> DewSchemaLanguageEngine {
+        DewSchemaLanguageEngine::from_value(
+            json!({
+                "receiver_id": "usdc.near",
+                "method_name": "ft_transfer",
+                "args": { "amount": 5000 }
+            }),
+            HashMap::new(),
+        )
+    }
+
+    fn policy() -> Policy {
+        Policy::new()
+            .with_rule(
+                PolicyRule::new("known_receiver", r#"$.receiver_id.in(["usdc.near"])"#)
+                    .with_description("Only transfers of USDC"),
+            )
+            .with_rule(
+                PolicyRule::new("large_transfer", "$.args.amount <= 1000")
+                    .with_severity(Severity::Warn),
+            )
+            .with_rule(
+                PolicyRule::new("storage_deposit", "$.args.amount == 1")
+                    .with_when(r#"$.method_name == "storage_deposit""#),
+            )
+            .with_rule(
+                PolicyRule::new("memo", "$.args.memo.length() < 64").with_severity(Severity::Info),
+            )
+    }
+
+    #[test]
+    fn test_policy_report() {
+        let engine = engine();
+        let report = PolicyEvaluator::new(&engine, &policy()).unwrap().evaluate();
+
+        fn names<'a>(rules: impl Iterator<Item = &'a RuleReport>) -> Vec<&'a str> {
+            rules.map(|rule| rule.name.as_str()).collect()
+        }
+        assert_eq!(names(report.passed()), vec!["known_receiver"]);
+        assert_eq!(names(report.failed()), vec!["large_transfer"]);
+        assert_eq!(names(report.skipped()), vec!["storage_deposit"]);
+        assert_eq!(names(report.errored()), vec!["memo"]);
+
+        let known_receiver = report.get("known_receiver").unwrap();
+        assert_eq!(known_receiver.severity, Severity::Deny);
+        assert_eq!(
+            known_receiver.description.as_deref(),
+            Some("Only transfers of USDC")
+        );
+        match &report.get("memo").unwrap().status {
+            RuleStatus::Errored(e) => assert_eq!(e.kind, DslErrorKind::TypeMismatch),
+            other => panic!("expected an error, got {:?}", other),
+        }
+
+        // the failing rule only warns, but the erroring one denies by default
+        assert!(report.is_denied());
+    }
+
+    #[test]
+    fn test_policy_deny_on_error() {
+        let engine = engine();
+        let lenient = policy().with_deny_on_error(false);
+        let report = PolicyEvaluator::new(&engine, &lenient).unwrap().evaluate();
+
+        assert!(!report.is_denied());
+
+        let root = json!({
+            "receiver_id": "usdc.near",
+            "method_name": "storage_deposit",
+            "args": { "amount": 2, "memo": "" }
+        });
+        let report = PolicyEvaluator::new(&engine, &lenient)
+            .unwrap()
+            .evaluate_root(&root);
+
+        assert_eq!(
+            report.get("storage_deposit").unwrap().status,
+            RuleStatus::Failed
+        );
+        assert!(report.is_denied());
+    }
+
+    #[test]
+    fn test_policy_rule_errors() {
+        let engine = engine();
+        let report = PolicyEvaluator::new(
+            &engine,
+            &Policy::new()
+                .with_rule(PolicyRule::new("amount", "$.args.amount"))
+                .with_rule(PolicyRule::new("when", "true").with_when("$.args.amount")),
+        )
+        .unwrap()
+        .evaluate();
+
+        for name in ["amount", "when"] {
+            match &report.get(name).unwrap().status {
+                RuleStatus::Errored(e) => assert_eq!(
+                    e.message,
+                    "A policy rule must evaluate to a boolean, got number"
+                ),
+                other => panic!("expected an error, got {:?}", other),
+            }
+        }
+
+        let error = |policy: Policy| PolicyEvaluator::new(&engine, &policy).err().unwrap();
+
+        let duplicate = error(
+            Policy::new()
+                .with_rule(PolicyRule::new("a", "true"))
+                .with_rule(PolicyRule::new("a", "false")),
+        );
+        assert_eq!(duplicate.kind, DslErrorKind::InvalidPolicy);
+        assert_eq!(duplicate.message, "Duplicate policy rule 'a'");
+
+        let unnamed = error(Policy::new().with_rule(PolicyRule::new("", "true")));
+        assert_eq!(unnamed.kind, DslErrorKind::InvalidPolicy);
+
+        let unknown =
+            error(Policy::new().with_rule(PolicyRule::new("b", "true").with_when("nope()")));
+        assert_eq!(unknown.kind, DslErrorKind::UnknownMethod);
+        assert_eq!(unknown.path.as_deref(), Some("b"));
+    }
+}
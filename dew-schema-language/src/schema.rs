@@ -0,0 +1,819 @@
+//! Declarative schemas: documents that map paths in the root object to rules written in
+//! the DSL, checked field by field by a [`SchemaValidator`].
+//!
+//! ```json
+//! {
+//!     "fields": {
+//!         "receiver_id": "value.in([\"usdc.near\", \"usdt.near\"])",
+//!         "args.amount": { "rule": "gt(0) && value <= 1000" },
+//!         "args.memo": { "rule": "length() <= 64", "required": false },
+//!         "actions": {
+//!             "rule": "value.length() > 0",
+//!             "items": { "fields": { "method_name": "value == \"ft_transfer\"" } }
+//!         }
+//!     }
+//! }
+//! ```
+
+use serde_json::Value;
+
+use crate::{
+    engine::{CompiledExpression, DewSchemaLanguageEngine, DewSchemaLanguageResult},
+    error::DslError,
+    expression::DewSchemaLanguageParser,
+};
+
+/// A parsed schema document. Rules are checked for syntax when the document is read and
+/// resolved against an engine's methods when a [`SchemaValidator`] is built from it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Schema {
+    fields: Vec<(String, FieldSchema)>,
+}
+
+/// The rule and nested schemas of one field.
+///
+/// A field is written either as a rule string, which is short for `{ "rule": "..." }`, or
+/// as an object with these keys, all optional:
+///
+/// - `rule`: an expression that must be `true`, with the field's value bound as `value` and
+///   used as the callee of a leading bare call, so `gt(0)` checks `value.gt(0)`;
+/// - `required`: whether a missing field fails the schema, `true` by default;
+/// - `fields`: schemas for properties of the field, which must then be an object;
+/// - `items`: a schema for every element of the field, which must then be an array.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FieldSchema {
+    rule: Option<String>,
+    required: bool,
+    fields: Vec<(String, FieldSchema)>,
+    items: Option<Box<FieldSchema>>,
+}
+
+impl Schema {
+    pub fn from_json(document: &str) -> Result<Self, DslError> {
+        let document: Value = serde_json::from_str(document)
+            .map_err(|e| DslError::invalid_schema(format!("Invalid schema JSON: {}", e)))?;
+
+        Self::from_value(&document)
+    }
+
+    pub fn from_toml(document: &str) -> Result<Self, DslError> {
+        let document: toml::Value = toml::from_str(document)
+            .map_err(|e| DslError::invalid_schema(format!("Invalid schema TOML: {}", e)))?;
+        let document = serde_json::to_value(document)
+            .map_err(|e| DslError::invalid_schema(format!("Invalid schema TOML: {}", e)))?;
+
+        Self::from_value(&document)
+    }
+
+    /// Reads a schema document that has already been parsed, e.g. from a larger config file.
+    pub fn from_value(document: &Value) -> Result<Self, DslError> {
+        let document = document
+            .as_object()
+            .ok_or_else(|| DslError::invalid_schema("A schema document must be an object"))?;
+
+        for key in document.keys() {
+            if key != "fields" {
+                return Err(DslError::invalid_schema(format!(
+                    "Unknown key '{}' in schema document",
+                    key
+                )));
+            }
+        }
+
+        Ok(Schema {
+            fields: parse_fields(document.get("fields"), "")?,
+        })
+    }
+
+    pub fn fields(&self) -> &[(String, FieldSchema)] {
+        &self.fields
+    }
+}
+
+impl FieldSchema {
+    pub fn rule(&self) -> Option<&str> {
+        self.rule.as_deref()
+    }
+
+    pub fn is_required(&self) -> bool {
+        self.required
+    }
+
+    pub fn fields(&self) -> &[(String, FieldSchema)] {
+        &self.fields
+    }
+
+    pub fn items(&self) -> Option<&FieldSchema> {
+        self.items.as_deref()
+    }
+}
+
+fn parse_fields(
+    fields: Option<&Value>,
+    parent: &str,
+) -> Result<Vec<(String, FieldSchema)>, DslError> {
+    let fields = match fields {
+        None => return Ok(Vec::new()),
+        Some(Value::Object(fields)) => fields,
+        Some(_) => {
+            return Err(DslError::invalid_schema("'fields' must be an object")
+                .or_path(|| parent.to_string()))
+        }
+    };
+
+    fields
+        .iter()
+        .map(|(key, field)| {
+            let valid =
+                split_key(key).is_some_and(|segments| !segments.iter().any(String::is_empty));
+
+            if !valid {
+                return Err(DslError::invalid_schema(format!(
+                    "Invalid field path '{}'",
+                    key
+                )));
+            }
+
+            Ok((key.clone(), parse_field(field, &join_path(parent, key))?))
+        })
+        .collect()
+}
+
+fn parse_field(field: &Value, path: &str) -> Result<FieldSchema, DslError> {
+    let mut schema = FieldSchema {
+        rule: None,
+        required: true,
+        fields: Vec::new(),
+        items: None,
+    };
+
+    let field = match field {
+        Value::String(rule) => {
+            schema.rule = Some(parse_rule(rule, path)?);
+            return Ok(schema);
+        }
+        Value::Object(field) => field,
+        _ => {
+            return Err(
+                DslError::invalid_schema("A field must be a rule string or an object")
+                    .or_path(|| path.to_string()),
+            )
+        }
+    };
+
+    for (key, value) in field {
+        match (key.as_str(), value) {
+            ("rule", Value::String(rule)) => schema.rule = Some(parse_rule(rule, path)?),
+            ("required", Value::Bool(required)) => schema.required = *required,
+            ("fields", _) => schema.fields = parse_fields(Some(value), path)?,
+            ("items", _) => {
+                schema.items = Some(Box::new(parse_field(value, &format!("{}[]", path))?))
+            }
+            ("rule", _) => {
+                return Err(DslError::invalid_schema("'rule' must be a string")
+                    .or_path(|| path.to_string()));
+            }
+            ("required", _) => {
+                return Err(DslError::invalid_schema("'required' must be a boolean")
+                    .or_path(|| path.to_string()));
+            }
+            _ => {
+                return Err(
+                    DslError::invalid_schema(format!("Unknown key '{}' in field", key))
+                        .or_path(|| path.to_string()),
+                );
+            }
+        }
+    }
+
+    Ok(schema)
+}
+
+/// Checks the rule's syntax so a broken document is rejected when it is read.
+fn parse_rule(rule: &str, path: &str) -> Result<String, DslError> {
+    DewSchemaLanguageParser::consume(rule).map_err(|e| e.or_path(|| path.to_string()))?;
+
+    Ok(rule.to_string())
+}
+
+/// Splits a field key into property names at every `.` not escaped as `\.`, so a property
+/// whose name contains a dot can still be addressed. `\\` stands for a backslash, and any
+/// other escape makes the key invalid.
+fn split_key(key: &str) -> Option<Vec<String>> {
+    let mut segments = Vec::new();
+    let mut segment = String::new();
+    let mut chars = key.chars();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => segment.push(chars.next().filter(|c| matches!(c, '.' | '\\'))?),
+            '.' => segments.push(std::mem::take(&mut segment)),
+            c => segment.push(c),
+        }
+    }
+    segments.push(segment);
+
+    Some(segments)
+}
+
+fn join_path(parent: &str, key: &str) -> String {
+    if parent.is_empty() {
+        key.to_string()
+    } else {
+        format!("{}.{}", parent, key)
+    }
+}
+
+/// Outcome of checking one field.
+#[derive(Debug, Clone, PartialEq)]
+pub enum FieldStatus {
+    /// The field is present and its rule, if any, evaluated to `true`.
+    Passed,
+    /// The field's rule evaluated to `false`.
+    Failed,
+    /// A required field is missing.
+    Missing,
+    /// An optional field is missing, so nothing was checked.
+    Skipped,
+    /// The rule could not be evaluated, did not produce a boolean, or the field does not
+    /// have the shape its nested schema expects.
+    Error(DslError),
+}
+
+impl FieldStatus {
+    /// Whether the field satisfies the schema.
+    pub fn is_ok(&self) -> bool {
+        matches!(self, FieldStatus::Passed | FieldStatus::Skipped)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct FieldReport {
+    /// Where the field is in the root object, e.g. `actions[0].method_name`.
+    pub path: String,
+    pub status: FieldStatus,
+}
+
+/// One entry per checked field, with nested fields after their parent. Fields are checked in
+/// the order of their keys, sorted by bytes, since schema documents are read without keeping
+/// the order of their objects.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SchemaReport {
+    fields: Vec<FieldReport>,
+}
+
+impl SchemaReport {
+    pub fn is_valid(&self) -> bool {
+        self.fields.iter().all(|field| field.status.is_ok())
+    }
+
+    pub fn fields(&self) -> &[FieldReport] {
+        &self.fields
+    }
+
+    pub fn get(&self, path: &str) -> Option<&FieldStatus> {
+        self.fields
+            .iter()
+            .find(|field| field.path == path)
+            .map(|field| &field.status)
+    }
+
+    /// The fields that do not satisfy the schema.
+    pub fn failures(&self) -> impl Iterator<Item = &FieldReport> {
+        self.fields.iter().filter(|field| !field.status.is_ok())
+    }
+}
+
+/// A field schema whose rule has been compiled by the validator's engine.
+struct CompiledField {
+    key: String,
+    segments: Vec<String>,
+    rule: Option<CompiledExpression>,
+    required: bool,
+    fields: Vec<CompiledField>,
+    items: Option<Box<CompiledField>>,
+}
+
+impl CompiledField {
+    fn compile(
+        engine: &DewSchemaLanguageEngine,
+        key: &str,
+        schema: &FieldSchema,
+        path: &str,
+    ) -> Result<Self, DslError> {
+        let rule = match &schema.rule {
+            Some(rule) => Some(
+                engine
+                    .compile(rule)
+                    .map_err(|e| e.or_path(|| path.to_string()))?,
+            ),
+            None => None,
+        };
+
+        Ok(CompiledField {
+            key: key.to_string(),
+            segments: split_key(key).expect("keys are checked when the schema is read"),
+            rule,
+            required: schema.required,
+            fields: compile_fields(engine, &schema.fields, path)?,
+            items: match &schema.items {
+                Some(items) => Some(Box::new(Self::compile(
+                    engine,
+                    "",
+                    items,
+                    &format!("{}[]", path),
+                )?)),
+                None => None,
+            },
+        })
+    }
+}
+
+fn compile_fields(
+    engine: &DewSchemaLanguageEngine,
+    fields: &[(String, FieldSchema)],
+    parent: &str,
+) -> Result<Vec<CompiledField>, DslError> {
+    fields
+        .iter()
+        .map(|(key, field)| CompiledField::compile(engine, key, field, &join_path(parent, key)))
+        .collect()
+}
+
+/// Checks root objects against a schema using an engine's methods and host functions.
+pub struct SchemaValidator<'validator_lifetime> {
+    engine: &'validator_lifetime DewSchemaLanguageEngine,
+    fields: Vec<CompiledField>,
+}
+
+impl<'validator_lifetime> SchemaValidator<'validator_lifetime> {
+    /// Compiles every rule of `schema`, failing on the first one that calls an unknown method.
+    pub fn new(
+        engine: &'validator_lifetime DewSchemaLanguageEngine,
+        schema: &Schema,
+    ) -> Result<Self, DslError> {
+        Ok(SchemaValidator {
+            engine,
+            fields: compile_fields(engine, &schema.fields, "")?,
+        })
+    }
+
+    /// Checks the engine's root object.
+    pub fn validate(&self) -> SchemaReport {
+        self.validate_root(self.engine.root())
+    }
+
+    /// Checks `root_object` instead of the engine's root. `$` in rules refers to it too.
+    pub fn validate_root(&self, root_object: &Value) -> SchemaReport {
+        let mut report = SchemaReport { fields: Vec::new() };
+
+        self.validate_fields(&self.fields, root_object, root_object, "", &mut report);
+
+        report
+    }
+
+    fn validate_fields(
+        &self,
+        fields: &[CompiledField],
+        object: &Value,
+        root_object: &Value,
+        parent: &str,
+        report: &mut SchemaReport,
+    ) {
+        for field in fields {
+            let value = field
+                .segments
+                .iter()
+                .try_fold(object, |value, key| value.get(key));
+
+            self.validate_field(
+                field,
+                value,
+                root_object,
+                join_path(parent, &field.key),
+                report,
+            );
+        }
+    }
+
+    fn validate_field(
+        &self,
+        field: &CompiledField,
+        value: Option<&Value>,
+        root_object: &Value,
+        path: String,
+        report: &mut SchemaReport,
+    ) {
+        let Some(value) = value else {
+            report.fields.push(FieldReport {
+                path,
+                status: if field.required {
+                    FieldStatus::Missing
+                } else {
+                    FieldStatus::Skipped
+                },
+            });
+            return;
+        };
+
+        let status = match &field.rule {
+            Some(rule) => self.check_rule(rule, value, root_object),
+            None => FieldStatus::Passed,
+        };
+        let status = match status {
+            FieldStatus::Passed if field.items.is_some() && !value.is_array() => {
+                FieldStatus::Error(DslError::type_mismatch(format!(
+                    "Expected an array, got {}",
+                    type_name(value)
+                )))
+            }
+            FieldStatus::Passed if !field.fields.is_empty() && !value.is_object() => {
+                FieldStatus::Error(DslError::type_mismatch(format!(
+                    "Expected an object, got {}",
+                    type_name(value)
+                )))
+            }
+            status => status,
+        };
+
+        report.fields.push(FieldReport {
+            path: path.clone(),
+            status,
+        });
+
+        if value.is_object() {
+            self.validate_fields(&field.fields, value, root_object, &path, report);
+        }
+
+        if let (Some(items), Value::Array(elements)) = (&field.items, value) {
+            for (index, element) in elements.iter().enumerate() {
+                self.validate_field(
+                    items,
+                    Some(element),
+                    root_object,
+                    format!("{}[{}]", path, index),
+                    report,
+                );
+            }
+        }
+    }
+
+    fn check_rule(
+        &self,
+        rule: &CompiledExpression,
+        value: &Value,
+        root_object: &Value,
+    ) -> FieldStatus {
+        let result = DewSchemaLanguageResult::from_json(value).and_then(|value| {
+            self.engine
+                .evaluate_compiled_with_value(rule, root_object, &value)
+        });
+
+        match result {
+            Ok(DewSchemaLanguageResult::Boolean(true)) => FieldStatus::Passed,
+            Ok(DewSchemaLanguageResult::Boolean(false)) => FieldStatus::Failed,
+            Ok(result) => FieldStatus::Error(DslError::type_mismatch(format!(
+                "A rule must evaluate to a boolean, got {}",
+                result.type_name()
+            ))),
+            Err(e) => FieldStatus::Error(e),
+        }
+    }
+}
+
+fn type_name(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "boolean",
+        Value::Number(_) => "number",
+        Value::String(_) => "string",
+        Value::Array(_) => "array",
+        Value::Object(_) => "object",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::DslErrorKind;
+    use serde_json::json;
+    use std::collections::HashMap;
+
+    fn engine(root_object: Value) -> DewSchemaLanguageEngine {
+        DewSchemaLanguageEngine::from_value(root_object, HashMap::new())
+    }
+
+    fn transfer() -> Value {
+        json!({
+            "receiver_id": "usdc.near",
+            "args": { "amount": 500, "memo": "rent" },
+            "actions": [
+                { "method_name": "ft_transfer", "deposit": 1 },
+                { "method_name": "storage_deposit", "deposit": 0 }
+            ]
+        })
+    }
+
+    #[test]
+    fn test_schema_from_json_and_toml() {
+        let from_json = Schema::from_json(
+            r#"{
+                "fields": {
+                    "receiver_id": "value.in([\"usdc.near\"])",
+                    "args.memo": { "rule": "value.length() <= 64", "required": false },
+                    "actions": { "items": { "fields": { "deposit": "value >= 0" } } }
+                }
+            }"#,
+        )
+        .unwrap();
+        let from_toml = Schema::from_toml(
+            r#"
+                [fields]
+                receiver_id = 'value.in(["usdc.near"])'
+                "args.memo" = { rule = "value.length() <= 64", required = false }
+
+                [fields.actions.items.fields]
+                deposit = "value >= 0"
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(from_json, from_toml);
+
+        let (key, memo) = &from_json.fields()[1];
+        assert_eq!(key, "args.memo");
+        assert_eq!(memo.rule(), Some("value.length() <= 64"));
+        assert!(!memo.is_required());
+        assert_eq!(
+            from_json.fields()[0].1.items().unwrap().fields()[0]
+                .1
+                .rule(),
+            Some("value >= 0")
+        );
+    }
+
+    #[test]
+    fn test_schema_document_errors() {
+        let error = |document: &str| Schema::from_json(document).unwrap_err();
+
+        assert_eq!(error("{").kind, DslErrorKind::InvalidSchema);
+        assert_eq!(error("[]").kind, DslErrorKind::InvalidSchema);
+        assert_eq!(
+            error(r#"{ "rules": {} }"#).message,
+            "Unknown key 'rules' in schema document"
+        );
+
+        let unknown_key =
+            error(r#"{ "fields": { "args": { "fields": { "x": { "rul": "" } } } } }"#);
+        assert_eq!(unknown_key.message, "Unknown key 'rul' in field");
+        assert_eq!(unknown_key.path.as_deref(), Some("args.x"));
+
+        let required = error(r#"{ "fields": { "x": { "required": "yes" } } }"#);
+        assert_eq!(required.message, "'required' must be a boolean");
+
+        let path = error(r#"{ "fields": { "args..amount": "value > 0" } }"#);
+        assert_eq!(path.message, "Invalid field path 'args..amount'");
+        let escape = error(r#"{ "fields": { "args\\amount": "value > 0" } }"#);
+        assert_eq!(escape.message, "Invalid field path 'args\\amount'");
+
+        // rules are parsed when the document is read
+        let syntax = error(r#"{ "fields": { "actions": { "items": "value > (" } } }"#);
+        assert_eq!(syntax.kind, DslErrorKind::Parse);
+        assert_eq!(syntax.path.as_deref(), Some("actions[]"));
+    }
+
+    #[test]
+    fn test_validate_fields() {
+        let engine = engine(transfer());
+        let schema = Schema::from_json(
+            r#"{
+                "fields": {
+                    "receiver_id": "value.in([\"usdc.near\", \"usdt.near\"])",
+                    "args.amount": "value > 0 && value <= $.limit.default(100)",
+                    "args.memo": { "rule": "value.length() <= 2", "required": false },
+                    "args.fee": { "rule": "value > 0", "required": false },
+                    "signer_id": {}
+                }
+            }"#,
+        )
+        .unwrap();
+        let report = SchemaValidator::new(&engine, &schema).unwrap().validate();
+
+        assert_eq!(report.get("receiver_id"), Some(&FieldStatus::Passed));
+        assert_eq!(report.get("args.amount"), Some(&FieldStatus::Failed));
+        assert_eq!(report.get("args.memo"), Some(&FieldStatus::Failed));
+        assert_eq!(report.get("args.fee"), Some(&FieldStatus::Skipped));
+        assert_eq!(report.get("signer_id"), Some(&FieldStatus::Missing));
+        assert!(!report.is_valid());
+        assert_eq!(
+            report
+                .failures()
+                .map(|field| field.path.as_str())
+                .collect::<Vec<_>>(),
+            vec!["args.amount", "args.memo", "signer_id"]
+        );
+
+        // `$` refers to the validated root
+        let mut root = transfer();
+        root["limit"] = json!(1000);
+        root["signer_id"] = json!("alice.near");
+        root["args"]["memo"] = json!("ok");
+        assert!(SchemaValidator::new(&engine, &schema)
+            .unwrap()
+            .validate_root(&root)
+            .is_valid());
+    }
+
+    #[test]
+    fn test_rules_call_on_field() {
+        let engine = engine(json!({ "limit": 1000, "args": { "amount": 500, "memo": "rent" } }));
+        let schema = Schema::from_json(
+            r#"{
+                "fields": {
+                    "args.amount": "gte(0) && value <= $.limit",
+                    "args.memo": "length() <= 2",
+                    "limit": "(value > 0)",
+                    "args": "is_object()"
+                }
+            }"#,
+        )
+        .unwrap();
+        let report = SchemaValidator::new(&engine, &schema).unwrap().validate();
+
+        // a leading bare call is made on the field, the rest of the rule uses `value`
+        assert_eq!(report.get("args.amount"), Some(&FieldStatus::Passed));
+        assert_eq!(report.get("args.memo"), Some(&FieldStatus::Failed));
+        assert_eq!(report.get("args"), Some(&FieldStatus::Passed));
+        // parentheses are not a method call on the field
+        assert_eq!(report.get("limit"), Some(&FieldStatus::Passed));
+
+        let root = json!({ "limit": 100, "args": { "amount": -1, "memo": "ok" } });
+        let report = SchemaValidator::new(&engine, &schema)
+            .unwrap()
+            .validate_root(&root);
+        assert_eq!(report.get("args.amount"), Some(&FieldStatus::Failed));
+        assert_eq!(report.get("args.memo"), Some(&FieldStatus::Passed));
+    }
+
+    #[test]
+    fn test_rules_bind_value() {
+        let engine = engine(json!({ "limit": 1000, "args": { "amount": 500, "fee": 0 } }));
+        let schema = Schema::from_json(
+            r#"{
+                "fields": {
+                    "args.amount": "value > 0 && $.limit >= value",
+                    "args.fee": "value > 0",
+                    "limit": "$.args.amount.lt(value)"
+                }
+            }"#,
+        )
+        .unwrap();
+        let report = SchemaValidator::new(&engine, &schema).unwrap().validate();
+
+        assert_eq!(report.get("args.amount"), Some(&FieldStatus::Passed));
+        assert_eq!(report.get("args.fee"), Some(&FieldStatus::Failed));
+        assert_eq!(report.get("limit"), Some(&FieldStatus::Passed));
+
+        let root = json!({ "limit": 100, "args": { "amount": 500, "fee": 1 } });
+        let report = SchemaValidator::new(&engine, &schema)
+            .unwrap()
+            .validate_root(&root);
+        assert_eq!(report.get("args.amount"), Some(&FieldStatus::Failed));
+        assert_eq!(report.get("args.fee"), Some(&FieldStatus::Passed));
+        assert_eq!(report.get("limit"), Some(&FieldStatus::Failed));
+    }
+
+    #[test]
+    fn test_field_order_and_escaped_keys() {
+        let engine = engine(json!({
+            "b": 1,
+            "a": { "token.near": 2, "token": { "near": 3 }, "x\\y": 4 },
+            "c": 5
+        }));
+        let schema = Schema::from_json(
+            r#"{
+                "fields": {
+                    "c": "value == 5",
+                    "a.token\\.near": "value == 2",
+                    "b": "value == 1",
+                    "a.token.near": "value == 3",
+                    "a.x\\\\y": "value == 4"
+                }
+            }"#,
+        )
+        .unwrap();
+        let report = SchemaValidator::new(&engine, &schema).unwrap().validate();
+
+        // keys are sorted, not kept in the order they were written
+        assert_eq!(
+            report
+                .fields()
+                .iter()
+                .map(|field| field.path.as_str())
+                .collect::<Vec<_>>(),
+            vec!["a.token.near", "a.token\\.near", "a.x\\\\y", "b", "c"]
+        );
+        assert!(report.is_valid(), "{:?}", report);
+    }
+
+    #[test]
+    fn test_validate_nested_schemas() {
+        let engine = engine(transfer());
+        let schema = Schema::from_json(
+            r#"{
+                "fields": {
+                    "args": { "fields": { "amount": "value.is_integer()" } },
+                    "actions": {
+                        "rule": "value.length() > 0",
+                        "items": {
+                            "fields": {
+                                "method_name": "value == \"ft_transfer\"",
+                                "deposit": "value == 1"
+                            }
+                        }
+                    },
+                    "receiver_id": { "fields": { "x": "value.is_defined()" } },
+                    "args.amount": { "items": "value > 0" }
+                }
+            }"#,
+        )
+        .unwrap();
+        let report = SchemaValidator::new(&engine, &schema).unwrap().validate();
+
+        assert_eq!(
+            report
+                .fields()
+                .iter()
+                .map(|field| field.path.as_str())
+                .collect::<Vec<_>>(),
+            vec![
+                "actions",
+                "actions[0]",
+                "actions[0].deposit",
+                "actions[0].method_name",
+                "actions[1]",
+                "actions[1].deposit",
+                "actions[1].method_name",
+                "args",
+                "args.amount",
+                "args.amount",
+                "receiver_id",
+            ]
+        );
+        assert_eq!(report.get("actions"), Some(&FieldStatus::Passed));
+        assert_eq!(
+            report.get("actions[0].method_name"),
+            Some(&FieldStatus::Passed)
+        );
+        assert_eq!(
+            report.get("actions[1].method_name"),
+            Some(&FieldStatus::Failed)
+        );
+        assert_eq!(report.get("actions[1].deposit"), Some(&FieldStatus::Failed));
+
+        let shape_error = |path: &str| match report.fields().iter().rev().find(|f| f.path == path) {
+            Some(FieldReport {
+                status: FieldStatus::Error(e),
+                ..
+            }) => e.message.clone(),
+            other => panic!("expected an error for {}, got {:?}", path, other),
+        };
+        assert_eq!(shape_error("receiver_id"), "Expected an object, got string");
+        assert_eq!(shape_error("args.amount"), "Expected an array, got number");
+    }
+
+    #[test]
+    fn test_validate_rule_errors() {
+        let engine = engine(transfer());
+        let schema = Schema::from_json(
+            r#"{
+                "fields": {
+                    "receiver_id": "value.length()",
+                    "args.amount": "value.assert_type(\"string\")",
+                    "args.memo": "value > 0"
+                }
+            }"#,
+        )
+        .unwrap();
+        let report = SchemaValidator::new(&engine, &schema).unwrap().validate();
+
+        let error = |path: &str| match report.get(path) {
+            Some(FieldStatus::Error(e)) => e.clone(),
+            other => panic!("expected an error for {}, got {:?}", path, other),
+        };
+        assert_eq!(
+            error("receiver_id").message,
+            "A rule must evaluate to a boolean, got number"
+        );
+        assert_eq!(error("args.amount").kind, DslErrorKind::AssertionFailed);
+        assert_eq!(error("args.memo").kind, DslErrorKind::TypeMismatch);
+
+        // unknown methods are rejected when the validator is built
+        let schema =
+            Schema::from_json(r#"{ "fields": { "args": { "fields": { "x": "nope()" } } } }"#)
+                .unwrap();
+        let error = SchemaValidator::new(&engine, &schema).err().unwrap();
+        assert_eq!(error.kind, DslErrorKind::UnknownMethod);
+        assert_eq!(error.path.as_deref(), Some("args.x"));
+    }
+}
@@ -1,38 +1,128 @@
+use crate::{
+    error::{DslError, Position, Span},
+    limits::DslLimits,
+    number,
+};
+
 #[derive(Debug, PartialEq, Eq, Clone)]
 pub enum DewSchemaLanguageToken {
     Number(String),
     Identifier(String),
     StringLiteral(String),
     Dot,
+    QuestionDot,
     Comma,
     LeftParenthesis,
     RightParenthesis,
+    LeftBracket,
+    RightBracket,
+    LeftBrace,
+    RightBrace,
+    Colon,
+    Assign,
+    Arrow,
+    Equal,
+    NotEqual,
+    LessThan,
+    LessThanOrEqual,
+    GreaterThan,
+    GreaterThanOrEqual,
+    And,
+    Or,
+    Not,
+    Plus,
+    Minus,
+    Asterisk,
+    Slash,
+    Percent,
+}
+
+/// A token together with the part of the source it was read from.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct SpannedToken {
+    pub token: DewSchemaLanguageToken,
+    pub span: Span,
+}
+
+/// Character iterator over the source that knows its byte offset, line and column.
+#[derive(Clone)]
+struct Cursor<'cursor_lifetime> {
+    input: &'cursor_lifetime str,
+    position: Position,
 }
 
-pub fn tokenize(input: &str) -> Result<Vec<DewSchemaLanguageToken>, String> {
+impl Cursor<'_> {
+    fn new(input: &str) -> Cursor<'_> {
+        Cursor {
+            input,
+            position: Position::new(0, 1, 1),
+        }
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.input[self.position.offset..].chars().next()
+    }
+
+    fn next_if_eq(&mut self, expected: char) -> Option<char> {
+        match self.peek() {
+            Some(c) if c == expected => self.next(),
+            _ => None,
+        }
+    }
+}
+
+impl Iterator for Cursor<'_> {
+    type Item = char;
+
+    fn next(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.position.offset += c.len_utf8();
+
+        if c == '\n' {
+            self.position.line += 1;
+            self.position.column = 1;
+        } else {
+            self.position.column += 1;
+        }
+
+        Some(c)
+    }
+}
+
+/// Splits `input` into tokens, each carrying the span it covers in `input`.
+pub fn tokenize(input: &str) -> Result<Vec<SpannedToken>, DslError> {
+    tokenize_with_max_number_digits(input, DslLimits::DEFAULT_MAX_NUMBER_DIGITS)
+}
+
+/// Like [`tokenize`], failing with [`DslErrorKind::NumberTooLarge`] for number literals
+/// that span more than `max_number_digits` digits.
+///
+/// [`DslErrorKind::NumberTooLarge`]: crate::error::DslErrorKind::NumberTooLarge
+pub fn tokenize_with_max_number_digits(
+    input: &str,
+    max_number_digits: u64,
+) -> Result<Vec<SpannedToken>, DslError> {
     let mut tokens = Vec::new();
-    let mut chars = input.chars().peekable();
+    let mut spans = Vec::new();
+    let mut chars = Cursor::new(input);
+
+    while let Some(ch) = chars.peek() {
+        let start = chars.position;
 
-    while let Some(&ch) = chars.peek() {
         match ch {
             // skip whitespace
             c if c.is_whitespace() => {
                 chars.next();
             }
 
-            // numbers (supports floats and scientific notation)
-            c if c.is_ascii_digit() || c == '-' => {
+            // numbers (supports floats and scientific notation); a leading `-` is the prefix
+            // operator, so `-5.add(1)` negates the whole chain like `-$.x.add(1)`
+            c if c.is_ascii_digit() => {
                 let mut number = String::new();
                 let mut seen_dot = false;
                 let mut seen_exp = false;
 
-                // allow leading negative sign
-                if c == '-' {
-                    number.push(c);
-                    chars.next(); // consume sign
-                }
-
-                while let Some(&c2) = chars.peek() {
+                while let Some(c2) = chars.peek() {
                     if c2.is_ascii_digit() {
                         number.push(c2);
                         chars.next();
@@ -58,7 +148,7 @@ pub fn tokenize(input: &str) -> Result<Vec<DewSchemaLanguageToken>, String> {
                         chars.next();
 
                         // optional sign after e/E
-                        if let Some(&sign) = chars.peek() {
+                        if let Some(sign) = chars.peek() {
                             if sign == '+' || sign == '-' {
                                 number.push(sign);
                                 chars.next();
@@ -69,13 +159,23 @@ pub fn tokenize(input: &str) -> Result<Vec<DewSchemaLanguageToken>, String> {
                     }
                 }
 
+                // malformed numbers are reported when they are evaluated
+                if let Ok(parsed) = number::parse_number(&number) {
+                    let digits = number::digit_count(&parsed);
+
+                    if digits > max_number_digits {
+                        return Err(DslError::number_too_large(digits, max_number_digits)
+                            .with_span(Span::new(start, chars.position)));
+                    }
+                }
+
                 tokens.push(DewSchemaLanguageToken::Number(number));
             }
 
             // identifiers
             c if c.is_alphabetic() || c == '_' || c == '$' => {
                 let mut identifier = String::new();
-                while let Some(&c2) = chars.peek() {
+                while let Some(c2) = chars.peek() {
                     if c2.is_alphanumeric() || c2 == '_' || c2 == '$' {
                         identifier.push(c2);
                         chars.next();
@@ -91,8 +191,9 @@ pub fn tokenize(input: &str) -> Result<Vec<DewSchemaLanguageToken>, String> {
                 chars.next(); // consume opening "
                 let mut literal = String::new();
                 let mut escaped = false;
+                let mut terminated = false;
 
-                while let Some(c2) = chars.next() {
+                for c2 in chars.by_ref() {
                     if escaped {
                         // handle escaped characters
                         literal.push(c2);
@@ -101,12 +202,20 @@ pub fn tokenize(input: &str) -> Result<Vec<DewSchemaLanguageToken>, String> {
                         escaped = true;
                     } else if c2 == '"' {
                         // end of string
+                        terminated = true;
                         break;
                     } else {
                         literal.push(c2);
                     }
                 }
 
+                if !terminated {
+                    return Err(DslError::lex(
+                        "Unterminated string literal",
+                        Span::new(start, chars.position),
+                    ));
+                }
+
                 tokens.push(DewSchemaLanguageToken::StringLiteral(literal));
             }
 
@@ -115,6 +224,16 @@ pub fn tokenize(input: &str) -> Result<Vec<DewSchemaLanguageToken>, String> {
                 chars.next();
                 tokens.push(DewSchemaLanguageToken::Dot);
             }
+            '?' => {
+                chars.next();
+                if chars.next_if_eq('.').is_none() {
+                    return Err(DslError::lex(
+                        "Unexpected character: ?, did you mean ?.?",
+                        Span::new(start, chars.position),
+                    ));
+                }
+                tokens.push(DewSchemaLanguageToken::QuestionDot);
+            }
             ',' => {
                 chars.next();
                 tokens.push(DewSchemaLanguageToken::Comma);
@@ -127,18 +246,157 @@ pub fn tokenize(input: &str) -> Result<Vec<DewSchemaLanguageToken>, String> {
                 chars.next();
                 tokens.push(DewSchemaLanguageToken::RightParenthesis);
             }
+            '[' => {
+                chars.next();
+                tokens.push(DewSchemaLanguageToken::LeftBracket);
+            }
+            ']' => {
+                chars.next();
+                tokens.push(DewSchemaLanguageToken::RightBracket);
+            }
+            '{' => {
+                chars.next();
+                tokens.push(DewSchemaLanguageToken::LeftBrace);
+            }
+            '}' => {
+                chars.next();
+                tokens.push(DewSchemaLanguageToken::RightBrace);
+            }
+            ':' => {
+                chars.next();
+                tokens.push(DewSchemaLanguageToken::Colon);
+            }
 
-            _ => return Err(format!("Unexpected character: {}", ch)),
+            // operators
+            '=' => {
+                chars.next();
+                if chars.next_if_eq('=').is_some() {
+                    tokens.push(DewSchemaLanguageToken::Equal);
+                } else if chars.next_if_eq('>').is_some() {
+                    tokens.push(DewSchemaLanguageToken::Arrow);
+                } else if is_let_binding(&tokens) {
+                    tokens.push(DewSchemaLanguageToken::Assign);
+                } else {
+                    return Err(DslError::lex(
+                        "Unexpected character: =, did you mean ==?",
+                        Span::new(start, chars.position),
+                    ));
+                }
+            }
+            '!' => {
+                chars.next();
+                if chars.next_if_eq('=').is_some() {
+                    tokens.push(DewSchemaLanguageToken::NotEqual);
+                } else {
+                    tokens.push(DewSchemaLanguageToken::Not);
+                }
+            }
+            '<' => {
+                chars.next();
+                if chars.next_if_eq('=').is_some() {
+                    tokens.push(DewSchemaLanguageToken::LessThanOrEqual);
+                } else {
+                    tokens.push(DewSchemaLanguageToken::LessThan);
+                }
+            }
+            '>' => {
+                chars.next();
+                if chars.next_if_eq('=').is_some() {
+                    tokens.push(DewSchemaLanguageToken::GreaterThanOrEqual);
+                } else {
+                    tokens.push(DewSchemaLanguageToken::GreaterThan);
+                }
+            }
+            '&' => {
+                chars.next();
+                if chars.next_if_eq('&').is_none() {
+                    return Err(DslError::lex(
+                        "Unexpected character: &, did you mean &&?",
+                        Span::new(start, chars.position),
+                    ));
+                }
+                tokens.push(DewSchemaLanguageToken::And);
+            }
+            '|' => {
+                chars.next();
+                if chars.next_if_eq('|').is_none() {
+                    return Err(DslError::lex(
+                        "Unexpected character: |, did you mean ||?",
+                        Span::new(start, chars.position),
+                    ));
+                }
+                tokens.push(DewSchemaLanguageToken::Or);
+            }
+            '+' => {
+                chars.next();
+                tokens.push(DewSchemaLanguageToken::Plus);
+            }
+            '-' => {
+                chars.next();
+                tokens.push(DewSchemaLanguageToken::Minus);
+            }
+            '*' => {
+                chars.next();
+                tokens.push(DewSchemaLanguageToken::Asterisk);
+            }
+            '/' => {
+                chars.next();
+                tokens.push(DewSchemaLanguageToken::Slash);
+            }
+            '%' => {
+                chars.next();
+                tokens.push(DewSchemaLanguageToken::Percent);
+            }
+
+            _ => {
+                chars.next();
+                return Err(DslError::lex(
+                    format!("Unexpected character: {}", ch),
+                    Span::new(start, chars.position),
+                ));
+            }
+        }
+
+        if tokens.len() > spans.len() {
+            spans.push(Span::new(start, chars.position));
         }
     }
 
-    Ok(tokens)
+    Ok(tokens
+        .into_iter()
+        .zip(spans)
+        .map(|(token, span)| SpannedToken { token, span })
+        .collect())
+}
+
+/// A lone `=` is only valid right after `let <name>`; anywhere else it is a typo for `==`.
+fn is_let_binding(tokens: &[DewSchemaLanguageToken]) -> bool {
+    matches!(
+        tokens,
+        [.., DewSchemaLanguageToken::Identifier(keyword), DewSchemaLanguageToken::Identifier(_)]
+            if keyword == "let"
+    )
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    fn tokens(input: &str) -> Vec<DewSchemaLanguageToken> {
+        tokenize(input)
+            .unwrap()
+            .into_iter()
+            .map(|spanned| spanned.token)
+            .collect()
+    }
+
+    fn span(start: (usize, usize, usize), end: (usize, usize, usize)) -> Span {
+        Span::new(
+            Position::new(start.0, start.1, start.2),
+            Position::new(end.0, end.1, end.2),
+        )
+    }
+
     #[test]
     fn test_tokenize_simple() {
         let input = r#"foo.bar(123, "hello")"#;
@@ -152,8 +410,7 @@ mod tests {
             DewSchemaLanguageToken::StringLiteral("hello".into()),
             DewSchemaLanguageToken::RightParenthesis,
         ];
-        let result = tokenize(input).unwrap();
-        assert_eq!(result, expected);
+        assert_eq!(tokens(input), expected);
     }
 
     #[test]
@@ -174,8 +431,7 @@ mod tests {
             DewSchemaLanguageToken::RightParenthesis,
             DewSchemaLanguageToken::RightParenthesis,
         ];
-        let result = tokenize(input).unwrap();
-        assert_eq!(result, expected);
+        assert_eq!(tokens(input), expected);
     }
 
     #[test]
@@ -189,38 +445,38 @@ mod tests {
             DewSchemaLanguageToken::Number("38".into()),
             DewSchemaLanguageToken::RightParenthesis,
         ];
-        let result = tokenize(input).unwrap();
-        assert_eq!(result, expected);
+        assert_eq!(tokens(input), expected);
     }
 
     #[test]
     fn test_tokenize_simple_negative_number() {
         let input = r#"-42.sub(-20)"#;
         let expected = vec![
-            DewSchemaLanguageToken::Number("-42".into()),
+            DewSchemaLanguageToken::Minus,
+            DewSchemaLanguageToken::Number("42".into()),
             DewSchemaLanguageToken::Dot,
             DewSchemaLanguageToken::Identifier("sub".into()),
             DewSchemaLanguageToken::LeftParenthesis,
-            DewSchemaLanguageToken::Number("-20".into()),
+            DewSchemaLanguageToken::Minus,
+            DewSchemaLanguageToken::Number("20".into()),
             DewSchemaLanguageToken::RightParenthesis,
         ];
-        let result = tokenize(input).unwrap();
-        assert_eq!(result, expected);
+        assert_eq!(tokens(input), expected);
     }
 
     #[test]
     fn test_tokenize_complex_numbers() {
         let input = r#"-3.2e3.mul(1e20)"#;
         let expected = vec![
-            DewSchemaLanguageToken::Number("-3.2e3".into()),
+            DewSchemaLanguageToken::Minus,
+            DewSchemaLanguageToken::Number("3.2e3".into()),
             DewSchemaLanguageToken::Dot,
             DewSchemaLanguageToken::Identifier("mul".into()),
             DewSchemaLanguageToken::LeftParenthesis,
             DewSchemaLanguageToken::Number("1e20".into()),
             DewSchemaLanguageToken::RightParenthesis,
         ];
-        let result = tokenize(input).unwrap();
-        assert_eq!(result, expected);
+        assert_eq!(tokens(input), expected);
     }
 
     #[test]
@@ -232,7 +488,183 @@ mod tests {
             DewSchemaLanguageToken::StringLiteral(r#"hello, "world"!"#.into()),
             DewSchemaLanguageToken::RightParenthesis,
         ];
-        let result = tokenize(input).unwrap();
+        assert_eq!(tokens(input), expected);
+    }
+
+    #[test]
+    fn test_tokenize_operators() {
+        let input = r#"$.a >= 5 && !($.b != "x") || 1 - -2 * 3 % 4 / 5 + 6 == 7 < 8 <= 9 > 0"#;
+        let expected = vec![
+            DewSchemaLanguageToken::Identifier("$".into()),
+            DewSchemaLanguageToken::Dot,
+            DewSchemaLanguageToken::Identifier("a".into()),
+            DewSchemaLanguageToken::GreaterThanOrEqual,
+            DewSchemaLanguageToken::Number("5".into()),
+            DewSchemaLanguageToken::And,
+            DewSchemaLanguageToken::Not,
+            DewSchemaLanguageToken::LeftParenthesis,
+            DewSchemaLanguageToken::Identifier("$".into()),
+            DewSchemaLanguageToken::Dot,
+            DewSchemaLanguageToken::Identifier("b".into()),
+            DewSchemaLanguageToken::NotEqual,
+            DewSchemaLanguageToken::StringLiteral("x".into()),
+            DewSchemaLanguageToken::RightParenthesis,
+            DewSchemaLanguageToken::Or,
+            DewSchemaLanguageToken::Number("1".into()),
+            DewSchemaLanguageToken::Minus,
+            DewSchemaLanguageToken::Minus,
+            DewSchemaLanguageToken::Number("2".into()),
+            DewSchemaLanguageToken::Asterisk,
+            DewSchemaLanguageToken::Number("3".into()),
+            DewSchemaLanguageToken::Percent,
+            DewSchemaLanguageToken::Number("4".into()),
+            DewSchemaLanguageToken::Slash,
+            DewSchemaLanguageToken::Number("5".into()),
+            DewSchemaLanguageToken::Plus,
+            DewSchemaLanguageToken::Number("6".into()),
+            DewSchemaLanguageToken::Equal,
+            DewSchemaLanguageToken::Number("7".into()),
+            DewSchemaLanguageToken::LessThan,
+            DewSchemaLanguageToken::Number("8".into()),
+            DewSchemaLanguageToken::LessThanOrEqual,
+            DewSchemaLanguageToken::Number("9".into()),
+            DewSchemaLanguageToken::GreaterThan,
+            DewSchemaLanguageToken::Number("0".into()),
+        ];
+        assert_eq!(tokens(input), expected);
+    }
+
+    #[test]
+    fn test_tokenize_binary_minus_without_spaces() {
+        let input = r#"$.a-1"#;
+        let expected = vec![
+            DewSchemaLanguageToken::Identifier("$".into()),
+            DewSchemaLanguageToken::Dot,
+            DewSchemaLanguageToken::Identifier("a".into()),
+            DewSchemaLanguageToken::Minus,
+            DewSchemaLanguageToken::Number("1".into()),
+        ];
+        assert_eq!(tokens(input), expected);
+    }
+
+    #[test]
+    fn test_tokenize_spans() {
+        let input = r#"$.név >= "ü""#;
+        let expected = vec![
+            (
+                DewSchemaLanguageToken::Identifier("$".into()),
+                span((0, 1, 1), (1, 1, 2)),
+            ),
+            (DewSchemaLanguageToken::Dot, span((1, 1, 2), (2, 1, 3))),
+            (
+                DewSchemaLanguageToken::Identifier("név".into()),
+                span((2, 1, 3), (6, 1, 6)),
+            ),
+            (
+                DewSchemaLanguageToken::GreaterThanOrEqual,
+                span((7, 1, 7), (9, 1, 9)),
+            ),
+            (
+                DewSchemaLanguageToken::StringLiteral("ü".into()),
+                span((10, 1, 10), (14, 1, 13)),
+            ),
+        ];
+        let result: Vec<_> = tokenize(input)
+            .unwrap()
+            .into_iter()
+            .map(|spanned| (spanned.token, spanned.span))
+            .collect();
         assert_eq!(result, expected);
     }
+
+    #[test]
+    fn test_tokenize_multiline_spans() {
+        let input = "$.a\n  .gte(\n1)";
+        let result = tokenize(input).unwrap();
+        assert_eq!(result[3].token, DewSchemaLanguageToken::Dot);
+        assert_eq!(result[3].span, span((6, 2, 3), (7, 2, 4)));
+        assert_eq!(result[6].token, DewSchemaLanguageToken::Number("1".into()));
+        assert_eq!(result[6].span, span((12, 3, 1), (13, 3, 2)));
+    }
+
+    #[test]
+    fn test_tokenize_let_binding() {
+        let expected = vec![
+            DewSchemaLanguageToken::Identifier("let".into()),
+            DewSchemaLanguageToken::Identifier("x".into()),
+            DewSchemaLanguageToken::Assign,
+            DewSchemaLanguageToken::Number("1".into()),
+            DewSchemaLanguageToken::Identifier("in".into()),
+            DewSchemaLanguageToken::Identifier("x".into()),
+            DewSchemaLanguageToken::Equal,
+            DewSchemaLanguageToken::Number("1".into()),
+        ];
+        assert_eq!(tokens("let x = 1 in x == 1"), expected);
+        assert!(tokenize("x = 1").is_err());
+    }
+
+    #[test]
+    fn test_tokenize_arrow() {
+        let expected = vec![
+            DewSchemaLanguageToken::Identifier("b".into()),
+            DewSchemaLanguageToken::Arrow,
+            DewSchemaLanguageToken::Identifier("b".into()),
+            DewSchemaLanguageToken::GreaterThanOrEqual,
+            DewSchemaLanguageToken::Number("1".into()),
+        ];
+        assert_eq!(tokens("b => b >= 1"), expected);
+    }
+
+    #[test]
+    fn test_tokenize_literals() {
+        let expected = vec![
+            DewSchemaLanguageToken::LeftBrace,
+            DewSchemaLanguageToken::Identifier("a".into()),
+            DewSchemaLanguageToken::Colon,
+            DewSchemaLanguageToken::LeftBracket,
+            DewSchemaLanguageToken::Number("1".into()),
+            DewSchemaLanguageToken::Comma,
+            DewSchemaLanguageToken::RightBracket,
+            DewSchemaLanguageToken::RightBrace,
+        ];
+        assert_eq!(tokens("{a: [1,]}"), expected);
+        assert_eq!(
+            tokens("[1]-1")[3..],
+            [
+                DewSchemaLanguageToken::Minus,
+                DewSchemaLanguageToken::Number("1".into())
+            ]
+        );
+    }
+
+    #[test]
+    fn test_tokenize_optional_chaining() {
+        let expected = vec![
+            DewSchemaLanguageToken::Identifier("$".into()),
+            DewSchemaLanguageToken::QuestionDot,
+            DewSchemaLanguageToken::Identifier("a".into()),
+            DewSchemaLanguageToken::QuestionDot,
+            DewSchemaLanguageToken::LeftBracket,
+            DewSchemaLanguageToken::Number("0".into()),
+            DewSchemaLanguageToken::RightBracket,
+        ];
+        assert_eq!(tokens("$?.a?.[0]"), expected);
+        assert!(tokenize("$.a ? 1").is_err());
+    }
+
+    #[test]
+    fn test_tokenize_errors() {
+        let error = tokenize_with_max_number_digits("1 + 1e40", 30).unwrap_err();
+        assert_eq!(error.kind, crate::error::DslErrorKind::NumberTooLarge);
+        assert_eq!(error.span.map(|span| span.start.offset), Some(4));
+        assert!(tokenize_with_max_number_digits("1 + 1e29", 30).is_ok());
+
+        let error = tokenize(r#"$.a # 1"#).unwrap_err();
+        assert_eq!(error.kind, crate::error::DslErrorKind::Lex);
+        assert_eq!(error.span, Some(span((4, 1, 5), (5, 1, 6))));
+
+        let error = tokenize(r#"$.a.equal("abc"#).unwrap_err();
+        assert_eq!(error.kind, crate::error::DslErrorKind::Lex);
+        assert_eq!(error.span, Some(span((10, 1, 11), (14, 1, 15))));
+    }
 }
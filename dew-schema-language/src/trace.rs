@@ -0,0 +1,215 @@
+//! Evaluation traces recorded by [`DewSchemaLanguageEngine::evaluate_traced`], which show
+//! the value of every sub-expression, e.g. to explain why a rule returned `false`.
+//!
+//! [`DewSchemaLanguageEngine::evaluate_traced`]: crate::engine::DewSchemaLanguageEngine::evaluate_traced
+
+use std::fmt::{self, Write};
+
+use serde_json::{json, Map, Value};
+
+use crate::{
+    engine::DewSchemaLanguageResult,
+    error::{DslError, Position, Span},
+};
+
+/// Longest value recorded in a trace; longer ones are cut.
+const MAX_RENDERED_VALUE_LENGTH: usize = 80;
+
+/// One evaluated node of the expression tree. Values are recorded as text, rendered by
+/// [`render_value`], so tracing a large root object does not copy it for every node.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DslTraceNode {
+    /// The node's source text, e.g. `$.args.amount` or `gte(5)`.
+    pub source: String,
+    pub span: Span,
+    /// The value the node was evaluated on: the previous step of a method chain.
+    pub callee: Option<String>,
+    /// For method calls, the outcome of every argument evaluation, in order, including
+    /// the one that failed. Iteration methods evaluate their argument once per element.
+    pub args: Vec<Result<String, DslError>>,
+    pub result: Result<String, DslError>,
+    /// Nodes evaluated to produce this one, in evaluation order.
+    pub children: Vec<DslTraceNode>,
+}
+
+/// Every node evaluated for one expression, rooted at the whole expression.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DslTrace {
+    pub root: DslTraceNode,
+    pub(crate) result: Result<DewSchemaLanguageResult, DslError>,
+}
+
+impl DslTrace {
+    /// The result of the whole expression, in full.
+    pub fn result(&self) -> &Result<DewSchemaLanguageResult, DslError> {
+        &self.result
+    }
+
+    /// Renders the trace with one node per line, children indented below their parent:
+    ///
+    /// ```
+    /// # use std::collections::HashMap;
+    /// # use dew_schema_language::engine::DewSchemaLanguageEngine;
+    /// # use serde_json::json;
+    /// let engine = DewSchemaLanguageEngine::from_value(json!({ "amount": 500 }), HashMap::new());
+    /// let trace = engine.evaluate_traced("$.amount <= 100".to_string()).unwrap();
+    ///
+    /// assert_eq!(
+    ///     trace.render_tree(),
+    ///     r#"$.amount <= 100 => false
+    ///   $ => {"amount":500}
+    ///   amount on {"amount":500} => 500
+    ///   <= 100 on 500 with 100 => false
+    ///     100 => 100
+    /// "#
+    /// );
+    /// ```
+    pub fn render_tree(&self) -> String {
+        let mut output = String::new();
+        render_node(&self.root, 0, &mut output);
+        output
+    }
+
+    /// The trace as nested JSON objects with `source`, `start`, `end`, `callee`, `args`,
+    /// `result` or `error`, and `children` keys. Values are given as their rendered text,
+    /// and each argument as a `{ result }` or `{ error }` object.
+    pub fn to_json(&self) -> Value {
+        node_to_json(&self.root)
+    }
+}
+
+impl fmt::Display for DslTrace {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.render_tree())
+    }
+}
+
+fn render_node(node: &DslTraceNode, depth: usize, output: &mut String) {
+    output.push_str(&"  ".repeat(depth));
+    output.push_str(&node.source);
+
+    if let Some(callee) = &node.callee {
+        output.push_str(" on ");
+        output.push_str(callee);
+    }
+
+    for (index, arg) in node.args.iter().enumerate() {
+        output.push_str(if index == 0 { " with " } else { ", " });
+        match arg {
+            Ok(arg) => output.push_str(arg),
+            Err(e) => {
+                output.push_str("error: ");
+                output.push_str(&e.message);
+            }
+        }
+    }
+
+    match &node.result {
+        Ok(result) => {
+            output.push_str(" => ");
+            output.push_str(result);
+        }
+        Err(e) => {
+            output.push_str(" => error: ");
+            output.push_str(&e.message);
+        }
+    }
+    output.push('\n');
+
+    for child in &node.children {
+        render_node(child, depth + 1, output);
+    }
+}
+
+/// Renders `value` as compact JSON, or `undefined`, stopping after
+/// [`MAX_RENDERED_VALUE_LENGTH`] characters so a large value is never rendered in full.
+pub(crate) fn render_value(value: &DewSchemaLanguageResult) -> String {
+    let mut rendered = BoundedString {
+        output: String::new(),
+        remaining: MAX_RENDERED_VALUE_LENGTH,
+    };
+
+    if write!(rendered, "{}", value).is_err() {
+        rendered.output.push('…');
+    }
+    rendered.output
+}
+
+/// Accepts characters until `remaining` runs out, then fails the write.
+struct BoundedString {
+    output: String,
+    remaining: usize,
+}
+
+impl Write for BoundedString {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for c in s.chars() {
+            if self.remaining == 0 {
+                return Err(fmt::Error);
+            }
+            self.output.push(c);
+            self.remaining -= 1;
+        }
+
+        Ok(())
+    }
+}
+
+fn node_to_json(node: &DslTraceNode) -> Value {
+    let mut object = Map::new();
+
+    object.insert("source".to_string(), Value::String(node.source.clone()));
+    object.insert("start".to_string(), position_to_json(node.span.start));
+    object.insert("end".to_string(), position_to_json(node.span.end));
+
+    if let Some(callee) = &node.callee {
+        object.insert("callee".to_string(), Value::String(callee.clone()));
+    }
+
+    if !node.args.is_empty() {
+        object.insert(
+            "args".to_string(),
+            Value::Array(
+                node.args
+                    .iter()
+                    .map(|arg| {
+                        let mut object = Map::new();
+                        insert_result(&mut object, arg);
+                        Value::Object(object)
+                    })
+                    .collect(),
+            ),
+        );
+    }
+
+    insert_result(&mut object, &node.result);
+
+    object.insert(
+        "children".to_string(),
+        Value::Array(node.children.iter().map(node_to_json).collect()),
+    );
+
+    Value::Object(object)
+}
+
+fn insert_result(object: &mut Map<String, Value>, result: &Result<String, DslError>) {
+    match result {
+        Ok(result) => {
+            object.insert("result".to_string(), Value::String(result.clone()));
+        }
+        Err(e) => {
+            object.insert(
+                "error".to_string(),
+                json!({ "kind": format!("{:?}", e.kind), "message": e.message }),
+            );
+        }
+    }
+}
+
+fn position_to_json(position: Position) -> Value {
+    json!({
+        "offset": position.offset,
+        "line": position.line,
+        "column": position.column,
+    })
+}
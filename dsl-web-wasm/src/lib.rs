@@ -4,7 +4,9 @@ use std::collections::HashMap;
 use wasm_bindgen::prelude::*;
 use wasm_bindgen::JsCast;
 
-use dew_schema_language::engine::{DewSchemaLanguageEngine, DewSchemaLanguageResult};
+use dew_schema_language::engine::{DewSchemaLanguageEngine, DewSchemaLanguageResult, DslFunction};
+use dew_schema_language::error::{DslError, Position};
+use dew_schema_language::number::{self, BigDecimal, ToPrimitive};
 // ^ adjust this path to your engine crate/module
 
 /// WASM wrapper around DewSchemaLanguageEngine
@@ -26,15 +28,7 @@ impl DewSchemaLanguageWasmWrapper {
             .map_err(|_| JsValue::from_str("hostFunctions must be an object"))?;
 
         let keys = Object::keys(&obj);
-        let mut callbacks: HashMap<
-            String,
-            Box<
-                dyn Fn(
-                    Vec<DewSchemaLanguageResult>,
-                    Option<&DewSchemaLanguageResult>,
-                ) -> Result<DewSchemaLanguageResult, String>,
-            >,
-        > = HashMap::new();
+        let mut callbacks: HashMap<String, DslFunction> = HashMap::new();
 
         for i in 0..keys.length() {
             let key = keys.get(i).as_string().unwrap();
@@ -47,17 +41,19 @@ impl DewSchemaLanguageWasmWrapper {
                 move |params: Vec<DewSchemaLanguageResult>,
                       callee: Option<&DewSchemaLanguageResult>| {
                     // Convert Rust params -> JsValue[]
-                    let js_args: Vec<JsValue> =
-                        params.into_iter().map(rust_result_to_jsvalue).collect();
                     let js_args_array = Array::new();
-                    for arg in js_args {
+                    for param in params {
+                        let arg = rust_result_to_jsvalue(param)
+                            .map_err(|e| format!("Cannot pass an argument to JS: {:?}", e))?;
                         js_args_array.push(&arg);
                     }
 
                     // Convert callee
-                    let js_callee = callee
-                        .map(|c| rust_result_to_jsvalue(c.clone()))
-                        .unwrap_or(JsValue::NULL);
+                    let js_callee = match callee {
+                        Some(c) => rust_result_to_jsvalue(c.clone())
+                            .map_err(|e| format!("Cannot pass the callee to JS: {:?}", e))?,
+                        None => JsValue::NULL,
+                    };
 
                     // Build the final args for JS callback: (args, callee)
                     let final_args = Array::new();
@@ -75,33 +71,197 @@ impl DewSchemaLanguageWasmWrapper {
             callbacks.insert(key, Box::new(closure) as _);
         }
 
-        Ok(Self {
-            engine: DewSchemaLanguageEngine::new(root_json, callbacks),
-        })
+        let engine = DewSchemaLanguageEngine::try_new(&root_json, callbacks)
+            .map_err(dsl_error_to_jsvalue)?;
+
+        Ok(Self { engine })
+    }
+
+    /// Rebinds `$` to a new root object, keeping the registered host functions.
+    #[wasm_bindgen(js_name = setRoot)]
+    pub fn set_root(&mut self, root_json: String) -> Result<(), JsValue> {
+        self.engine
+            .set_root_json(&root_json)
+            .map_err(dsl_error_to_jsvalue)?;
+
+        Ok(())
     }
 
     pub fn evaluate(&self, expression: String) -> Result<JsValue, JsValue> {
         let result = self
             .engine
             .evaluate(expression)
-            .map_err(|e| JsValue::from_str(&e))?;
-        Ok(rust_result_to_jsvalue(result))
+            .map_err(dsl_error_to_jsvalue)?;
+        rust_result_to_jsvalue(result)
+    }
+
+    /// Evaluates without stopping at failed assertions. Returns
+    /// `{ result, error, violations }`: `error` is the error that stopped evaluation or
+    /// `null`, and `violations` lists every failed assertion as an error object.
+    #[wasm_bindgen(js_name = evaluateCollecting)]
+    pub fn evaluate_collecting(&self, expression: String) -> JsValue {
+        let evaluation = self.engine.evaluate_collecting(expression);
+        let output = Object::new();
+        let violations: Array = evaluation
+            .violations
+            .into_iter()
+            .map(dsl_error_to_jsvalue)
+            .collect();
+        let (result, error) = match evaluation.result.map(rust_result_to_jsvalue) {
+            Ok(Ok(result)) => (result, JsValue::NULL),
+            Ok(Err(e)) => (JsValue::UNDEFINED, e),
+            Err(e) => (JsValue::UNDEFINED, dsl_error_to_jsvalue(e)),
+        };
+
+        // setting a property on a plain object cannot fail
+        let _ = Reflect::set(&output, &JsValue::from_str("result"), &result);
+        let _ = Reflect::set(&output, &JsValue::from_str("error"), &error);
+        let _ = Reflect::set(&output, &JsValue::from_str("violations"), &violations);
+
+        output.into()
+    }
+
+    /// Evaluates while metering gas. Returns `{ result, error, gasUsed }`: `error` is the
+    /// error that stopped evaluation or `null`.
+    #[wasm_bindgen(js_name = evaluateMetered)]
+    pub fn evaluate_metered(&self, expression: String) -> JsValue {
+        let evaluation = self.engine.evaluate_metered(expression);
+        let output = Object::new();
+        let (result, error) = match evaluation.result.map(rust_result_to_jsvalue) {
+            Ok(Ok(result)) => (result, JsValue::NULL),
+            Ok(Err(e)) => (JsValue::UNDEFINED, e),
+            Err(e) => (JsValue::UNDEFINED, dsl_error_to_jsvalue(e)),
+        };
+
+        // setting a property on a plain object cannot fail
+        let _ = Reflect::set(&output, &JsValue::from_str("result"), &result);
+        let _ = Reflect::set(&output, &JsValue::from_str("error"), &error);
+        let _ = Reflect::set(
+            &output,
+            &JsValue::from_str("gasUsed"),
+            &JsValue::from_f64(evaluation.gas_used as f64),
+        );
+
+        output.into()
+    }
+
+    /// Evaluates while recording every sub-expression. Returns `{ tree, trace }`: the trace
+    /// rendered as indented text, and as nested `{ source, start, end, callee, args, result,
+    /// error, children }` objects.
+    #[wasm_bindgen(js_name = evaluateTraced)]
+    pub fn evaluate_traced(&self, expression: String) -> Result<JsValue, JsValue> {
+        let trace = self
+            .engine
+            .evaluate_traced(expression)
+            .map_err(dsl_error_to_jsvalue)?;
+        let output = Object::new();
+        let json = js_sys::JSON::parse(&trace.to_json().to_string())?;
+
+        Reflect::set(
+            &output,
+            &JsValue::from_str("tree"),
+            &JsValue::from_str(&trace.render_tree()),
+        )?;
+        Reflect::set(&output, &JsValue::from_str("trace"), &json)?;
+
+        Ok(output.into())
     }
 }
 
-/// --- Conversion helpers ---
-fn rust_result_to_jsvalue(r: DewSchemaLanguageResult) -> JsValue {
-    match r {
-        DewSchemaLanguageResult::Number(n) => JsValue::from_f64(n),
+// --- Conversion helpers ---
+
+/// Errors are thrown as `{ kind, message, path, start, end }` objects so the editor can
+/// branch on the kind and underline the expression from `start` to `end`, each given as
+/// `{ offset, line, column }`.
+fn dsl_error_to_jsvalue(e: DslError) -> JsValue {
+    let error = Object::new();
+    let set = |key: &str, value: JsValue| {
+        // setting a property on a plain object cannot fail
+        let _ = Reflect::set(&error, &JsValue::from_str(key), &value);
+    };
+
+    set("kind", JsValue::from_str(&format!("{:?}", e.kind)));
+    set("message", JsValue::from_str(&e.message));
+    set(
+        "path",
+        e.path
+            .as_deref()
+            .map(JsValue::from_str)
+            .unwrap_or(JsValue::NULL),
+    );
+    let position = |key: &str, position: Option<Position>| {
+        set(
+            key,
+            position
+                .map(|position| {
+                    let object = Object::new();
+                    for (field, value) in [
+                        ("offset", position.offset),
+                        ("line", position.line),
+                        ("column", position.column),
+                    ] {
+                        let _ = Reflect::set(
+                            &object,
+                            &JsValue::from_str(field),
+                            &JsValue::from_f64(value as f64),
+                        );
+                    }
+                    object.into()
+                })
+                .unwrap_or(JsValue::NULL),
+        )
+    };
+    position("start", e.span.map(|span| span.start));
+    position("end", e.span.map(|span| span.end));
+
+    error.into()
+}
+fn rust_result_to_jsvalue(r: DewSchemaLanguageResult) -> Result<JsValue, JsValue> {
+    Ok(match r {
+        DewSchemaLanguageResult::Number(n) => number_to_jsvalue(&n),
         DewSchemaLanguageResult::String(s) => JsValue::from_str(&s),
         DewSchemaLanguageResult::Boolean(b) => JsValue::from_bool(b),
-        DewSchemaLanguageResult::Value(v) => {
-            let s = serde_json::to_string(&v).unwrap();
-            js_sys::JSON::parse(&s).unwrap()
-        }
+        DewSchemaLanguageResult::Value(v) => json_to_jsvalue(&v)?,
         DewSchemaLanguageResult::Error(e) => JsValue::from_str(&format!("Error: {}", e)),
         DewSchemaLanguageResult::Null => JsValue::NULL,
         DewSchemaLanguageResult::Undefined => JsValue::UNDEFINED,
+    })
+}
+
+/// Converts arrays and objects element by element, so nested numbers keep their precision
+/// the same way top-level ones do.
+fn json_to_jsvalue(value: &Value) -> Result<JsValue, JsValue> {
+    Ok(match value {
+        Value::Null => JsValue::NULL,
+        Value::Bool(b) => JsValue::from_bool(*b),
+        Value::Number(n) => {
+            let n = number::parse_number(&n.to_string()).map_err(dsl_error_to_jsvalue)?;
+            number_to_jsvalue(&n)
+        }
+        Value::String(s) => JsValue::from_str(s),
+        Value::Array(elements) => {
+            let array = Array::new();
+            for element in elements {
+                array.push(&json_to_jsvalue(element)?);
+            }
+            array.into()
+        }
+        Value::Object(entries) => {
+            let object = Object::new();
+            for (key, entry) in entries {
+                Reflect::set(&object, &JsValue::from_str(key), &json_to_jsvalue(entry)?)?;
+            }
+            object.into()
+        }
+    })
+}
+
+/// Numbers that a JS `number` can hold exactly are returned as numbers, larger or more
+/// precise ones (e.g. yoctoNEAR amounts) as decimal strings so no precision is lost.
+fn number_to_jsvalue(n: &BigDecimal) -> JsValue {
+    match n.to_f64() {
+        Some(f) if number::from_f64(f).as_ref() == Ok(n) => JsValue::from_f64(f),
+        _ => JsValue::from_str(&n.to_plain_string()),
     }
 }
 
@@ -113,11 +273,15 @@ fn jsvalue_to_rust_result(v: JsValue) -> DewSchemaLanguageResult {
     } else if let Some(b) = v.as_bool() {
         DewSchemaLanguageResult::Boolean(b)
     } else if let Some(n) = v.as_f64() {
-        DewSchemaLanguageResult::Number(n)
+        match number::from_f64(n) {
+            Ok(n) => DewSchemaLanguageResult::Number(n),
+            Err(e) => DewSchemaLanguageResult::Error(e.message),
+        }
     } else if let Some(s) = v.as_string() {
         DewSchemaLanguageResult::String(s)
     } else {
         let serde_val: Value = serde_wasm_bindgen::from_value(v).unwrap_or(Value::Null);
-        DewSchemaLanguageResult::Value(serde_val)
+        DewSchemaLanguageResult::from_json(&serde_val)
+            .unwrap_or_else(|e| DewSchemaLanguageResult::Error(e.message))
     }
 }